@@ -0,0 +1,267 @@
+//! Checks AI comment lines against the diff before they are posted. GitHub rejects review
+//! comments on lines outside the PR's hunks with a 422, so each comment is matched to the
+//! hunk lines on its side, moved to a nearby line when it narrowly misses, and otherwise
+//! set aside for one general PR comment.
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{DiffLine, FileDiff, Hunk, LineKind, Side};
+use crate::review_result::ReviewComment;
+
+/// How far a comment may move to a line whose content it quotes
+const SNAP_BY_CONTENT: u64 = 20;
+/// How far a comment may move to the nearest diff line when nothing matches its content
+const SNAP_BY_DISTANCE: u64 = 3;
+/// Shorter code snippets match too many lines to say where a comment belongs
+const MIN_SNIPPET_CHARS: usize = 4;
+
+/// A file of the frontend's `ParsedDiff`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffFileInput {
+    pub path: String,
+    #[serde(default)]
+    pub old_path: Option<String>,
+    #[serde(default)]
+    pub binary: bool,
+    pub hunks: Vec<DiffHunkInput>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunkInput {
+    pub header: String,
+    pub lines: Vec<DiffLineInput>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLineInput {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub content: String,
+    pub old_line_number: Option<u32>,
+    pub new_line_number: Option<u32>,
+}
+
+impl From<DiffFileInput> for FileDiff {
+    fn from(file: DiffFileInput) -> Self {
+        let mut diff = FileDiff {
+            path: file.path,
+            old_path: file.old_path,
+            binary: file.binary,
+            ..Default::default()
+        };
+        for hunk in file.hunks {
+            let lines = hunk
+                .lines
+                .into_iter()
+                .filter_map(|line| {
+                    let kind = match line.kind.as_str() {
+                        "context" => LineKind::Context,
+                        "addition" => LineKind::Added,
+                        "deletion" => LineKind::Removed,
+                        // Hunk headers are repeated as lines by the frontend parser
+                        _ => return None,
+                    };
+                    Some(DiffLine {
+                        kind,
+                        old_line: line.old_line_number,
+                        new_line: line.new_line_number,
+                        content: line.content,
+                    })
+                })
+                .collect::<Vec<_>>();
+            diff.additions += lines.iter().filter(|l| l.kind == LineKind::Added).count();
+            diff.deletions += lines.iter().filter(|l| l.kind == LineKind::Removed).count();
+            diff.hunks.push(Hunk {
+                header: hunk.header,
+                lines,
+            });
+        }
+        diff
+    }
+}
+
+/// An AI comment, optionally carrying the frontend's id so results can be matched back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorComment {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub comment: ReviewComment,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchoredComment {
+    /// With `path`, `line` and `side` set to where it can be posted
+    #[serde(flatten)]
+    pub comment: AnchorComment,
+    pub original_line: u64,
+    /// Whether the line or path was changed
+    pub moved: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnanchoredComment {
+    #[serde(flatten)]
+    pub comment: AnchorComment,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorResult {
+    /// Comments that can be posted inline
+    pub inline: Vec<AnchoredComment>,
+    /// Comments no diff line could be found for
+    pub unanchored: Vec<UnanchoredComment>,
+    /// Markdown for a general PR comment carrying the unanchored comments
+    pub general_body: Option<String>,
+}
+
+fn parse_side(side: &str) -> Side {
+    if side.eq_ignore_ascii_case("left") {
+        Side::Left
+    } else {
+        Side::Right
+    }
+}
+
+/// Code the comment quotes: its suggestion and any `inline code` in the body
+fn snippets(comment: &ReviewComment) -> Vec<String> {
+    let mut snippets: Vec<String> = comment
+        .suggestion
+        .iter()
+        .flat_map(|suggestion| suggestion.lines())
+        .map(|line| line.trim().to_string())
+        .collect();
+    snippets.extend(
+        comment
+            .body
+            .split('`')
+            .skip(1)
+            .step_by(2)
+            .map(|code| code.trim().to_string()),
+    );
+    snippets.retain(|snippet| snippet.chars().count() >= MIN_SNIPPET_CHARS);
+    snippets
+}
+
+fn quotes(snippets: &[String], content: &str) -> bool {
+    let content = content.trim();
+    content.chars().count() >= MIN_SNIPPET_CHARS
+        && snippets
+            .iter()
+            .any(|snippet| content.contains(snippet.as_str()) || snippet.contains(content))
+}
+
+/// The diff line closest to `target` within `max_distance` that passes `accept`
+fn closest(
+    lines: &[(u32, &str)],
+    target: u64,
+    max_distance: u64,
+    accept: impl Fn(&str) -> bool,
+) -> Option<u32> {
+    lines
+        .iter()
+        .filter(|(number, content)| {
+            (*number as u64).abs_diff(target) <= max_distance && accept(content)
+        })
+        .min_by_key(|(number, _)| (*number as u64).abs_diff(target))
+        .map(|(number, _)| *number)
+}
+
+/// Find the line a comment can be posted on, or the reason there is none
+fn anchor(files: &[FileDiff], comment: &mut ReviewComment) -> Result<bool, String> {
+    let file = files
+        .iter()
+        .find(|file| file.path == comment.path)
+        .or_else(|| {
+            files
+                .iter()
+                .find(|file| file.old_path.as_deref() == Some(comment.path.as_str()))
+        })
+        .ok_or_else(|| format!("{} is not part of the diff", comment.path))?;
+    if file.binary {
+        return Err(format!("{} is a binary file", file.path));
+    }
+
+    let side = parse_side(&comment.side);
+    let lines = file.commentable_lines(side);
+    let moved = file.path != comment.path;
+    comment.path = file.path.clone();
+    // GitHub only accepts the upper-case names
+    comment.side = match side {
+        Side::Left => "LEFT",
+        Side::Right => "RIGHT",
+    }
+    .to_string();
+
+    if lines
+        .iter()
+        .any(|(number, _)| *number as u64 == comment.line)
+    {
+        return Ok(moved);
+    }
+
+    let snippets = snippets(comment);
+    let snapped = closest(&lines, comment.line, SNAP_BY_CONTENT, |content| {
+        quotes(&snippets, content)
+    })
+    .or_else(|| closest(&lines, comment.line, SNAP_BY_DISTANCE, |_| true));
+    match snapped {
+        Some(line) => {
+            comment.line = line as u64;
+            Ok(true)
+        }
+        None => Err(format!(
+            "Line {} is not within the diff of {}",
+            comment.line, comment.path
+        )),
+    }
+}
+
+/// Split comments into ones that can be posted inline, moved onto the diff where needed,
+/// and ones for a general comment
+pub fn anchor_comments(files: &[FileDiff], comments: Vec<AnchorComment>) -> AnchorResult {
+    let mut result = AnchorResult::default();
+    for mut comment in comments {
+        let original_line = comment.comment.line;
+        match anchor(files, &mut comment.comment) {
+            Ok(moved) => result.inline.push(AnchoredComment {
+                comment,
+                original_line,
+                moved,
+            }),
+            Err(reason) => result
+                .unanchored
+                .push(UnanchoredComment { comment, reason }),
+        }
+    }
+    if !result.unanchored.is_empty() {
+        result.general_body = Some(general_body(&result.unanchored));
+    }
+    result
+}
+
+fn general_body(comments: &[UnanchoredComment]) -> String {
+    let mut body = String::from("Review comments on lines outside the diff:");
+    for unanchored in comments {
+        let comment = &unanchored.comment.comment;
+        body.push_str(&format!(
+            "\n\n### `{}:{}`\n**[{}]** {}",
+            comment.path,
+            comment.line,
+            comment.severity.to_uppercase(),
+            comment.body
+        ));
+        if let Some(suggestion) = &comment.suggestion {
+            body.push_str(&format!("\n\n**Suggestion:**\n```\n{}\n```", suggestion));
+        }
+    }
+    body
+}
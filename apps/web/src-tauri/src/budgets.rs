@@ -0,0 +1,476 @@
+//! Daily token and cost limits for AI runs, globally and per repository. A run that would
+//! go over a limit is either refused or held in the job queue until the budget allows it,
+//! and deferrals are reported as `AIEvent::Budget` on the run's own event channel. Admitted
+//! runs reserve their estimate until they end, so runs launched together can't all slip
+//! under the same limit before any of them has reported usage.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::jobs::JobQueue;
+use crate::providers::TokenUsage;
+use crate::streams::{emit_event, AIEvent};
+use crate::usage::{UsageLog, UsageRecord};
+
+const DAY_MS: u64 = 86_400_000;
+/// Earlier runs within this window price a new run's estimate
+const PRICING_WINDOW_MS: u64 = 30 * DAY_MS;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetLimit {
+    /// Input, output and cache-write tokens; cache reads are too cheap to count
+    pub max_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExceededAction {
+    /// Fail `start_ai_stream`
+    #[default]
+    Refuse,
+    /// Accept the run but hold it in the queue until the budget allows it
+    Queue,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetConfig {
+    /// Limit across all repositories, per UTC day
+    #[serde(default)]
+    pub daily: BudgetLimit,
+    /// Per-repository limits keyed by "owner/name", per UTC day
+    #[serde(default)]
+    pub repositories: HashMap<String, BudgetLimit>,
+    #[serde(default)]
+    pub when_exceeded: ExceededAction,
+}
+
+/// What the budget did with a run that didn't fit; refused runs fail to start instead
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetAction {
+    Deferred,
+    Resumed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetDecision {
+    pub action: BudgetAction,
+    pub reason: String,
+}
+
+/// Expected size and cost of a run, checked against the budget before it starts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Estimate {
+    pub tokens: u64,
+    /// `tokens` at the cost per token of recent runs with the same provider and model;
+    /// zero if none of them reported a cost
+    pub cost_usd: f64,
+}
+
+/// An estimate set aside for a run that hasn't finished
+struct Reservation {
+    repository: Option<String>,
+    estimate: Estimate,
+}
+
+#[derive(Default)]
+pub struct Budgets {
+    /// Config file in the app data dir; changes aren't persisted until it is set
+    path: OnceLock<PathBuf>,
+    config: Mutex<BudgetConfig>,
+    /// Runs held in the job queue until the budget allows them
+    deferred: Mutex<HashMap<String, Reservation>>,
+    /// Runs admitted but not yet ended, queued or running. Locked after `deferred`.
+    reserved: Mutex<HashMap<String, Reservation>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn counted_tokens(usage: &TokenUsage) -> u64 {
+    usage.input_tokens + usage.output_tokens + usage.cache_creation_input_tokens
+}
+
+/// Rough token count of a prompt, used to check it fits before it runs
+pub fn estimate_tokens(prompt: &str) -> u64 {
+    (prompt.len() / 4) as u64
+}
+
+/// Estimate a run from its prompt, pricing it from the usage history of its provider
+/// and model
+pub fn estimate(usage: &UsageLog, provider: &str, model: Option<&str>, prompt: &str) -> Estimate {
+    let tokens = estimate_tokens(prompt);
+    let mut priced = Spend::default();
+    let records = usage
+        .records(Some(now_ms().saturating_sub(PRICING_WINDOW_MS)), None)
+        .unwrap_or_default();
+    for record in records.iter().filter(|record| {
+        record.provider == provider
+            && record.model.as_deref() == model
+            && record.usage.cost_usd.is_some()
+    }) {
+        priced.add(&record.usage);
+    }
+    let cost_usd = if priced.tokens > 0 {
+        priced.cost_usd / priced.tokens as f64 * tokens as f64
+    } else {
+        0.0
+    };
+    Estimate { tokens, cost_usd }
+}
+
+#[derive(Default)]
+struct Spend {
+    tokens: u64,
+    cost_usd: f64,
+}
+
+impl Spend {
+    fn add(&mut self, usage: &TokenUsage) {
+        self.tokens += counted_tokens(usage);
+        self.cost_usd += usage.cost_usd.unwrap_or(0.0);
+    }
+
+    /// Count a reserved run as its estimate, or as what it has used if that is more
+    fn add_reserved(&mut self, estimate: &Estimate, reported: Option<&TokenUsage>) {
+        let (tokens, cost_usd) = reported.map_or((0, 0.0), |usage| {
+            (counted_tokens(usage), usage.cost_usd.unwrap_or(0.0))
+        });
+        self.tokens += tokens.max(estimate.tokens);
+        self.cost_usd += cost_usd.max(estimate.cost_usd);
+    }
+}
+
+/// Why a run can't start under `limit`, if it can't, and whether it would fit once the
+/// day's spend resets
+fn exceeds(
+    limit: &BudgetLimit,
+    spent: &Spend,
+    estimate: &Estimate,
+    scope: &str,
+) -> Option<(String, bool)> {
+    let estimated_tokens = estimate.tokens;
+    if let Some(max_tokens) = limit.max_tokens {
+        if estimated_tokens > max_tokens {
+            return Some((
+                format!(
+                    "The prompt alone (~{} tokens) is larger than the {} budget of {} tokens",
+                    estimated_tokens, scope, max_tokens
+                ),
+                false,
+            ));
+        }
+        if spent.tokens + estimated_tokens > max_tokens {
+            return Some((
+                format!(
+                    "The {} budget of {} tokens would be exceeded ({} used today, ~{} needed)",
+                    scope, max_tokens, spent.tokens, estimated_tokens
+                ),
+                true,
+            ));
+        }
+    }
+    if let Some(max_cost) = limit.max_cost_usd {
+        if estimate.cost_usd > max_cost {
+            return Some((
+                format!(
+                    "The run alone (~${:.2}) costs more than the {} budget of ${:.2}",
+                    estimate.cost_usd, scope, max_cost
+                ),
+                false,
+            ));
+        }
+        if spent.cost_usd >= max_cost {
+            return Some((
+                format!(
+                    "The {} budget of ${:.2} is used up (${:.2} spent today)",
+                    scope, max_cost, spent.cost_usd
+                ),
+                true,
+            ));
+        }
+        if spent.cost_usd + estimate.cost_usd > max_cost {
+            return Some((
+                format!(
+                    "The {} budget of ${:.2} would be exceeded (${:.2} spent today, ~${:.2} needed)",
+                    scope, max_cost, spent.cost_usd, estimate.cost_usd
+                ),
+                true,
+            ));
+        }
+    }
+    None
+}
+
+impl Budgets {
+    pub fn init(&self, path: PathBuf) {
+        if let Some(config) = read_config(&path) {
+            *self.config.lock().unwrap() = config;
+        }
+        let _ = self.path.set(path);
+    }
+
+    pub fn config(&self) -> BudgetConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: BudgetConfig) -> Result<(), String> {
+        let limits = std::iter::once(&config.daily).chain(config.repositories.values());
+        for limit in limits {
+            if let Some(cost) = limit.max_cost_usd {
+                if !cost.is_finite() || cost < 0.0 {
+                    return Err(format!("Invalid cost budget: {}", cost));
+                }
+            }
+        }
+        if let Some(path) = self.path.get() {
+            write_config(path, &config)?;
+        }
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+
+    /// The reason a run can't start now, and whether it would fit once the day's spend
+    /// resets. `reserved` is the locked reservation map, so a run can be reserved under
+    /// the same lock it was checked under.
+    fn check(
+        &self,
+        usage: &UsageLog,
+        reserved: &HashMap<String, Reservation>,
+        repository: Option<&str>,
+        estimate: &Estimate,
+    ) -> Result<Option<(String, bool)>, String> {
+        let config = self.config();
+        let repo_limit = repository.and_then(|repo| config.repositories.get(repo));
+        if config.daily.max_tokens.is_none()
+            && config.daily.max_cost_usd.is_none()
+            && repo_limit.is_none()
+        {
+            return Ok(None);
+        }
+
+        let now = now_ms();
+        let mut total = Spend::default();
+        let mut repo = Spend::default();
+        let mut running: HashMap<String, UsageRecord> = usage
+            .running()
+            .into_iter()
+            .map(|record| (record.process_id.clone(), record))
+            .collect();
+        for (process_id, reservation) in reserved {
+            let reported = running.remove(process_id).map(|record| record.usage);
+            total.add_reserved(&reservation.estimate, reported.as_ref());
+            if reservation.repository.as_deref() == repository {
+                repo.add_reserved(&reservation.estimate, reported.as_ref());
+            }
+        }
+        let records = usage
+            .records(Some(now - now % DAY_MS), None)?
+            .into_iter()
+            .chain(running.into_values());
+        for record in records {
+            total.add(&record.usage);
+            if record.repository.as_deref() == repository {
+                repo.add(&record.usage);
+            }
+        }
+
+        if let Some(exceeded) = exceeds(&config.daily, &total, estimate, "daily") {
+            return Ok(Some(exceeded));
+        }
+        if let (Some(limit), Some(repository)) = (repo_limit, repository) {
+            let scope = format!("daily {}", repository);
+            return Ok(exceeds(limit, &repo, estimate, &scope));
+        }
+        Ok(None)
+    }
+
+    /// Decide whether a new run may start, reserving its estimate if it may. Returns the
+    /// reason to hold it in the queue, or an error if it is refused. The caller reports a
+    /// deferral once the run's event channel is open, and releases the run when it ends.
+    pub fn admit(
+        &self,
+        usage: &UsageLog,
+        process_id: &str,
+        repository: Option<&str>,
+        estimate: Estimate,
+    ) -> Result<Option<String>, String> {
+        let reservation = Reservation {
+            repository: repository.map(str::to_string),
+            estimate,
+        };
+        let mut reserved = self.reserved.lock().unwrap();
+        let (reason, can_wait) = match self.check(usage, &reserved, repository, &estimate)? {
+            Some(exceeded) => exceeded,
+            None => {
+                reserved.insert(process_id.to_string(), reservation);
+                return Ok(None);
+            }
+        };
+        // `deferred` is locked before `reserved` everywhere else
+        drop(reserved);
+
+        if !can_wait || self.config().when_exceeded != ExceededAction::Queue {
+            log::info!("Refusing AI run {}: {}", process_id, reason);
+            return Err(reason);
+        }
+
+        log::info!("Deferring AI run {}: {}", process_id, reason);
+        self.deferred
+            .lock()
+            .unwrap()
+            .insert(process_id.to_string(), reservation);
+        Ok(Some(reason))
+    }
+
+    /// Drop a run's reservation once it has ended or was cancelled; from then on its usage
+    /// record counts instead
+    pub fn release(&self, process_id: &str) {
+        self.deferred.lock().unwrap().remove(process_id);
+        self.reserved.lock().unwrap().remove(process_id);
+    }
+
+    /// Release deferred runs the budget now allows, e.g. after midnight UTC or a config change
+    pub fn recheck(&self, app: &AppHandle, usage: &UsageLog, jobs: &JobQueue) {
+        let mut deferred = self.deferred.lock().unwrap();
+        // Runs cancelled while deferred are no longer queued
+        deferred.retain(|id, _| jobs.is_queued(id));
+
+        // Released runs are reserved straight away, so each check counts the ones before it
+        let mut reserved = self.reserved.lock().unwrap();
+        let ids: Vec<String> = deferred.keys().cloned().collect();
+        let mut released = Vec::new();
+        for id in ids {
+            let run = &deferred[&id];
+            match self.check(usage, &reserved, run.repository.as_deref(), &run.estimate) {
+                Ok(None) => {
+                    if let Some(run) = deferred.remove(&id) {
+                        reserved.insert(id.clone(), run);
+                    }
+                    released.push(id);
+                }
+                Ok(Some(_)) => {}
+                Err(e) => log::warn!("Failed to check AI budget: {}", e),
+            }
+        }
+        drop(reserved);
+        drop(deferred);
+
+        for id in released {
+            if !jobs.is_queued(&id) {
+                continue;
+            }
+            // Reported before the job state change it causes
+            emit_event(
+                app,
+                &id,
+                AIEvent::Budget {
+                    decision: BudgetDecision {
+                        action: BudgetAction::Resumed,
+                        reason: "The budget allows this run now".to_string(),
+                    },
+                },
+            );
+            jobs.unblock(app, &id);
+        }
+    }
+}
+
+fn read_config(path: &Path) -> Option<BudgetConfig> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            log::warn!("Ignoring invalid {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_config(path: &Path, config: &BudgetConfig) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    // Write then rename so a crash mid-write never leaves a truncated file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to save budgets: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budgets(max_tokens: u64) -> Budgets {
+        let budgets = Budgets::default();
+        budgets
+            .set_config(BudgetConfig {
+                daily: BudgetLimit {
+                    max_tokens: Some(max_tokens),
+                    max_cost_usd: None,
+                },
+                ..Default::default()
+            })
+            .unwrap();
+        budgets
+    }
+
+    fn estimate(tokens: u64) -> Estimate {
+        Estimate {
+            tokens,
+            cost_usd: 0.0,
+        }
+    }
+
+    #[test]
+    fn runs_launched_together_share_the_budget() {
+        let budgets = budgets(1000);
+        let usage = UsageLog::default();
+        for id in ["chunk-0", "chunk-1"] {
+            assert_eq!(budgets.admit(&usage, id, None, estimate(400)), Ok(None));
+        }
+        let refused = budgets.admit(&usage, "chunk-2", None, estimate(400));
+        assert!(refused.unwrap_err().contains("would be exceeded"));
+
+        budgets.release("chunk-0");
+        assert_eq!(
+            budgets.admit(&usage, "chunk-2", None, estimate(400)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn running_runs_count_what_they_used_beyond_their_estimate() {
+        let budgets = budgets(1000);
+        let usage = UsageLog::default();
+        assert_eq!(budgets.admit(&usage, "run", None, estimate(100)), Ok(None));
+        usage.begin("run", "claude", None, None);
+        usage.add(
+            "run",
+            &TokenUsage {
+                input_tokens: 700,
+                ..Default::default()
+            },
+        );
+
+        let refused = budgets.admit(&usage, "next", None, estimate(400));
+        assert!(refused.unwrap_err().contains("700 used today"));
+        assert_eq!(
+            budgets.admit(&usage, "small", None, estimate(300)),
+            Ok(None)
+        );
+    }
+}
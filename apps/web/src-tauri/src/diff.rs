@@ -0,0 +1,266 @@
+//! Parser for unified diffs as printed by `gh pr diff` / `git diff`, keeping enough
+//! structure to split a diff by file and hunk and to tell which lines of either side it covers.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+    /// "\ No newline at end of file"
+    NoNewline,
+}
+
+/// Side of the diff a review comment is on, as GitHub names them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    /// The old file: removed and context lines
+    Left,
+    /// The new file: added and context lines
+    Right,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// Line number in the old file, for context and removed lines
+    pub old_line: Option<u32>,
+    /// Line number in the new file, for context and added lines
+    pub new_line: Option<u32>,
+    /// The line without its +/-/space prefix
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// The full `@@ -a,b +c,d @@ ...` line
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileDiff {
+    /// Path in the new tree, or the old path for deleted files
+    pub path: String,
+    /// Set when the file was renamed or deleted
+    pub old_path: Option<String>,
+    /// Raw lines from `diff --git` up to the first hunk
+    pub header: Vec<String>,
+    pub hunks: Vec<Hunk>,
+    pub binary: bool,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+impl Hunk {
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(self.header.len() + self.lines.len() * 40);
+        out.push_str(&self.header);
+        out.push('\n');
+        for line in &self.lines {
+            match line.kind {
+                LineKind::Context => out.push(' '),
+                LineKind::Added => out.push('+'),
+                LineKind::Removed => out.push('-'),
+                LineKind::NoNewline => {}
+            }
+            out.push_str(&line.content);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl FileDiff {
+    /// The file's section of the diff, restricted to `hunks`
+    pub fn render_hunks(&self, hunks: &[Hunk]) -> String {
+        let mut out = String::new();
+        for line in &self.header {
+            out.push_str(line);
+            out.push('\n');
+        }
+        for hunk in hunks {
+            out.push_str(&hunk.render());
+        }
+        out
+    }
+
+    pub fn render(&self) -> String {
+        self.render_hunks(&self.hunks)
+    }
+
+    /// Line numbers and content of the lines on `side` that can carry a review comment
+    pub fn commentable_lines(&self, side: Side) -> Vec<(u32, &str)> {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter_map(|diff_line| {
+                let number = match side {
+                    Side::Left => diff_line.old_line,
+                    Side::Right => diff_line.new_line,
+                }?;
+                Some((number, diff_line.content.as_str()))
+            })
+            .collect()
+    }
+
+    /// Whether the file no longer exists in the new tree
+    pub fn deleted(&self) -> bool {
+        self.header
+            .iter()
+            .any(|line| line.starts_with("deleted file mode"))
+    }
+}
+
+/// Parse `a/path b/path` from a `diff --git` line. Only used when there is no `+++`/`---`
+/// path, e.g. for binary files and pure renames.
+fn paths_from_git_line(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix("diff --git ")?;
+    let rest = rest.strip_prefix("a/")?;
+    let split = rest.find(" b/")?;
+    Some((rest[..split].to_string(), rest[split + 3..].to_string()))
+}
+
+/// The old and new start lines of `@@ -a,b +c,d @@`
+fn parse_hunk_header(line: &str) -> Option<(u32, u32)> {
+    let ranges = line.strip_prefix("@@ ")?;
+    let end = ranges.find(" @@")?;
+    let mut parts = ranges[..end].split(' ');
+    let old_range = parts.next()?.strip_prefix('-')?;
+    let new_range = parts.next()?.strip_prefix('+')?;
+    Some((
+        old_range.split(',').next()?.parse().ok()?,
+        new_range.split(',').next()?.parse().ok()?,
+    ))
+}
+
+fn strip_path_prefix(path: &str) -> Option<String> {
+    let path = path.trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    // Strip a trailing tab-separated timestamp as written by some diff tools
+    let path = path.split('\t').next().unwrap_or(path);
+    Some(
+        path.strip_prefix("a/")
+            .or_else(|| path.strip_prefix("b/"))
+            .unwrap_or(path)
+            .to_string(),
+    )
+}
+
+pub fn parse_unified_diff(text: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut current: Option<FileDiff> = None;
+    let mut old_path: Option<String> = None;
+    let mut new_path: Option<String> = None;
+    // Next old/new line numbers inside the current hunk
+    let mut old_line = 0;
+    let mut new_line = 0;
+
+    let finish = |file: Option<FileDiff>,
+                  old_path: Option<String>,
+                  new_path: Option<String>,
+                  files: &mut Vec<FileDiff>| {
+        if let Some(mut file) = file {
+            let git_paths = file.header.first().and_then(|l| paths_from_git_line(l));
+            let old = old_path.or_else(|| git_paths.as_ref().map(|(old, _)| old.clone()));
+            let new = new_path.or_else(|| git_paths.map(|(_, new)| new));
+            let deleted = file
+                .header
+                .iter()
+                .any(|l| l.starts_with("deleted file mode"));
+            let new = if deleted { None } else { new };
+
+            file.path = new.clone().or_else(|| old.clone()).unwrap_or_default();
+            file.old_path = match (&old, &new) {
+                (Some(old), Some(new)) if old != new => Some(old.clone()),
+                (Some(old), None) => Some(old.clone()),
+                _ => None,
+            };
+            files.push(file);
+        }
+    };
+
+    for line in text.lines() {
+        if line.starts_with("diff --git ") {
+            finish(current.take(), old_path.take(), new_path.take(), &mut files);
+            current = Some(FileDiff {
+                header: vec![line.to_string()],
+                ..Default::default()
+            });
+            continue;
+        }
+        let file = match current.as_mut() {
+            Some(file) => file,
+            // Anything before the first file header
+            None => continue,
+        };
+
+        if line.starts_with("@@") {
+            if let Some((old_start, new_start)) = parse_hunk_header(line) {
+                old_line = old_start;
+                new_line = new_start;
+                file.hunks.push(Hunk {
+                    header: line.to_string(),
+                    lines: Vec::new(),
+                });
+                continue;
+            }
+        }
+
+        let hunk = match file.hunks.last_mut() {
+            Some(hunk) => hunk,
+            None => {
+                if let Some(path) = line.strip_prefix("--- ") {
+                    old_path = strip_path_prefix(path);
+                } else if let Some(path) = line.strip_prefix("+++ ") {
+                    new_path = strip_path_prefix(path);
+                } else if let Some(path) = line.strip_prefix("rename from ") {
+                    old_path = Some(path.to_string());
+                } else if let Some(path) = line.strip_prefix("rename to ") {
+                    new_path = Some(path.to_string());
+                } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                    file.binary = true;
+                }
+                file.header.push(line.to_string());
+                continue;
+            }
+        };
+
+        let (kind, content) = match line.chars().next() {
+            Some('+') => (LineKind::Added, &line[1..]),
+            Some('-') => (LineKind::Removed, &line[1..]),
+            Some(' ') => (LineKind::Context, &line[1..]),
+            Some('\\') => (LineKind::NoNewline, line),
+            // Some tools drop the space on empty context lines
+            None => (LineKind::Context, ""),
+            Some(_) => continue,
+        };
+        let (old, new) = match kind {
+            LineKind::Context => {
+                old_line += 1;
+                new_line += 1;
+                (Some(old_line - 1), Some(new_line - 1))
+            }
+            LineKind::Added => {
+                new_line += 1;
+                file.additions += 1;
+                (None, Some(new_line - 1))
+            }
+            LineKind::Removed => {
+                old_line += 1;
+                file.deletions += 1;
+                (Some(old_line - 1), None)
+            }
+            LineKind::NoNewline => (None, None),
+        };
+        hunk.lines.push(DiffLine {
+            kind,
+            old_line: old,
+            new_line: new,
+            content: content.to_string(),
+        });
+    }
+    finish(current, old_path, new_path, &mut files);
+    files
+}
@@ -0,0 +1,260 @@
+//! Merging of reviews from several providers or models run on the same PR. Comments on the
+//! same file, side and line are combined into one, and every finding records which members
+//! raised it, so findings several models agree on can be told apart from single-model ones.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::jobs::JobOutcome;
+use crate::review_result::{parse_review, ReviewComment, ReviewSuggestion};
+
+/// A finished ensemble member, as handed to `merge`
+pub struct MemberRun {
+    /// Unique within the ensemble
+    pub label: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub process_id: String,
+    /// `None` if the run was refused before it was queued
+    pub outcome: Option<JobOutcome>,
+    pub error: Option<String>,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleComment {
+    #[serde(flatten)]
+    pub comment: ReviewComment,
+    /// Labels of the members that raised it
+    pub sources: Vec<String>,
+    /// Raised by more than one member
+    pub consensus: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleSuggestion {
+    #[serde(flatten)]
+    pub suggestion: ReviewSuggestion,
+    pub sources: Vec<String>,
+    pub consensus: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleMemberInfo {
+    pub label: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub process_id: String,
+    pub outcome: Option<JobOutcome>,
+    pub error: Option<String>,
+    pub summary: Option<String>,
+    pub overall_score: Option<f64>,
+    pub comment_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleReview {
+    pub summary: String,
+    /// Mean of the members' scores
+    pub overall_score: Option<f64>,
+    pub comments: Vec<EnsembleComment>,
+    pub suggestions: Vec<EnsembleSuggestion>,
+    pub members: Vec<EnsembleMemberInfo>,
+    pub warnings: Vec<String>,
+}
+
+fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "critical" => 3,
+        "warning" => 2,
+        "suggestion" => 1,
+        _ => 0,
+    }
+}
+
+/// Parse each member's output and merge the findings. Fails only if no member produced a review.
+pub fn merge(runs: Vec<MemberRun>) -> Result<EnsembleReview, String> {
+    let mut warnings = Vec::new();
+    let mut members = Vec::new();
+    // Findings are grouped with the index of the member that raised them, so members that
+    // happen to share a label still count separately
+    let mut comments: BTreeMap<(String, String, u64), Vec<(usize, ReviewComment)>> =
+        BTreeMap::new();
+    let mut suggestions: BTreeMap<(String, u64, u64), Vec<(usize, ReviewSuggestion)>> =
+        BTreeMap::new();
+    let mut scores = Vec::new();
+    let labels: Vec<String> = runs.iter().map(|run| run.label.clone()).collect();
+
+    for (index, run) in runs.into_iter().enumerate() {
+        let mut info = EnsembleMemberInfo {
+            label: run.label.clone(),
+            provider: run.provider,
+            model: run.model,
+            process_id: run.process_id,
+            outcome: run.outcome,
+            error: run.error,
+            summary: None,
+            overall_score: None,
+            comment_count: 0,
+        };
+
+        let output = match (run.outcome, run.output) {
+            (Some(JobOutcome::Completed), Some(output)) => output,
+            _ => {
+                warnings.push(format!(
+                    "{} did not finish: {}",
+                    run.label,
+                    info.error.as_deref().unwrap_or("no output")
+                ));
+                members.push(info);
+                continue;
+            }
+        };
+
+        let parsed = parse_review(&output);
+        warnings.extend(
+            parsed
+                .warnings
+                .iter()
+                .map(|warning| format!("{}: {}", run.label, warning)),
+        );
+        info.summary = parsed.summary;
+        info.overall_score = parsed.overall_score;
+        info.comment_count = parsed.comments.len();
+        scores.extend(parsed.overall_score);
+
+        for comment in parsed.comments {
+            comments
+                .entry((comment.path.clone(), comment.side.clone(), comment.line))
+                .or_default()
+                .push((index, comment));
+        }
+        for suggestion in parsed.suggestions {
+            suggestions
+                .entry((
+                    suggestion.path.clone(),
+                    suggestion.start_line,
+                    suggestion.end_line,
+                ))
+                .or_default()
+                .push((index, suggestion));
+        }
+        members.push(info);
+    }
+
+    if !members
+        .iter()
+        .any(|member| member.summary.is_some() || member.comment_count > 0)
+    {
+        return Err(format!(
+            "No ensemble member produced a review: {}",
+            warnings.join("; ")
+        ));
+    }
+
+    let comments: Vec<EnsembleComment> = comments
+        .into_values()
+        .map(|group| merge_comments(group, &labels))
+        .collect();
+    let suggestions: Vec<EnsembleSuggestion> = suggestions
+        .into_values()
+        .map(|group| {
+            let sources = unique_sources(group.iter().map(|(index, _)| *index));
+            let (_, suggestion) = group.into_iter().next().expect("groups are never empty");
+            EnsembleSuggestion {
+                suggestion,
+                consensus: sources.len() > 1,
+                sources: source_labels(&sources, &labels),
+            }
+        })
+        .collect();
+
+    let overall_score = if scores.is_empty() {
+        None
+    } else {
+        Some(scores.iter().sum::<f64>() / scores.len() as f64)
+    };
+
+    Ok(EnsembleReview {
+        summary: summarize(&members, &comments),
+        overall_score,
+        comments,
+        suggestions,
+        members,
+        warnings,
+    })
+}
+
+/// Indices of the members in a group, each once
+fn unique_sources(indices: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut sources: Vec<usize> = Vec::new();
+    for index in indices {
+        if !sources.contains(&index) {
+            sources.push(index);
+        }
+    }
+    sources
+}
+
+fn source_labels(sources: &[usize], labels: &[String]) -> Vec<String> {
+    sources.iter().map(|&index| labels[index].clone()).collect()
+}
+
+/// Combine comments on one line: the most severe one leads, and every member's text is kept
+fn merge_comments(mut group: Vec<(usize, ReviewComment)>, labels: &[String]) -> EnsembleComment {
+    let sources = unique_sources(group.iter().map(|(index, _)| *index));
+    group.sort_by_key(|(_, comment)| std::cmp::Reverse(severity_rank(&comment.severity)));
+
+    let body = if group.len() == 1 {
+        group[0].1.body.clone()
+    } else {
+        group
+            .iter()
+            .map(|(index, comment)| format!("**{}:** {}", labels[*index], comment.body))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    let suggestion = group
+        .iter()
+        .find_map(|(_, comment)| comment.suggestion.clone());
+
+    let (_, lead) = group.into_iter().next().expect("groups are never empty");
+    EnsembleComment {
+        comment: ReviewComment {
+            body,
+            suggestion,
+            ..lead
+        },
+        consensus: sources.len() > 1,
+        sources: source_labels(&sources, labels),
+    }
+}
+
+fn summarize(members: &[EnsembleMemberInfo], comments: &[EnsembleComment]) -> String {
+    let labels: Vec<&str> = members.iter().map(|member| member.label.as_str()).collect();
+    let consensus = comments.iter().filter(|comment| comment.consensus).count();
+    let mut summary = format!(
+        "Ensemble review by {}. {} finding(s) raised by more than one model, {} by a single model.",
+        labels.join(", "),
+        consensus,
+        comments.len() - consensus
+    );
+
+    for member in members {
+        summary.push_str(&format!("\n\n### {}\n", member.label));
+        match (&member.summary, &member.outcome) {
+            (Some(text), _) => summary.push_str(text),
+            (None, Some(JobOutcome::Completed)) => summary.push_str("No summary."),
+            (None, _) => summary.push_str(&format!(
+                "Did not finish: {}",
+                member.error.as_deref().unwrap_or("no output")
+            )),
+        }
+    }
+    summary
+}
@@ -0,0 +1,949 @@
+//! A read-only stand-in for `gh` on AI agents' PATH. Agents are allowed to run `gh` so they
+//! can read the PR, which would also let a prompt-injected PR description make them merge,
+//! comment or delete. The shim is a script that re-runs this app with `--gh-shim`, and that
+//! process only relays its arguments to the running app over a Unix socket (localhost TCP on
+//! Windows), which sandboxes without network access leave open. The app checks
+//! them against the scope of the run the request's grant was issued to, runs the real `gh`
+//! itself, and relays its output with secrets redacted; everything else is refused and
+//! logged.
+//!
+//! The scope, the real `gh` and the log never leave the app, so all an agent can change is
+//! the grant it presents, and grants are unguessable and revoked when their run ends.
+//! Agents also get no GitHub credentials in their environment and a `gh` config dir of
+//! their own, so running the real binary directly doesn't act as the user. If the shim can't
+//! be installed, CLI agents are refused rather than run with the real `gh`.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(windows)]
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::get_enhanced_path;
+use crate::secrets;
+
+/// First argument that makes the app binary act as the shim
+pub const SHIM_FLAG: &str = "--gh-shim";
+
+const ENV_ENDPOINT: &str = "LYON_GH_SHIM_ENDPOINT";
+// Not called a token: Codex strips variables with TOKEN in their name from its shell
+const ENV_GRANT: &str = "LYON_GH_SHIM_GRANT";
+
+/// Variables `gh` and other tools take GitHub credentials from
+const CREDENTIAL_VARS: &[&str] = &[
+    "GH_TOKEN",
+    "GITHUB_TOKEN",
+    "GH_ENTERPRISE_TOKEN",
+    "GITHUB_ENTERPRISE_TOKEN",
+];
+
+/// Media type that makes GitHub return file contents as they are
+const RAW_ACCEPT: &str = "Accept: application/vnd.github.raw";
+
+/// Largest request the app reads from a shim
+const MAX_REQUEST_BYTES: u64 = 1024 * 1024;
+
+/// Sections of `repos/{owner}/{repo}/...` an agent may read besides the PR itself
+const READABLE_REPO_SECTIONS: &[&str] = &[
+    "contents",
+    "commits",
+    "compare",
+    "git",
+    "readme",
+    "languages",
+    "branches",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedCommand {
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    pub process_id: Option<String>,
+    pub repository: Option<String>,
+    pub pr_number: Option<u64>,
+    pub args: Vec<String>,
+    pub reason: String,
+}
+
+/// What the shim lets an agent read
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    /// `owner/name`
+    pub repository: Option<String>,
+    pub pr_number: Option<u64>,
+}
+
+/// A run's permission to use the shim
+struct Grant {
+    process_id: String,
+    scope: Scope,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ShimRequest {
+    grant: String,
+    args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ShimResponse {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+impl ShimResponse {
+    fn failed(code: i32, message: &str) -> Self {
+        ShimResponse {
+            code,
+            stdout: String::new(),
+            stderr: format!("gh: {}\n", message),
+        }
+    }
+}
+
+/// The app side of the shim, shared with the thread serving it
+#[derive(Default)]
+struct ShimState {
+    /// Directory holding the `gh` script, skipped when looking for the real one
+    dir: OnceLock<PathBuf>,
+    log: OnceLock<PathBuf>,
+    /// Keyed by the grant handed to the run's agent
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+#[derive(Default)]
+pub struct GhShim {
+    state: Arc<ShimState>,
+    /// Socket path (TCP address on Windows) the app serves shims on, once installed
+    endpoint: OnceLock<String>,
+    /// Why installing failed, if it did
+    failure: OnceLock<String>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+const PATH_SEPARATOR: char = ':';
+#[cfg(windows)]
+const PATH_SEPARATOR: char = ';';
+
+#[cfg(unix)]
+const SOCKET_NAME: &str = "gh.sock";
+
+#[cfg(unix)]
+const SCRIPT_NAME: &str = "gh";
+#[cfg(windows)]
+const SCRIPT_NAME: &str = "gh.cmd";
+
+/// The `gh` executable the shim forwards to, skipping the shim's own directory
+fn find_real_gh(path: &str, shim_dir: &Path) -> Option<PathBuf> {
+    let names: &[&str] = if cfg!(windows) { &["gh.exe"] } else { &["gh"] };
+    path.split(PATH_SEPARATOR)
+        .filter(|dir| !dir.is_empty() && Path::new(dir) != shim_dir)
+        .flat_map(|dir| names.iter().map(move |name| Path::new(dir).join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(unix)]
+fn write_script(dir: &Path, exe: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let exe = exe.to_string_lossy().replace('\'', r"'\''");
+    let script = format!("#!/bin/sh\nexec '{}' {} \"$@\"\n", exe, SHIM_FLAG);
+    let path = dir.join(SCRIPT_NAME);
+    std::fs::write(&path, script).map_err(|e| e.to_string())?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(windows)]
+fn write_script(dir: &Path, exe: &Path) -> Result<(), String> {
+    let script = format!("@\"{}\" {} %*\r\n", exe.display(), SHIM_FLAG);
+    std::fs::write(dir.join(SCRIPT_NAME), script).map_err(|e| e.to_string())
+}
+
+/// Serve shims on a socket in `dir`, returning its path
+#[cfg(unix)]
+fn listen(dir: &Path, state: Arc<ShimState>) -> Result<String, String> {
+    let path = dir.join(SOCKET_NAME);
+    // Left over from an earlier launch
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).map_err(|e| e.to_string())?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let state = state.clone();
+            std::thread::spawn(move || state.serve(stream));
+        }
+    });
+    Ok(path.display().to_string())
+}
+
+/// Serve shims on a localhost port, returning its address
+#[cfg(windows)]
+fn listen(_dir: &Path, state: Arc<ShimState>) -> Result<String, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let state = state.clone();
+            std::thread::spawn(move || state.serve(stream));
+        }
+    });
+    Ok(addr.to_string())
+}
+
+#[cfg(unix)]
+fn connect(endpoint: &str) -> std::io::Result<UnixStream> {
+    UnixStream::connect(endpoint)
+}
+
+#[cfg(windows)]
+fn connect(endpoint: &str) -> std::io::Result<TcpStream> {
+    TcpStream::connect(endpoint)
+}
+
+impl GhShim {
+    /// Write the `gh` script into `dir`, pointing at the running app, and start serving it.
+    /// A failure is kept, so agent runs can be refused with the reason.
+    pub fn init(&self, dir: PathBuf, log: PathBuf) -> Result<(), String> {
+        let result = self.install(dir, log);
+        if let Err(e) = &result {
+            let _ = self.failure.set(e.clone());
+        }
+        result
+    }
+
+    fn install(&self, dir: PathBuf, log: PathBuf) -> Result<(), String> {
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(dir.join("config")).map_err(|e| e.to_string())?;
+        write_script(&dir, &exe)?;
+        let endpoint = listen(&dir, self.state.clone())?;
+
+        let _ = self.state.log.set(log);
+        let _ = self.state.dir.set(dir);
+        let _ = self.endpoint.set(endpoint);
+        Ok(())
+    }
+
+    /// Path of the `gh` script, or why agents can't be given one
+    pub fn command(&self) -> Result<PathBuf, String> {
+        match (self.state.dir.get(), self.endpoint.get()) {
+            (Some(dir), Some(_)) => Ok(dir.join(SCRIPT_NAME)),
+            _ => Err(match self.failure.get() {
+                Some(e) => format!(
+                    "AI agents are disabled because the read-only gh proxy failed to install: {}",
+                    e
+                ),
+                None => {
+                    "AI agents are disabled until the read-only gh proxy is installed".to_string()
+                }
+            }),
+        }
+    }
+
+    /// Set up an agent process to use the shim: a PATH that finds it first, a grant for
+    /// `scope`, and no GitHub credentials of its own. Runs are refused before this if the
+    /// shim isn't installed, so it then leaves the command alone.
+    pub fn configure(&self, cmd: &mut tokio::process::Command, process_id: &str, scope: &Scope) {
+        let (dir, endpoint) = match (self.state.dir.get(), self.endpoint.get()) {
+            (Some(dir), Some(endpoint)) => (dir, endpoint),
+            _ => return,
+        };
+        let grant = Uuid::new_v4().simple().to_string();
+        self.state.grants.lock().unwrap().insert(
+            grant.clone(),
+            Grant {
+                process_id: process_id.to_string(),
+                scope: scope.clone(),
+            },
+        );
+
+        for var in CREDENTIAL_VARS {
+            cmd.env_remove(var);
+        }
+        cmd.env(
+            "PATH",
+            format!("{}{}{}", dir.display(), PATH_SEPARATOR, get_enhanced_path()),
+        )
+        .env("GH_CONFIG_DIR", dir.join("config"))
+        .env(ENV_ENDPOINT, endpoint)
+        .env(ENV_GRANT, grant);
+    }
+
+    /// Withdraw a run's grants once it has ended
+    pub fn revoke(&self, process_id: &str) {
+        self.state
+            .grants
+            .lock()
+            .unwrap()
+            .retain(|_, grant| grant.process_id != process_id);
+    }
+
+    /// Commands the shim refused, oldest first, optionally for one run
+    pub fn blocked(&self, process_id: Option<&str>) -> Result<Vec<BlockedCommand>, String> {
+        let path = match self.state.log.get() {
+            Some(path) => path,
+            None => return Ok(Vec::new()),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<BlockedCommand>(&line).ok())
+            .filter(|blocked| {
+                process_id.map_or(true, |id| blocked.process_id.as_deref() == Some(id))
+            })
+            .collect())
+    }
+}
+
+impl ShimState {
+    /// Answer one shim connection
+    fn serve(&self, mut stream: impl Read + Write) {
+        let mut line = String::new();
+        let request = BufReader::new((&mut stream).take(MAX_REQUEST_BYTES))
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::from_str::<ShimRequest>(&line).map_err(|e| e.to_string()));
+        let response = match request {
+            Ok(request) => self.respond(&request),
+            Err(e) => ShimResponse::failed(1, &format!("invalid request to the gh proxy: {}", e)),
+        };
+        if let Ok(json) = serde_json::to_vec(&response) {
+            let _ = stream.write_all(&json);
+        }
+    }
+
+    fn respond(&self, request: &ShimRequest) -> ShimResponse {
+        let (process_id, scope) = match self.grants.lock().unwrap().get(&request.grant) {
+            Some(grant) => (grant.process_id.clone(), grant.scope.clone()),
+            None => return ShimResponse::failed(1, "this run may not use gh (any more)"),
+        };
+        let forwarded = match check(&request.args, &scope) {
+            Ok(forwarded) => forwarded,
+            Err(reason) => {
+                self.log_blocked(&process_id, &request.args, &scope, &reason);
+                return ShimResponse::failed(
+                    1,
+                    &format!("blocked by Lyon's read-only gh proxy: {}", reason),
+                );
+            }
+        };
+
+        let real = self
+            .dir
+            .get()
+            .and_then(|dir| find_real_gh(&get_enhanced_path(), dir));
+        let real = match real {
+            Some(real) => real,
+            None => {
+                return ShimResponse::failed(
+                    127,
+                    "the GitHub CLI is not installed or not available on PATH",
+                )
+            }
+        };
+        let output = Command::new(real)
+            .args(&forwarded)
+            .env("PATH", get_enhanced_path())
+            .stdin(Stdio::null())
+            .output();
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                return ShimResponse::failed(1, &format!("failed to run the GitHub CLI: {}", e))
+            }
+        };
+
+        // Diffs and files reach the agent without passing the prompt's secret redaction
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (stdout, redacted) = match contents_path(&forwarded) {
+            Some(path) => secrets::redact_file(&path, &stdout),
+            None => secrets::redact(&stdout),
+        };
+        if redacted > 0 {
+            log::warn!(
+                "Redacted {} secret(s) from gh output for {}",
+                redacted,
+                process_id
+            );
+        }
+        ShimResponse {
+            code: output.status.code().unwrap_or(1),
+            stdout,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+
+    fn log_blocked(&self, process_id: &str, args: &[String], scope: &Scope, reason: &str) {
+        let path = match self.log.get() {
+            Some(path) => path,
+            None => return,
+        };
+        let record = BlockedCommand {
+            time: now_ms(),
+            process_id: Some(process_id.to_string()),
+            repository: scope.repository.clone(),
+            pr_number: scope.pr_number,
+            args: args.to_vec(),
+            reason: reason.to_string(),
+        };
+        if let Ok(mut json) = serde_json::to_string(&record) {
+            json.push('\n');
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(json.as_bytes()));
+        }
+    }
+}
+
+fn same_repository(a: &str, b: &str) -> bool {
+    a.trim_end_matches(".git").eq_ignore_ascii_case(b)
+}
+
+/// The PR a `gh pr` selector names, given as a number or URL
+fn selector_number(selector: &str, repository: &str) -> Option<u64> {
+    if let Ok(number) = selector.trim_start_matches('#').parse() {
+        return Some(number);
+    }
+    let url = selector
+        .trim_start_matches("https://")
+        .trim_start_matches("github.com/");
+    let (repo, number) = url.split_once("/pull/")?;
+    if !same_repository(repo, repository) {
+        return None;
+    }
+    number.split(['/', '?', '#']).next()?.parse().ok()
+}
+
+/// Check `gh pr view|diff|checks` targets the PR under review
+fn check_pr(args: &[String], scope: &Scope, repository: &str) -> Result<Vec<String>, String> {
+    let subcommand = args.get(1).map(String::as_str).unwrap_or_default();
+    if !matches!(subcommand, "view" | "diff" | "checks") {
+        return Err(format!("gh pr {} is not read-only", subcommand));
+    }
+    if args.iter().any(|arg| arg == "--web" || arg == "-w") {
+        return Err("Opening a browser is not allowed".to_string());
+    }
+    let pr_number = scope
+        .pr_number
+        .ok_or("No PR is under review, so gh pr is not available")?;
+    let selector = args[2..]
+        .iter()
+        .find(|arg| !arg.starts_with('-'))
+        .ok_or("Name the PR under review explicitly")?;
+    if selector_number(selector, repository) != Some(pr_number) {
+        return Err(format!(
+            "Only PR #{} of {} may be read",
+            pr_number, repository
+        ));
+    }
+
+    // gh would otherwise pick the repository from the working directory
+    let mut args = args.to_vec();
+    args.extend(["--repo".to_string(), repository.to_string()]);
+    Ok(args)
+}
+
+/// Hosts `gh api --hostname` may send to: github.com, or the GitHub Enterprise host set in
+/// the app's `GH_HOST`. Any other host would let an agent send data anywhere in the URL.
+fn allowed_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("github.com")
+        || std::env::var("GH_HOST").is_ok_and(|configured| configured.eq_ignore_ascii_case(host))
+}
+
+/// Check `gh api` is a GET of the repository or of the PR under review
+fn check_api(args: &[String], scope: &Scope, repository: &str) -> Result<Vec<String>, String> {
+    let mut method: Option<String> = None;
+    let mut sends_fields = false;
+    let mut sets_accept = false;
+    let mut formats_output = false;
+    let mut endpoint: Option<usize> = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg, None),
+        };
+        let mut value = || -> Result<String, String> {
+            if let Some(value) = &inline_value {
+                return Ok(value.clone());
+            }
+            i += 1;
+            args.get(i)
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match flag {
+            "-X" | "--method" => method = Some(value()?.to_uppercase()),
+            "-f" | "-F" | "--field" | "--raw-field" => {
+                value()?;
+                sends_fields = true;
+            }
+            "--input" => return Err("Sending a request body is not allowed".to_string()),
+            "-H" | "--header" => {
+                let header = value()?.to_lowercase();
+                if header.contains("x-http-method-override") {
+                    return Err("Overriding the HTTP method is not allowed".to_string());
+                }
+                sets_accept |= header.trim_start().starts_with("accept");
+            }
+            "-p" | "--preview" => {
+                value()?;
+                sets_accept = true;
+            }
+            "-q" | "--jq" | "-t" | "--template" => {
+                value()?;
+                formats_output = true;
+            }
+            "--hostname" => {
+                let host = value()?;
+                if !allowed_host(&host) {
+                    return Err(format!("Requests to {} are not allowed", host));
+                }
+            }
+            "--cache" => {
+                value()?;
+            }
+            "--paginate" | "--slurp" | "-i" | "--include" | "--silent" | "--verbose" => {}
+            _ if arg.starts_with("-X") && arg.len() > 2 => {
+                method = Some(arg[2..].to_uppercase());
+            }
+            _ if arg.starts_with('-') => return Err(format!("gh api {} is not allowed", arg)),
+            _ if endpoint.is_none() => endpoint = Some(i),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+        i += 1;
+    }
+
+    // gh switches to POST when fields are given without a method
+    match method.as_deref() {
+        Some("GET") => {}
+        Some(other) => return Err(format!("Only GET requests are allowed, not {}", other)),
+        None if sends_fields => return Err("Fields without -X GET make gh send a POST".to_string()),
+        None => {}
+    }
+
+    let index = endpoint.ok_or("gh api needs an endpoint")?;
+    let (owner, name) = repository
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid repository {}", repository))?;
+    // gh fills these placeholders from the working directory, which isn't the repository
+    let resolved = args[index]
+        .replace("{owner}", owner)
+        .replace("{repo}", name);
+    let path = resolved.trim_start_matches('/');
+    let path = path.split('?').next().unwrap_or(path);
+    // GitHub would resolve these after the checks below, e.g. repos/o/r/contents/../../x
+    if path.contains(['%', '\\']) || path.split('/').any(|part| part == ".." || part == ".") {
+        return Err("Encoded or relative path segments are not allowed".to_string());
+    }
+    if path == "graphql" {
+        return Err("GraphQL requests can't be checked for writes; use REST GET endpoints".into());
+    }
+
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    let in_repository = parts.len() >= 3
+        && parts[0] == "repos"
+        && same_repository(&format!("{}/{}", parts[1], parts[2]), repository);
+    if !in_repository {
+        return Err(format!(
+            "Only endpoints under repos/{} are allowed",
+            repository
+        ));
+    }
+    match parts.get(3).copied() {
+        None => {}
+        Some("pulls" | "issues") => {
+            let pr_number = scope
+                .pr_number
+                .ok_or("No PR is under review, so PR endpoints are not available")?;
+            if parts.get(4).and_then(|n| n.parse::<u64>().ok()) != Some(pr_number) {
+                return Err(format!("Only PR #{} may be read", pr_number));
+            }
+        }
+        Some(section) if READABLE_REPO_SECTIONS.contains(&section) => {}
+        Some(section) => return Err(format!("repos/{}/{} is not allowed", repository, section)),
+    }
+
+    // File contents come base64-encoded in JSON, which would hide secrets from redaction,
+    // so they are always fetched raw
+    let file_contents = matches!(parts.get(3).copied(), Some("contents" | "readme"))
+        || parts.get(3..5) == Some(&["git", "blobs"]);
+
+    let mut args = args.to_vec();
+    args[index] = resolved;
+    if file_contents {
+        if sets_accept || formats_output {
+            return Err(
+                "File contents are returned raw, so custom Accept headers, --jq and --template \
+                 are not available for them"
+                    .to_string(),
+            );
+        }
+        args.extend(["-H".to_string(), RAW_ACCEPT.to_string()]);
+    }
+    Ok(args)
+}
+
+/// Path of the file a checked `gh api` call reads raw, if it reads one
+fn contents_path(args: &[String]) -> Option<String> {
+    if args.first().map(String::as_str) != Some("api") {
+        return None;
+    }
+    let endpoint = args
+        .iter()
+        .skip(1)
+        .map(|arg| arg.trim_start_matches('/'))
+        .find(|arg| arg.starts_with("repos/"))?;
+    let path = endpoint.split('?').next().unwrap_or(endpoint);
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match parts.get(3).copied() {
+        Some("contents") if parts.len() > 4 => Some(parts[4..].join("/")),
+        _ => None,
+    }
+}
+
+/// Check `gh auth status` only uses flags that can't print the token
+fn check_auth_status(flags: &[String]) -> Result<(), String> {
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "-a" | "--active" => {}
+            "-h" | "--hostname" => {
+                flags.next().ok_or("--hostname needs a value")?;
+            }
+            _ if flag.starts_with("--hostname=") => {}
+            _ => return Err(format!("gh auth status {} is not allowed", flag)),
+        }
+    }
+    Ok(())
+}
+
+/// Decide whether `args` may run, returning them as they should be passed to the real gh
+pub fn check(args: &[String], scope: &Scope) -> Result<Vec<String>, String> {
+    // Pull out --repo wherever it is, as gh accepts it anywhere
+    let mut rest = Vec::with_capacity(args.len());
+    let mut repo_flag: Option<String> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-R" || arg == "--repo" {
+            repo_flag = Some(iter.next().cloned().ok_or("--repo needs a value")?);
+        } else if let Some(value) = arg.strip_prefix("--repo=") {
+            repo_flag = Some(value.to_string());
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+
+    let command = rest.first().cloned().unwrap_or_default();
+    match command.as_str() {
+        "--version" | "version" | "--help" | "help" => return Ok(args.to_vec()),
+        "auth" if rest.get(1).map(String::as_str) == Some("status") => {
+            check_auth_status(&rest[2..])?;
+            return Ok(args.to_vec());
+        }
+        _ => {}
+    }
+
+    let repository = scope
+        .repository
+        .as_deref()
+        .ok_or("No repository is under review, so gh is not available")?;
+    if let Some(repo) = &repo_flag {
+        if !same_repository(repo, repository) {
+            return Err(format!("Only {} may be read, not {}", repository, repo));
+        }
+    }
+
+    match command.as_str() {
+        "pr" => check_pr(&rest, scope, repository),
+        "api" => check_api(&rest, scope, repository),
+        "repo" if rest.get(1).map(String::as_str) == Some("view") => {
+            if rest.iter().any(|arg| arg == "--web" || arg == "-w") {
+                return Err("Opening a browser is not allowed".to_string());
+            }
+            match rest.get(2).filter(|arg| !arg.starts_with('-')) {
+                Some(repo) if !same_repository(repo, repository) => {
+                    Err(format!("Only {} may be read, not {}", repository, repo))
+                }
+                Some(_) => Ok(rest),
+                None => {
+                    let mut args = rest;
+                    args.insert(2, repository.to_string());
+                    Ok(args)
+                }
+            }
+        }
+        "" => Err("No gh command given".to_string()),
+        other => Err(format!("gh {} is not allowed", other)),
+    }
+}
+
+fn send(endpoint: &str, request: &ShimRequest) -> Result<ShimResponse, String> {
+    let mut stream = connect(endpoint).map_err(|e| e.to_string())?;
+    let mut json = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+    json.push(b'\n');
+    stream.write_all(&json).map_err(|e| e.to_string())?;
+    let mut body = Vec::new();
+    stream.read_to_end(&mut body).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&body).map_err(|e| e.to_string())
+}
+
+/// Entry point when the app runs as the shim. Returns the exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    let (endpoint, grant) = match (std::env::var(ENV_ENDPOINT), std::env::var(ENV_GRANT)) {
+        (Ok(endpoint), Ok(grant)) => (endpoint, grant),
+        _ => {
+            eprintln!("gh: this gh is only available to AI agents started by Lyon");
+            return 1;
+        }
+    };
+    let response = match send(&endpoint, &ShimRequest { grant, args }) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("gh: failed to reach Lyon's gh proxy: {}", e);
+            return 1;
+        }
+    };
+    let _ = std::io::stdout().write_all(response.stdout.as_bytes());
+    let _ = std::io::stderr().write_all(response.stderr.as_bytes());
+    response.code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope() -> Scope {
+        Scope {
+            repository: Some("octo/app".to_string()),
+            pr_number: Some(7),
+        }
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn allowed(line: &str) -> Vec<String> {
+        check(&args(line), &scope()).unwrap_or_else(|e| panic!("{} was refused: {}", line, e))
+    }
+
+    fn refused(line: &str) -> String {
+        match check(&args(line), &scope()) {
+            Ok(forwarded) => panic!("{} was allowed as {:?}", line, forwarded),
+            Err(reason) => reason,
+        }
+    }
+
+    #[test]
+    fn reads_the_pr_under_review() {
+        assert_eq!(allowed("pr view 7"), args("pr view 7 --repo octo/app"));
+        assert_eq!(
+            allowed("pr diff https://github.com/octo/app/pull/7/files"),
+            args("pr diff https://github.com/octo/app/pull/7/files --repo octo/app")
+        );
+        assert_eq!(
+            allowed("pr checks --repo octo/app #7"),
+            args("pr checks #7 --repo octo/app")
+        );
+    }
+
+    #[test]
+    fn refuses_other_prs_and_writes() {
+        refused("pr view 8");
+        refused("pr diff https://github.com/other/app/pull/7");
+        refused("pr view");
+        refused("pr view 7 --web");
+        refused("pr merge 7");
+        refused("pr comment 7 --body hi");
+        refused("pr view 7 --repo other/app");
+        refused("issue close 7");
+        refused("repo delete octo/app");
+    }
+
+    #[test]
+    fn needs_a_repository_in_scope() {
+        let reason = check(&args("pr view 7"), &Scope::default()).unwrap_err();
+        assert!(reason.contains("No repository"), "{}", reason);
+        assert_eq!(
+            check(&args("--version"), &Scope::default()).unwrap(),
+            args("--version")
+        );
+    }
+
+    #[test]
+    fn auth_status_never_shows_the_token() {
+        assert_eq!(allowed("auth status"), args("auth status"));
+        allowed("auth status --active --hostname github.com");
+        allowed("auth status -h github.com");
+        refused("auth status --show-token");
+        refused("auth status -t");
+        refused("auth status -a -t");
+        refused("auth status --json hosts");
+        refused("auth token");
+    }
+
+    #[test]
+    fn repo_view_is_scoped() {
+        assert_eq!(allowed("repo view"), args("repo view octo/app"));
+        assert_eq!(allowed("repo view octo/app"), args("repo view octo/app"));
+        refused("repo view other/app");
+        refused("repo view --web");
+    }
+
+    #[test]
+    fn api_allows_gets_under_the_repository() {
+        assert_eq!(
+            allowed("api repos/{owner}/{repo}/pulls/7/files"),
+            args("api repos/octo/app/pulls/7/files")
+        );
+        allowed("api /repos/octo/app/contents/src/main.rs?ref=main");
+        allowed("api repos/octo/app/issues/7/comments --paginate --jq .[].body");
+        allowed("api -X GET repos/octo/app/commits -f per_page=5");
+        allowed("api --method=get repos/octo/app");
+    }
+
+    #[test]
+    fn api_refuses_writes_and_other_endpoints() {
+        refused("api -X POST repos/octo/app/issues/7/comments");
+        refused("api -XDELETE repos/octo/app");
+        refused("api repos/octo/app/issues/7/comments -f body=hi");
+        refused("api repos/octo/app/pulls/7/merge --input body.json");
+        refused("api repos/octo/app/pulls -H X-HTTP-Method-Override:PUT");
+        refused("api repos/octo/app/pulls/8");
+        refused("api repos/octo/app/hooks");
+        refused("api repos/other/app/contents/x");
+        refused("api user");
+        refused("api graphql -f query=x");
+        refused("api https://api.github.com/repos/octo/app");
+    }
+
+    #[test]
+    fn api_only_sends_to_github() {
+        allowed("api repos/octo/app --hostname github.com");
+        allowed("api --hostname=GitHub.com repos/octo/app");
+        refused("api repos/octo/app --hostname evil.example");
+        refused("api --hostname=evil.example repos/octo/app/contents/x");
+        refused("api repos/octo/app --hostname");
+    }
+
+    #[test]
+    fn api_refuses_relative_and_encoded_paths() {
+        refused("api repos/octo/app/contents/../../../user/repos");
+        refused("api repos/octo/app/pulls/7/../../hooks");
+        refused("api repos/octo/app/./hooks");
+        refused("api repos/octo/app/contents/%2e%2e/%2e%2e/hooks");
+        refused("api repos/octo/app/pulls%2F8");
+        refused("api repos/octo/app/contents/..%5c..%5chooks");
+    }
+
+    #[test]
+    fn api_fetches_file_contents_raw() {
+        let forwarded = allowed("api repos/{owner}/{repo}/contents/config/.env?ref=main");
+        assert_eq!(
+            forwarded,
+            [
+                "api",
+                "repos/octo/app/contents/config/.env?ref=main",
+                "-H",
+                RAW_ACCEPT
+            ]
+        );
+        assert_eq!(contents_path(&forwarded).as_deref(), Some("config/.env"));
+        assert_eq!(
+            allowed("api repos/octo/app/git/blobs/abc123")[2..],
+            ["-H", RAW_ACCEPT]
+        );
+        assert_eq!(allowed("api repos/octo/app/commits").len(), 2);
+        assert_eq!(contents_path(&allowed("api repos/octo/app/readme")), None);
+
+        refused("api repos/octo/app/contents/.env --jq .content");
+        refused("api repos/octo/app/readme -H Accept:application/json");
+        refused("api repos/octo/app/git/blobs/abc123 -p mercy");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relays_requests_over_the_socket() {
+        let dir = std::env::temp_dir().join(format!("gh-shim-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let endpoint = listen(&dir, Arc::new(ShimState::default())).unwrap();
+
+        let response = send(
+            &endpoint,
+            &ShimRequest {
+                grant: "guess".to_string(),
+                args: args("pr view 7"),
+            },
+        )
+        .unwrap();
+        assert_eq!(response.code, 1);
+        assert!(
+            response.stderr.contains("may not use gh"),
+            "{}",
+            response.stderr
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn requests_are_checked_against_their_grant() {
+        let log = std::env::temp_dir().join(format!("gh-shim-test-{}.jsonl", Uuid::new_v4()));
+        let shim = GhShim::default();
+        let _ = shim.state.log.set(log.clone());
+        shim.state.grants.lock().unwrap().insert(
+            "grant".to_string(),
+            Grant {
+                process_id: "run-1".to_string(),
+                scope: scope(),
+            },
+        );
+        let request = |grant: &str, line: &str| {
+            shim.state.respond(&ShimRequest {
+                grant: grant.to_string(),
+                args: args(line),
+            })
+        };
+
+        let response = request("grant", "pr merge 7");
+        assert_eq!(response.code, 1);
+        assert!(response.stderr.contains("blocked"), "{}", response.stderr);
+        let blocked = shim.blocked(Some("run-1")).unwrap();
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].args, args("pr merge 7"));
+
+        assert!(request("guess", "pr view 7")
+            .stderr
+            .contains("may not use gh"));
+        shim.revoke("run-1");
+        assert!(request("grant", "pr view 7")
+            .stderr
+            .contains("may not use gh"));
+        let _ = std::fs::remove_file(log);
+    }
+}
@@ -0,0 +1,411 @@
+//! Incremental re-reviews: only the commits pushed since the last recorded review of a PR
+//! are reviewed, with that review's findings in the prompt so the model can say which of
+//! them the new commits resolved instead of raising them again.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager};
+
+use crate::diff::parse_unified_diff;
+use crate::jobs::JobOutcome;
+use crate::map_reduce::REVIEW_FORMAT;
+use crate::providers::ProviderRequest;
+use crate::review_batch::fence_for;
+use crate::review_history::{ReviewHistory, ReviewRecord};
+use crate::review_result::{parse_review, ReviewComment, ReviewSuggestion};
+use crate::streams::{AIEventEnvelope, OutputCollector};
+use crate::{launch_ai_run, run_gh_command, AITimeouts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingStatus {
+    Resolved,
+    StillPresent,
+    /// The new commits touch the file but the model didn't say
+    Unverified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousFinding {
+    #[serde(flatten)]
+    pub comment: ReviewComment,
+    pub status: FindingStatus,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncrementalReview {
+    /// Head commit of the previous review
+    pub base_sha: String,
+    pub head_sha: String,
+    pub summary: String,
+    pub overall_score: Option<f64>,
+    /// New findings in the commits since `base_sha`
+    pub comments: Vec<ReviewComment>,
+    pub suggestions: Vec<ReviewSuggestion>,
+    pub previous_findings: Vec<PreviousFinding>,
+    pub warnings: Vec<String>,
+}
+
+pub struct IncrementalParams {
+    pub provider: String,
+    /// Model, reasoning effort and repository; the prompt is built from the delta
+    pub request: ProviderRequest,
+    pub pr_number: u64,
+    pub system_prompt: String,
+    pub review_id: String,
+    pub priority: i32,
+    pub timeouts: AITimeouts,
+}
+
+struct PrHead {
+    sha: String,
+    title: String,
+}
+
+async fn fetch_head(number: u64, repository: &str) -> Result<PrHead, String> {
+    let json = run_gh_command(
+        [
+            "pr",
+            "view",
+            &number.to_string(),
+            "--repo",
+            repository,
+            "--json",
+            "headRefOid,title",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    )
+    .await?;
+    let view: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse PR #{}: {}", number, e))?;
+    let field = |key: &str| view.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    if field("headRefOid").is_empty() {
+        return Err(format!("PR #{} has no head commit", number));
+    }
+    Ok(PrHead {
+        sha: field("headRefOid").to_string(),
+        title: field("title").to_string(),
+    })
+}
+
+/// The diff between two commits, which works across forks as long as `base` still exists
+async fn fetch_compare_diff(repository: &str, base: &str, head: &str) -> Result<String, String> {
+    run_gh_command(vec![
+        "api".to_string(),
+        format!("repos/{}/compare/{}...{}", repository, base, head),
+        "-H".to_string(),
+        "Accept: application/vnd.github.diff".to_string(),
+    ])
+    .await
+    .map_err(|e| {
+        format!(
+            "Could not compare {} with {}; the branch may have been force-pushed, so run a full review instead. {}",
+            short_sha(base),
+            short_sha(head),
+            e
+        )
+    })
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+fn incremental_prompt(
+    params: &IncrementalParams,
+    repository: &str,
+    title: &str,
+    previous: &ReviewRecord,
+    head_sha: &str,
+    diff: &str,
+) -> String {
+    let findings = previous
+        .comments
+        .iter()
+        .enumerate()
+        .map(|(i, comment)| {
+            let first_line = comment.body.lines().next().unwrap_or_default();
+            format!(
+                "[{}] {}:{} ({}) {}",
+                i + 1,
+                comment.path,
+                comment.line,
+                comment.severity,
+                first_line
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut prompt = format!(
+        "{system_prompt}
+
+You already reviewed Pull Request #{number} in repository {repository} (\"{title}\") at commit {base}. The author has since pushed commits up to {head}. Review only the changes below, made since that review; the rest of the PR was already reviewed. The diff is included here; do not fetch it yourself.
+",
+        system_prompt = params.system_prompt,
+        number = params.pr_number,
+        base = short_sha(&previous.head_sha),
+        head = short_sha(head_sha),
+    );
+    if !findings.is_empty() {
+        prompt.push_str(&format!(
+            "
+Findings from that review:
+{findings}
+
+For each of them, decide from the changes below whether it has been resolved or is still present. A finding in code these changes don't touch is still present. Don't repeat them in \"comments\"; only report new issues introduced by these changes there.
+"
+        ));
+    }
+    let fence = fence_for(diff);
+    prompt.push_str(&format!("\n{fence}diff\n{diff}{fence}\n\n{REVIEW_FORMAT}"));
+    if !findings.is_empty() {
+        prompt.push_str(
+            "
+
+Also include a \"previousFindings\" array with one entry per earlier finding:
+\"previousFindings\": [{\"id\": 1, \"status\": \"resolved\" or \"still_present\", \"line\": current line if it moved, \"note\": \"How it was addressed, or why it still applies\"}]",
+        );
+    }
+    prompt
+}
+
+struct Assessment {
+    status: FindingStatus,
+    line: Option<u64>,
+    note: Option<String>,
+}
+
+/// Read the model's verdicts on earlier findings, keyed by their 1-based id
+fn parse_assessments(
+    value: Option<&Value>,
+    warnings: &mut Vec<String>,
+) -> HashMap<usize, Assessment> {
+    let mut assessments = HashMap::new();
+    let items = match value {
+        Some(Value::Array(items)) => items,
+        Some(Value::Null) | None => return assessments,
+        Some(_) => {
+            warnings.push("Ignored previousFindings that were not an array".to_string());
+            return assessments;
+        }
+    };
+    for item in items {
+        let id = match item.get("id") {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => s.trim().trim_start_matches('#').parse().ok(),
+            _ => None,
+        };
+        let status = item
+            .get("status")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_lowercase().replace([' ', '-'], "_"));
+        let status = match status.as_deref() {
+            Some("resolved" | "fixed" | "addressed") => FindingStatus::Resolved,
+            Some("still_present" | "present" | "open" | "unresolved" | "not_resolved") => {
+                FindingStatus::StillPresent
+            }
+            other => {
+                warnings.push(format!(
+                    "Ignored previous finding with status {:?}",
+                    other.unwrap_or("(none)")
+                ));
+                continue;
+            }
+        };
+        let id = match id {
+            Some(id) if id > 0 => id as usize,
+            _ => {
+                warnings.push("Ignored previous finding without an id".to_string());
+                continue;
+            }
+        };
+        assessments.insert(
+            id,
+            Assessment {
+                status,
+                line: item.get("line").and_then(|v| v.as_u64()).filter(|&l| l > 0),
+                note: item
+                    .get("note")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+            },
+        );
+    }
+    assessments
+}
+
+pub async fn run(
+    app: AppHandle,
+    params: IncrementalParams,
+    on_event: Channel<AIEventEnvelope>,
+) -> Result<IncrementalReview, String> {
+    let repository = params
+        .request
+        .repository
+        .clone()
+        .ok_or("An incremental review needs the PR's repository")?;
+    let previous = app
+        .state::<ReviewHistory>()
+        .latest(&repository, params.pr_number)?
+        .ok_or_else(|| {
+            format!(
+                "PR #{} has no earlier AI review to continue from; run a full review first",
+                params.pr_number
+            )
+        })?;
+
+    let head = fetch_head(params.pr_number, &repository).await?;
+    if head.sha == previous.head_sha {
+        return Err(format!(
+            "No new commits since the last AI review at {}",
+            short_sha(&head.sha)
+        ));
+    }
+    let diff = fetch_compare_diff(&repository, &previous.head_sha, &head.sha).await?;
+    let files = parse_unified_diff(&diff);
+    if files.is_empty() {
+        return Err(format!(
+            "The commits since {} don't change any files",
+            short_sha(&previous.head_sha)
+        ));
+    }
+
+    let prompt = incremental_prompt(
+        &params,
+        &repository,
+        &head.title,
+        &previous,
+        &head.sha,
+        &diff,
+    );
+    let collector = OutputCollector::default();
+    let outcome_rx = launch_ai_run(
+        &app,
+        params.provider.clone(),
+        ProviderRequest {
+            prompt,
+            ..params.request.clone()
+        },
+        params.review_id.clone(),
+        params.priority,
+        params.timeouts,
+        vec![on_event, collector.channel()],
+    )
+    .await?;
+    let outcome = outcome_rx.await.unwrap_or(JobOutcome::Cancelled);
+    let output = collector.take();
+    if outcome != JobOutcome::Completed {
+        return Err(output
+            .error()
+            .unwrap_or_else(|| "The review did not finish".to_string()));
+    }
+    let parsed = parse_review(&output.text);
+
+    let mut warnings = parsed.warnings;
+    let mut assessments = parse_assessments(parsed.extra.get("previousFindings"), &mut warnings);
+    let touched = |path: &str| {
+        files
+            .iter()
+            .any(|file| file.path == path || file.old_path.as_deref() == Some(path))
+    };
+
+    let previous_findings: Vec<PreviousFinding> = previous
+        .comments
+        .iter()
+        .enumerate()
+        .map(|(i, comment)| {
+            let mut comment = comment.clone();
+            match assessments.remove(&(i + 1)) {
+                Some(assessment) => {
+                    if let Some(line) = assessment.line {
+                        comment.line = line;
+                    }
+                    PreviousFinding {
+                        comment,
+                        status: assessment.status,
+                        note: assessment.note,
+                    }
+                }
+                None if !touched(&comment.path) => PreviousFinding {
+                    comment,
+                    status: FindingStatus::StillPresent,
+                    note: Some("The file is unchanged since the last review".to_string()),
+                },
+                None => {
+                    warnings.push(format!(
+                        "Previous finding #{} ({}:{}) was not assessed",
+                        i + 1,
+                        comment.path,
+                        comment.line
+                    ));
+                    PreviousFinding {
+                        comment,
+                        status: FindingStatus::Unverified,
+                        note: None,
+                    }
+                }
+            }
+        })
+        .collect();
+    for id in assessments.keys() {
+        warnings.push(format!("Ignored assessment of unknown finding #{}", id));
+    }
+
+    let summary = parsed.summary.unwrap_or_default();
+    // Without a parsed result the delta wasn't really reviewed, so the next incremental
+    // review should cover it again
+    if parsed.found_json {
+        let open_findings = previous_findings
+            .iter()
+            .filter(|finding| finding.status != FindingStatus::Resolved)
+            .map(|finding| finding.comment.clone());
+        let carried_suggestions = previous
+            .suggestions
+            .iter()
+            .filter(|suggestion| !touched(&suggestion.path))
+            .cloned();
+        app.state::<ReviewHistory>().add(ReviewRecord {
+            review_id: params.review_id.clone(),
+            repository: repository.clone(),
+            pr_number: params.pr_number,
+            head_sha: head.sha.clone(),
+            provider: params.provider.clone(),
+            model: params.request.model.clone(),
+            created_at: 0,
+            summary: Some(summary.clone()),
+            overall_score: parsed.overall_score,
+            comments: parsed
+                .comments
+                .iter()
+                .cloned()
+                .chain(open_findings)
+                .collect(),
+            suggestions: parsed
+                .suggestions
+                .iter()
+                .cloned()
+                .chain(carried_suggestions)
+                .collect(),
+        })?;
+    }
+
+    Ok(IncrementalReview {
+        base_sha: previous.head_sha,
+        head_sha: head.sha,
+        summary,
+        overall_score: parsed.overall_score,
+        comments: parsed.comments,
+        suggestions: parsed.suggestions,
+        previous_findings,
+        warnings,
+    })
+}
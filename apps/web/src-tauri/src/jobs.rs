@@ -0,0 +1,379 @@
+//! Queue for AI runs. Each `start_ai_stream` call becomes a job that waits for a free
+//! slot; at most `max_concurrency` jobs run at once and higher priorities start first.
+
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tokio::sync::oneshot;
+
+use crate::streams::{emit_event, AIEvent};
+
+const DEFAULT_MAX_CONCURRENCY: usize = 2;
+/// Finished jobs kept around for `list_ai_jobs`
+const MAX_DONE_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+    #[serde(rename = "timeout")]
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    /// Same as the process id used by the stream events
+    pub id: String,
+    pub provider: String,
+    pub priority: i32,
+    pub state: JobState,
+    /// Why a queued job is being held back instead of waiting for a slot
+    pub blocked_by: Option<String>,
+    pub outcome: Option<JobOutcome>,
+    /// Milliseconds since the Unix epoch
+    pub queued_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+struct Job {
+    info: JobInfo,
+    /// Enqueue order, so equal priorities start first-in first-out
+    seq: u64,
+    /// Fired when the job gets a slot; dropping it abandons the waiting run
+    start_tx: Option<oneshot::Sender<()>>,
+    /// Set when a running job is cancelled before its process is registered
+    cancel_requested: bool,
+}
+
+struct QueueInner {
+    max_concurrency: usize,
+    next_seq: u64,
+    jobs: Vec<Job>,
+}
+
+pub struct JobQueue {
+    inner: Mutex<QueueInner>,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(QueueInner {
+                max_concurrency: DEFAULT_MAX_CONCURRENCY,
+                next_seq: 0,
+                jobs: Vec::new(),
+            }),
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Report queue changes on each run's own event channel. A finished job is reported by
+/// the run's terminal event instead.
+fn emit_jobs(app: &AppHandle, changed: Vec<JobInfo>) {
+    for info in changed {
+        if info.state == JobState::Done {
+            continue;
+        }
+        emit_event(app, &info.id.clone(), AIEvent::Job { info });
+    }
+}
+
+impl JobQueue {
+    /// Add a job; the returned receiver fires once it may start running. A blocked job
+    /// doesn't start until `unblock` is called. Fails if a job with the same id is still
+    /// queued or running.
+    pub fn enqueue(
+        &self,
+        app: &AppHandle,
+        id: &str,
+        provider: &str,
+        priority: i32,
+        blocked_by: Option<String>,
+    ) -> Result<oneshot::Receiver<()>, String> {
+        let (start_tx, start_rx) = oneshot::channel();
+        let mut changed = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.is_active(id) {
+                return Err(format!("AI run {} is already queued or running", id));
+            }
+            let seq = inner.next_seq;
+            inner.next_seq += 1;
+            let info = JobInfo {
+                id: id.to_string(),
+                provider: provider.to_string(),
+                priority,
+                state: JobState::Queued,
+                blocked_by,
+                outcome: None,
+                queued_at: now_ms(),
+                started_at: None,
+                finished_at: None,
+            };
+            changed.push(info.clone());
+            inner.jobs.push(Job {
+                info,
+                seq,
+                start_tx: Some(start_tx),
+                cancel_requested: false,
+            });
+            inner.dispatch(&mut changed);
+        }
+        emit_jobs(app, changed);
+        Ok(start_rx)
+    }
+
+    /// Mark a running job as done and start whatever is next in line
+    pub fn finish(&self, app: &AppHandle, id: &str, outcome: JobOutcome) {
+        let mut changed = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.mark_done(id, outcome, &mut changed);
+            inner.dispatch(&mut changed);
+        }
+        emit_jobs(app, changed);
+    }
+
+    /// Drop a job that hasn't started yet. Returns false if it isn't queued.
+    pub fn cancel_queued(&self, app: &AppHandle, id: &str) -> bool {
+        let mut changed = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let queued = inner
+                .jobs
+                .iter()
+                .any(|job| job.info.id == id && job.info.state == JobState::Queued);
+            if !queued {
+                return false;
+            }
+            inner.mark_done(id, JobOutcome::Cancelled, &mut changed);
+        }
+        emit_jobs(app, changed);
+        true
+    }
+
+    /// Let a blocked job start once a slot is free. Returns false if it isn't queued.
+    pub fn unblock(&self, app: &AppHandle, id: &str) -> bool {
+        let mut changed = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let job = match inner
+                .jobs
+                .iter_mut()
+                .find(|job| job.info.id == id && job.info.state == JobState::Queued)
+            {
+                Some(job) => job,
+                None => return false,
+            };
+            if job.info.blocked_by.take().is_some() {
+                changed.push(job.info.clone());
+            }
+            inner.dispatch(&mut changed);
+        }
+        emit_jobs(app, changed);
+        true
+    }
+
+    /// Flag a running job as cancelled, for a run whose process isn't registered yet.
+    /// Returns false if the job isn't running.
+    pub fn request_cancel(&self, id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner
+            .jobs
+            .iter_mut()
+            .find(|job| job.info.id == id && job.info.state == JobState::Running)
+        {
+            Some(job) => {
+                job.cancel_requested = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_requested(&self, id: &str) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.jobs.iter().any(|job| {
+            job.info.id == id && job.info.state == JobState::Running && job.cancel_requested
+        })
+    }
+
+    /// Whether a job with this id is queued or running
+    pub fn is_active(&self, id: &str) -> bool {
+        self.inner.lock().unwrap().is_active(id)
+    }
+
+    pub fn is_queued(&self, id: &str) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .jobs
+            .iter()
+            .any(|job| job.info.id == id && job.info.state == JobState::Queued)
+    }
+
+    pub fn reprioritize(
+        &self,
+        app: &AppHandle,
+        id: &str,
+        priority: i32,
+    ) -> Result<JobInfo, String> {
+        let info = {
+            let mut inner = self.inner.lock().unwrap();
+            let job = inner
+                .jobs
+                .iter_mut()
+                .find(|job| job.info.id == id)
+                .ok_or_else(|| format!("Unknown AI job: {}", id))?;
+            if job.info.state != JobState::Queued {
+                return Err(format!("AI job {} has already started", id));
+            }
+            job.info.priority = priority;
+            job.info.clone()
+        };
+        emit_jobs(app, vec![info.clone()]);
+        Ok(info)
+    }
+
+    pub fn set_max_concurrency(&self, app: &AppHandle, max: usize) -> Result<(), String> {
+        if max == 0 {
+            return Err("Max concurrency must be at least 1".to_string());
+        }
+        let mut changed = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            // Lowering the limit lets running jobs finish; it only holds back new starts
+            inner.max_concurrency = max;
+            inner.dispatch(&mut changed);
+        }
+        emit_jobs(app, changed);
+        Ok(())
+    }
+
+    /// Running jobs, then queued jobs in the order they will start, then finished jobs (newest first)
+    pub fn list(&self) -> Vec<JobInfo> {
+        let inner = self.inner.lock().unwrap();
+        let mut jobs: Vec<&Job> = inner.jobs.iter().collect();
+        jobs.sort_by(|a, b| {
+            let rank = |job: &Job| match job.info.state {
+                JobState::Running => 0,
+                JobState::Queued => 1,
+                JobState::Done => 2,
+            };
+            rank(a).cmp(&rank(b)).then_with(|| match a.info.state {
+                JobState::Queued => b
+                    .info
+                    .priority
+                    .cmp(&a.info.priority)
+                    .then(a.seq.cmp(&b.seq)),
+                JobState::Running => a.info.started_at.cmp(&b.info.started_at),
+                JobState::Done => b.info.finished_at.cmp(&a.info.finished_at),
+            })
+        });
+        jobs.into_iter().map(|job| job.info.clone()).collect()
+    }
+}
+
+impl QueueInner {
+    fn is_active(&self, id: &str) -> bool {
+        self.jobs
+            .iter()
+            .any(|job| job.info.id == id && job.info.state != JobState::Done)
+    }
+
+    fn mark_done(&mut self, id: &str, outcome: JobOutcome, changed: &mut Vec<JobInfo>) {
+        if let Some(job) = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.info.id == id && job.info.state != JobState::Done)
+        {
+            job.start_tx = None;
+            job.info.state = JobState::Done;
+            job.info.outcome = Some(outcome);
+            job.info.finished_at = Some(now_ms());
+            changed.push(job.info.clone());
+        }
+
+        let done = self
+            .jobs
+            .iter()
+            .filter(|job| job.info.state == JobState::Done)
+            .count();
+        if done > MAX_DONE_JOBS {
+            // Jobs are appended in enqueue order, so the first done ones are the oldest
+            let mut excess = done - MAX_DONE_JOBS;
+            self.jobs.retain(|job| {
+                if excess > 0 && job.info.state == JobState::Done {
+                    excess -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+
+    /// Start queued, unblocked jobs while there are free slots
+    fn dispatch(&mut self, changed: &mut Vec<JobInfo>) {
+        loop {
+            let running = self
+                .jobs
+                .iter()
+                .filter(|job| job.info.state == JobState::Running)
+                .count();
+            if running >= self.max_concurrency {
+                return;
+            }
+
+            let next = self
+                .jobs
+                .iter_mut()
+                .filter(|job| job.info.state == JobState::Queued && job.info.blocked_by.is_none())
+                .max_by(|a, b| {
+                    a.info
+                        .priority
+                        .cmp(&b.info.priority)
+                        .then(b.seq.cmp(&a.seq))
+                });
+            let job = match next {
+                Some(job) => job,
+                None => return,
+            };
+
+            let started = job
+                .start_tx
+                .take()
+                .map(|tx| tx.send(()).is_ok())
+                .unwrap_or(false);
+            if started {
+                job.info.state = JobState::Running;
+                job.info.started_at = Some(now_ms());
+            } else {
+                // Nobody is waiting on this job anymore
+                job.info.state = JobState::Done;
+                job.info.outcome = Some(JobOutcome::Cancelled);
+                job.info.finished_at = Some(now_ms());
+            }
+            changed.push(job.info.clone());
+        }
+    }
+}
@@ -1,13 +1,67 @@
 use std::collections::HashMap;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tauri::{async_runtime::spawn_blocking, AppHandle, Emitter, Manager, State};
+use tauri::{async_runtime::spawn_blocking, ipc::Channel, AppHandle, Emitter, Manager, State};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command as TokioCommand;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
+
+mod anchoring;
+mod budgets;
+mod diff;
+mod ensemble;
+mod gh_shim;
+mod incremental;
+mod jobs;
+mod map_reduce;
+mod policy;
+mod process_groups;
+mod providers;
+mod review_batch;
+mod review_context;
+mod review_history;
+mod review_result;
+mod rules;
+mod secrets;
+mod streams;
+mod transcripts;
+mod usage;
+
+use anchoring::{AnchorComment, AnchorResult, DiffFileInput};
+use budgets::{BudgetAction, BudgetConfig, BudgetDecision, Budgets};
+use ensemble::{EnsembleReview, MemberRun};
+use gh_shim::{BlockedCommand, GhShim};
+use incremental::{IncrementalParams, IncrementalReview};
+use jobs::{JobInfo, JobOutcome, JobQueue};
+use map_reduce::{MapReduceParams, MapReduceProgress, MapReduceReview};
+use policy::ReviewPolicy;
+use process_groups::ProcessGroupRegistry;
+use providers::{
+    AiProvider, CustomProviderSet, HttpProvider, ProviderErrorKind, ProviderList, ProviderRequest,
+    ProviderStatus,
+};
+use review_batch::{BatchReviewRequest, BatchReviewResult};
+use review_context::{ContextOptions, ReviewContext};
+use review_history::{ReviewHistory, ReviewRecord};
+use review_result::ParsedReview;
+use rules::{ReviewRules, RuleReport, RulesConfig};
+use secrets::SecretFinding;
+use streams::{
+    emit_event, AIEvent, AIEventEnvelope, AttachResult, OutputCollector, StreamBuffers, StreamInfo,
+};
+use transcripts::{TranscriptInfo, TranscriptRecord, Transcripts};
+use usage::{UsageLog, UsageSummary};
+
+pub use gh_shim::SHIM_FLAG as GH_SHIM_FLAG;
+
+/// Run as the read-only `gh` proxy for AI agents instead of as the app. Returns the exit code.
+pub fn run_gh_shim(args: Vec<String>) -> i32 {
+    gh_shim::run(args)
+}
 
 /// Get enhanced PATH for finding CLI tools like gh, claude, codex, etc.
 /// macOS GUI apps launched from Finder don't inherit shell PATH, so we need to add common paths.
@@ -117,30 +171,148 @@ struct ProcessHandle {
 
 type ProcessMap = Arc<Mutex<HashMap<String, ProcessHandle>>>;
 
+const DEFAULT_RUN_TIMEOUT_SECS: u64 = 60 * 60;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 10 * 60;
+/// Longest single command-line argument Linux accepts (MAX_ARG_STRLEN)
+const MAX_ARG_BYTES: usize = 128 * 1024;
+
+/// Per-run limits; omitted values use the defaults and 0 disables a limit
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AITimeouts {
+    total_secs: Option<u64>,
+    idle_secs: Option<u64>,
+}
+
+impl AITimeouts {
+    fn total(&self) -> Option<Duration> {
+        match self.total_secs.unwrap_or(DEFAULT_RUN_TIMEOUT_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    fn idle(&self) -> Option<Duration> {
+        match self.idle_secs.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Time of the last output from a run, in milliseconds since it started
+#[derive(Clone)]
+struct ActivityClock {
+    started: Instant,
+    last_output_ms: Arc<AtomicU64>,
+}
+
+impl ActivityClock {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_output_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_output_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn last_output(&self) -> Instant {
+        self.started + Duration::from_millis(self.last_output_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// Resolves with the reason once a run exceeds its wall-clock limit or goes quiet for too long
+async fn watchdog(timeouts: AITimeouts, clock: ActivityClock) -> String {
+    let total = timeouts.total();
+    let idle = timeouts.idle();
+    loop {
+        let now = Instant::now();
+        let deadline = total.map(|total| clock.started + total);
+        let idle_deadline = idle.map(|idle| clock.last_output() + idle);
+
+        if let (Some(deadline), Some(total)) = (deadline, total) {
+            if now >= deadline {
+                return format!("Run exceeded the {} second time limit", total.as_secs());
+            }
+        }
+        if let (Some(idle_deadline), Some(idle)) = (idle_deadline, idle) {
+            if now >= idle_deadline {
+                return format!("No output for {} seconds", idle.as_secs());
+            }
+        }
+
+        // Output may have arrived meanwhile, so re-check at the earliest deadline
+        match deadline.into_iter().chain(idle_deadline).min() {
+            Some(wake) => tokio::time::sleep_until(wake.into()).await,
+            None => std::future::pending::<()>().await,
+        }
+    }
+}
+
+/// Terminate a provider process and, on Unix, everything in its process group
+async fn kill_process_tree(child: &mut tokio::process::Child, child_pid: Option<u32>) {
+    // Kill the entire process group on Unix
+    #[cfg(unix)]
+    if let Some(pid) = child_pid {
+        // Kill the process group (negative PID)
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGTERM);
+        }
+        // Give it a moment to terminate gracefully
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        // Force kill if still running
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = child_pid;
+        let _ = child.kill().await;
+    }
+    let _ = child.wait().await;
+}
+
 pub struct AIProcessState {
     processes: ProcessMap,
+    jobs: Arc<JobQueue>,
+    groups: Arc<ProcessGroupRegistry>,
 }
 
 impl Default for AIProcessState {
     fn default() -> Self {
         Self {
             processes: Arc::new(Mutex::new(HashMap::new())),
+            jobs: Arc::new(JobQueue::default()),
+            groups: Arc::new(ProcessGroupRegistry::default()),
         }
     }
 }
 
-#[derive(Clone, Serialize)]
-struct AIStreamEvent {
-    process_id: String,
-    event_type: String,
-    data: String,
+/// User-defined providers from `custom-providers.json` in the app config dir
+#[derive(Default)]
+pub struct CustomProviderState {
+    providers: Mutex<CustomProviderSet>,
 }
 
-#[derive(Clone, Serialize)]
-struct AIContentEvent {
-    process_id: String,
-    event_type: String,
-    text: String,
+fn custom_providers_path(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("custom-providers.json"))
+        .map_err(|e| format!("Failed to resolve config dir: {}", e))
+}
+
+fn load_custom_providers(app: &AppHandle) -> Result<CustomProviderSet, String> {
+    let path = custom_providers_path(app)?;
+    let set = providers::load_custom_providers(&path, &providers::builtin_provider_ids());
+    for error in &set.errors {
+        log::warn!("Custom provider rejected: {}", error);
+    }
+    Ok(set)
 }
 
 #[tauri::command]
@@ -223,24 +395,613 @@ async fn run_shell_command(command: String, args: Vec<String>) -> Result<String,
 }
 
 #[tauri::command]
+async fn list_ai_providers(custom: State<'_, CustomProviderState>) -> Result<ProviderList, String> {
+    let set = custom.providers.lock().await;
+    Ok(providers::list_providers(&set))
+}
+
+/// Re-read and validate `custom-providers.json`
+#[tauri::command]
+async fn reload_ai_providers(
+    app: AppHandle,
+    custom: State<'_, CustomProviderState>,
+) -> Result<ProviderList, String> {
+    let loaded = load_custom_providers(&app)?;
+    let mut set = custom.providers.lock().await;
+    *set = loaded;
+    Ok(providers::list_providers(&set))
+}
+
+/// Check that a provider's CLI is installed and usable, like `run_shell_command` with `--version`
+#[tauri::command]
+async fn check_ai_provider(
+    provider: String,
+    custom: State<'_, CustomProviderState>,
+) -> Result<ProviderStatus, String> {
+    let provider = providers::get_provider(&provider, &custom.providers.lock().await.providers)
+        .ok_or_else(|| format!("Unknown AI provider: {}", provider))?;
+
+    spawn_blocking(move || {
+        let command = provider.command().to_string();
+        let output = std::process::Command::new(&command)
+            .args(provider.version_args())
+            .env("PATH", get_enhanced_path())
+            .output();
+
+        let (exit_code, error_output) = match output {
+            Ok(output) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                return ProviderStatus {
+                    installed: true,
+                    authenticated: true,
+                    version: if version.is_empty() { None } else { Some(version) },
+                    error: None,
+                };
+            }
+            Ok(output) => (output.status.code(), String::from_utf8_lossy(&output.stderr).to_string()),
+            Err(e) => (None, e.to_string()),
+        };
+
+        match provider.classify_error(exit_code, &error_output) {
+            ProviderErrorKind::NotInstalled => ProviderStatus {
+                installed: false,
+                authenticated: false,
+                version: None,
+                error: Some(ProviderErrorKind::NotInstalled.describe(&command)),
+            },
+            ProviderErrorKind::NotAuthenticated => ProviderStatus {
+                installed: true,
+                authenticated: false,
+                version: None,
+                error: Some(ProviderErrorKind::NotAuthenticated.describe(&command)),
+            },
+            // If the version check fails some other way, the CLI might still work
+            _ => ProviderStatus {
+                installed: true,
+                authenticated: true,
+                version: None,
+                error: None,
+            },
+        }
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// A resolved provider, ready to start once its job gets a slot
+enum AILaunch {
+    Cli(Box<dyn AiProvider>),
+    Http(Box<dyn HttpProvider>, Box<reqwest::RequestBuilder>),
+}
+
+/// Queue an AI run. The process starts when the job queue has a free slot, so spawn
+/// failures are reported as `AIEvent::Error` on `on_event` rather than through this result.
+/// With `context`, `request.prompt` holds only the instructions: the backend fetches the PR
+/// named by `request.repository` and `request.pr_number` into the prompt and sends it on stdin.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn start_ai_stream(
-    command: String,
-    args: Vec<String>,
-    stdin_input: Option<String>,
+    provider: String,
+    mut request: ProviderRequest,
     process_id: Option<String>,
+    priority: Option<i32>,
+    timeouts: Option<AITimeouts>,
+    context: Option<ContextOptions>,
+    on_event: Channel<AIEventEnvelope>,
     app: AppHandle,
-    state: State<'_, AIProcessState>,
 ) -> Result<String, String> {
     let process_id = process_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-    let process_id_clone = process_id.clone();
+    if let Some(options) = context {
+        let (repository, pr_number) = match (request.repository.as_deref(), request.pr_number) {
+            (Some(repository), Some(pr_number)) => (repository, pr_number),
+            _ => return Err("Building the review context needs the repository and PR".to_string()),
+        };
+        let context =
+            review_context::build(repository, pr_number, &request.prompt, &options).await?;
+        for warning in &context.warnings {
+            log::warn!("Review context for {}#{}: {}", repository, pr_number, warning);
+        }
+        request.prompt = context.prompt;
+        request.prompt_on_stdin = true;
+    }
+    launch_ai_run(
+        &app,
+        provider,
+        request,
+        process_id.clone(),
+        priority.unwrap_or(0),
+        timeouts.unwrap_or_default(),
+        vec![on_event],
+    )
+    .await?;
+    Ok(process_id)
+}
+
+/// Assemble the review prompt `start_ai_stream` would send with `context`, without running it
+#[tauri::command]
+async fn build_review_context(
+    repository: String,
+    pr_number: u64,
+    instructions: String,
+    options: Option<ContextOptions>,
+) -> Result<ReviewContext, String> {
+    review_context::build(&repository, pr_number, &instructions, &options.unwrap_or_default()).await
+}
+
+/// Secrets a PR's diff adds, as critical findings. They are found without any model and are
+/// redacted from every prompt before it is sent.
+#[tauri::command]
+async fn scan_pr_secrets(repository: String, pr_number: u64) -> Result<Vec<SecretFinding>, String> {
+    let diff = run_gh_command(vec![
+        "pr".to_string(),
+        "diff".to_string(),
+        pr_number.to_string(),
+        "--repo".to_string(),
+        repository,
+    ])
+    .await?;
+    Ok(secrets::scan_diff(&diff::parse_unified_diff(&diff)))
+}
+
+#[tauri::command]
+async fn get_review_rules(rules: State<'_, ReviewRules>) -> Result<RulesConfig, String> {
+    Ok(rules.config())
+}
+
+/// Replace the local review rules; the change is refused if any rule doesn't compile
+#[tauri::command]
+async fn set_review_rules(config: RulesConfig, rules: State<'_, ReviewRules>) -> Result<(), String> {
+    rules.set_config(config)
+}
+
+/// Evaluate the local review rules and those of the repository's review policy against a
+/// PR's diff, skipping the files and severities the policy excludes. Findings have the
+/// shape of AI review comments.
+#[tauri::command]
+async fn run_review_rules(
+    repository: String,
+    pr_number: u64,
+    rules: State<'_, ReviewRules>,
+) -> Result<RuleReport, String> {
+    let policy = policy::resolve(&repository, pr_number, "", rules.config().rules).await;
+    let diff = run_gh_command(vec![
+        "pr".to_string(),
+        "diff".to_string(),
+        pr_number.to_string(),
+        "--repo".to_string(),
+        repository.clone(),
+    ])
+    .await?;
+    let files: Vec<_> = diff::parse_unified_diff(&diff)
+        .into_iter()
+        .filter(|file| !policy.is_ignored(&file.path))
+        .collect();
+    let mut report = rules::evaluate(&policy.rules, &repository, &files);
+    report.findings.retain(|finding| {
+        policy::meets_threshold(
+            &finding.comment.severity,
+            policy.severity_threshold.as_deref(),
+        )
+    });
+    report.warnings.extend(policy.warnings);
+    Ok(report)
+}
+
+/// The review configuration for a PR: `instructions` (the personal system prompt) and the
+/// local rules with the repository's `.github/lyon.yml` from the base branch applied
+#[tauri::command]
+async fn get_review_policy(
+    repository: String,
+    pr_number: u64,
+    instructions: String,
+    rules: State<'_, ReviewRules>,
+) -> Result<ReviewPolicy, String> {
+    Ok(policy::resolve(&repository, pr_number, &instructions, rules.config().rules).await)
+}
+
+/// One provider/model in an ensemble review; unset fields fall back to the shared request
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnsembleMember {
+    provider: String,
+    model: Option<String>,
+    reasoning_effort: Option<String>,
+}
+
+/// Review the same prompt with several providers or models in parallel and merge their
+/// findings. Members are ordinary queued runs with process ids `{ensemble_id}-{index}`,
+/// all streaming to `on_event`; this resolves once every member has finished.
+#[tauri::command]
+async fn run_ensemble_review(
+    members: Vec<EnsembleMember>,
+    request: ProviderRequest,
+    ensemble_id: Option<String>,
+    priority: Option<i32>,
+    timeouts: Option<AITimeouts>,
+    on_event: Channel<AIEventEnvelope>,
+    app: AppHandle,
+) -> Result<EnsembleReview, String> {
+    if members.len() < 2 {
+        return Err("An ensemble review needs at least two members".to_string());
+    }
+    let ensemble_id = ensemble_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let timeouts = timeouts.unwrap_or_default();
+
+    let mut launched = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    for (index, member) in members.into_iter().enumerate() {
+        let process_id = format!("{}-{}", ensemble_id, index);
+        let mut label = match &member.model {
+            Some(model) => format!("{} ({})", member.provider, model),
+            None => member.provider.clone(),
+        };
+        // Members with the same provider and model are told apart by their position
+        if labels.contains(&label) {
+            label = format!("{} #{}", label, index + 1);
+        }
+        labels.push(label.clone());
+        let member_request = ProviderRequest {
+            model: member.model.clone().or_else(|| request.model.clone()),
+            reasoning_effort: member
+                .reasoning_effort
+                .clone()
+                .or_else(|| request.reasoning_effort.clone()),
+            ..request.clone()
+        };
+        let collector = OutputCollector::default();
+        let outcome_rx = launch_ai_run(
+            &app,
+            member.provider.clone(),
+            member_request,
+            process_id.clone(),
+            priority.unwrap_or(0),
+            timeouts,
+            vec![on_event.clone(), collector.channel()],
+        )
+        .await;
+        launched.push((member, label, process_id, collector, outcome_rx));
+    }
+
+    let mut runs = Vec::new();
+    for (member, label, process_id, collector, outcome_rx) in launched {
+        let (outcome, error, output) = match outcome_rx {
+            Ok(outcome_rx) => {
+                let outcome = outcome_rx.await.unwrap_or(JobOutcome::Cancelled);
+                let output = collector.take();
+                (Some(outcome), output.error(), Some(output.text))
+            }
+            // Refused by its budget or misconfigured
+            Err(e) => (None, Some(e), None),
+        };
+        runs.push(MemberRun {
+            label,
+            provider: member.provider,
+            model: member.model,
+            process_id,
+            outcome,
+            error,
+            output,
+        });
+    }
+
+    ensemble::merge(runs)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MapReduceOptions {
+    /// Token budget per chunk of the diff
+    chunk_tokens: Option<u64>,
+    /// Chunks of this review queued at once
+    max_parallel: Option<usize>,
+    review_id: Option<String>,
+    priority: Option<i32>,
+    timeouts: Option<AITimeouts>,
+}
+
+/// Review a PR too large for one prompt: the backend fetches the diff, reviews it in
+/// chunks with process ids `{review_id}-chunk-{index}` and merges the results in a final
+/// summary run `{review_id}-summary`. Progress is reported per chunk on `on_progress`;
+/// cancelling any chunk stops the chunks that haven't started.
+#[tauri::command]
+async fn run_map_reduce_review(
+    provider: String,
+    request: ProviderRequest,
+    pr_number: u64,
+    system_prompt: String,
+    options: Option<MapReduceOptions>,
+    on_progress: Channel<MapReduceProgress>,
+    app: AppHandle,
+) -> Result<MapReduceReview, String> {
+    let options = options.unwrap_or_default();
+    let params = MapReduceParams {
+        provider,
+        request: ProviderRequest {
+            pr_number: Some(pr_number),
+            ..request
+        },
+        pr_number,
+        system_prompt,
+        chunk_tokens: options
+            .chunk_tokens
+            .unwrap_or(map_reduce::DEFAULT_CHUNK_TOKENS),
+        max_parallel: options
+            .max_parallel
+            .unwrap_or(map_reduce::DEFAULT_MAX_PARALLEL),
+        review_id: options
+            .review_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        priority: options.priority.unwrap_or(0),
+        timeouts: options.timeouts.unwrap_or_default(),
+    };
+    map_reduce::run(app, params, on_progress).await
+}
+
+/// Remember the head commit a finished review was run against, for incremental re-reviews
+#[tauri::command]
+fn record_ai_review(record: ReviewRecord, history: State<'_, ReviewHistory>) -> Result<(), String> {
+    history.add(record)
+}
+
+#[tauri::command]
+fn get_last_ai_review(
+    repository: String,
+    pr_number: u64,
+    history: State<'_, ReviewHistory>,
+) -> Result<Option<ReviewRecord>, String> {
+    history.latest(&repository, pr_number)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IncrementalOptions {
+    review_id: Option<String>,
+    priority: Option<i32>,
+    timeouts: Option<AITimeouts>,
+}
+
+/// Review only the commits pushed since the last recorded review of a PR, as one run with
+/// process id `review_id`, and mark that review's findings as resolved or still present.
+/// The result is recorded as the PR's latest review.
+#[tauri::command]
+async fn run_incremental_review(
+    provider: String,
+    request: ProviderRequest,
+    pr_number: u64,
+    system_prompt: String,
+    options: Option<IncrementalOptions>,
+    on_event: Channel<AIEventEnvelope>,
+    app: AppHandle,
+) -> Result<IncrementalReview, String> {
+    let options = options.unwrap_or_default();
+    let params = IncrementalParams {
+        provider,
+        request: ProviderRequest {
+            pr_number: Some(pr_number),
+            ..request
+        },
+        pr_number,
+        system_prompt,
+        review_id: options
+            .review_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        priority: options.priority.unwrap_or(0),
+        timeouts: options.timeouts.unwrap_or_default(),
+    };
+    incremental::run(app, params, on_event).await
+}
+
+/// Resolve, budget-check and queue a run whose events go to `subscribers`. The returned
+/// receiver yields the run's outcome; it errors if the run is cancelled before it starts.
+async fn launch_ai_run(
+    app: &AppHandle,
+    provider: String,
+    mut request: ProviderRequest,
+    process_id: String,
+    priority: i32,
+    timeouts: AITimeouts,
+    subscribers: Vec<Channel<AIEventEnvelope>>,
+) -> Result<oneshot::Receiver<JobOutcome>, String> {
+    // A second run under the same id would take over the first one's events and cancellation
+    if app.state::<AIProcessState>().jobs.is_active(&process_id) {
+        return Err(format!("AI run {} is already queued or running", process_id));
+    }
+
+    // Prompts that inline a diff must not carry its secrets to the provider
+    let (prompt, redacted) = secrets::redact(&request.prompt);
+    if redacted > 0 {
+        log::warn!("Redacted {} secret(s) from the prompt of {}", redacted, process_id);
+        request.prompt = prompt;
+    }
+
+    // Resolve the provider up front so configuration errors are returned immediately
+    let launch = if let Some(http_provider) = providers::get_http_provider(&provider) {
+        let http_request = http_provider.build_request(&reqwest::Client::new(), &request)?;
+        AILaunch::Http(http_provider, Box::new(http_request))
+    } else {
+        let cli_provider = providers::get_provider(
+            &provider,
+            &app.state::<CustomProviderState>().providers.lock().await.providers,
+        )
+        .ok_or_else(|| format!("Unknown AI provider: {}", provider))?;
+        // Agents only ever get the read-only gh proxy, never the real gh
+        let gh = app.state::<GhShim>().command()?;
+        request.gh_command = Some(gh.display().to_string());
+        AILaunch::Cli(cli_provider)
+    };
+
+    // Refused runs fail here, before they are queued; over-budget runs may be held instead
+    let usage = app.state::<UsageLog>();
+    let estimate = budgets::estimate(
+        &usage,
+        &provider,
+        request.model.as_deref(),
+        &request.prompt,
+    );
+    let blocked_by = app.state::<Budgets>().admit(
+        &usage,
+        &process_id,
+        request.repository.as_deref(),
+        estimate,
+    )?;
+
+    // Events are buffered from here on, so a frontend that attaches late misses nothing
+    app.state::<StreamBuffers>().open(&process_id, &provider, subscribers);
+    if let Some(reason) = &blocked_by {
+        emit_event(
+            app,
+            &process_id,
+            AIEvent::Budget {
+                decision: BudgetDecision {
+                    action: BudgetAction::Deferred,
+                    reason: reason.clone(),
+                },
+            },
+        );
+    }
+    let state = app.state::<AIProcessState>();
+    let jobs = state.jobs.clone();
+    let processes = state.processes.clone();
+    let start_rx = match jobs.enqueue(app, &process_id, &provider, priority, blocked_by) {
+        Ok(start_rx) => start_rx,
+        Err(e) => {
+            app.state::<Budgets>().release(&process_id);
+            return Err(e);
+        }
+    };
+
+    let (outcome_tx, outcome_rx) = oneshot::channel();
+    let app = app.clone();
+    let job_process_id = process_id;
+    tokio::spawn(async move {
+        // The sender is dropped if the job is cancelled while still queued
+        if start_rx.await.is_err() {
+            app.state::<Budgets>().release(&job_process_id);
+            return;
+        }
+        // Cancelled after getting a slot but before anything was spawned
+        if jobs.cancel_requested(&job_process_id) {
+            emit_event(
+                &app,
+                &job_process_id,
+                AIEvent::Cancelled {
+                    message: "Review cancelled before it started".to_string(),
+                },
+            );
+            jobs.finish(&app, &job_process_id, JobOutcome::Cancelled);
+            app.state::<Budgets>().release(&job_process_id);
+            let _ = outcome_tx.send(JobOutcome::Cancelled);
+            return;
+        }
+        app.state::<UsageLog>().begin(
+            &job_process_id,
+            &provider,
+            request.model.as_deref(),
+            request.repository.as_deref(),
+        );
+
+        let run = match launch {
+            AILaunch::Cli(provider) => {
+                spawn_cli_stream(
+                    provider,
+                    request,
+                    job_process_id.clone(),
+                    timeouts,
+                    app.clone(),
+                    processes.clone(),
+                )
+                .await
+            }
+            AILaunch::Http(provider, http_request) => Ok(spawn_http_stream(
+                provider,
+                *http_request,
+                job_process_id.clone(),
+                timeouts,
+                app.clone(),
+                processes.clone(),
+            )
+            .await),
+        };
+
+        let outcome = match run {
+            Ok(task) => {
+                // Cancelled while the run was being spawned, before it was in the process map
+                if jobs.cancel_requested(&job_process_id) {
+                    cancel_registered(&app, &mut *processes.lock().await, &job_process_id);
+                }
+                // An aborted run task means it was cancelled
+                task.await.unwrap_or(JobOutcome::Cancelled)
+            }
+            Err(e) => {
+                emit_event(&app, &job_process_id, AIEvent::Error { message: e });
+                JobOutcome::Failed
+            }
+        };
+        jobs.finish(&app, &job_process_id, outcome);
+        app.state::<GhShim>().revoke(&job_process_id);
+        app.state::<Transcripts>().end(&job_process_id, outcome);
+        app.state::<UsageLog>().end(&job_process_id, outcome);
+        // After `end`, so the run's finished record is counted before its reservation goes
+        app.state::<Budgets>().release(&job_process_id);
+        let _ = outcome_tx.send(outcome);
+    });
+
+    Ok(outcome_rx)
+}
+
+/// Spawn a CLI provider process and its reader tasks. The returned task resolves when the run ends.
+async fn spawn_cli_stream(
+    provider: Box<dyn AiProvider>,
+    mut request: ProviderRequest,
+    process_id: String,
+    timeouts: AITimeouts,
+    app: AppHandle,
+    processes: ProcessMap,
+) -> Result<tokio::task::JoinHandle<JobOutcome>, String> {
+    let command = provider.command().to_string();
+    let shim = app.state::<GhShim>();
+    let mut args = provider.build_args(&request);
+    // A prompt too long for one argument goes on stdin, for providers that can take it there
+    if !request.prompt_on_stdin && args.iter().any(|arg| arg.len() >= MAX_ARG_BYTES) {
+        request.prompt_on_stdin = true;
+        args = provider.build_args(&request);
+    }
+    if let Some(arg) = args.iter().find(|arg| arg.len() >= MAX_ARG_BYTES) {
+        return Err(format!(
+            "The prompt is too long to pass to {} as an argument ({} KB, the limit is {} KB); \
+             configure the provider to read it from stdin (promptOnStdin)",
+            command,
+            arg.len() / 1024,
+            MAX_ARG_BYTES / 1024
+        ));
+    }
+    let stdin_input = provider.stdin_input(&request);
+    log::info!("Starting AI stream {} with provider {}", process_id, provider.id());
+
+    app.state::<Transcripts>().begin(
+        &process_id,
+        provider.id(),
+        &command,
+        &args,
+        request.model.as_deref(),
+    );
 
     let mut cmd = TokioCommand::new(&command);
     cmd.args(&args)
+        .envs(provider.env(&request))
         .env("PATH", get_enhanced_path())
         .stdin(if stdin_input.is_some() { Stdio::piped() } else { Stdio::null() })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .kill_on_drop(true);
+    shim.configure(
+        &mut cmd,
+        &process_id,
+        &gh_shim::Scope {
+            repository: request.repository.clone(),
+            pr_number: request.pr_number,
+        },
+    );
 
     // On Unix, create a new process group so we can kill all descendants
     #[cfg(unix)]
@@ -252,8 +1013,16 @@ async fn start_ai_stream(
         });
     }
 
-    let mut child = cmd.spawn()
-        .map_err(|e| format!("Failed to spawn {}: {}", command, e))?;
+    let mut child = cmd.spawn().map_err(|e| {
+        let kind = provider.classify_error(None, &e.to_string());
+        format!("{} (failed to spawn {}: {})", kind.describe(&command), command, e)
+    })?;
+
+    // Record the process group so it is killed on quit, or reaped on the next launch after a crash
+    let groups = app.state::<AIProcessState>().groups.clone();
+    if let Some(pid) = child.id() {
+        groups.register(pid, &command);
+    }
 
     if let Some(input) = stdin_input {
         if let Some(mut stdin) = child.stdin.take() {
@@ -267,10 +1036,11 @@ async fn start_ai_stream(
     let stderr = child.stderr.take().ok_or("Failed to capture stderr")?;
 
     let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+    let clock = ActivityClock::new();
 
-    // Channels to signal when readers are done
-    let (stdout_done_tx, stdout_done_rx) = tokio::sync::oneshot::channel::<()>();
-    let (stderr_done_tx, stderr_done_rx) = tokio::sync::oneshot::channel::<()>();
+    // Channels to signal when readers are done, carrying any error output for classification
+    let (stdout_done_tx, stdout_done_rx) = tokio::sync::oneshot::channel::<Option<String>>();
+    let (stderr_done_tx, stderr_done_rx) = tokio::sync::oneshot::channel::<String>();
 
     // Collect abort handles to cancel tasks on cleanup
     let mut abort_handles = Vec::new();
@@ -278,125 +1048,67 @@ async fn start_ai_stream(
     // Stdout reader task
     let stdout_process_id = process_id.clone();
     let stdout_app = app.clone();
+    let mut parser = provider.output_parser();
+    let stdout_clock = clock.clone();
     let stdout_task = tokio::spawn(async move {
-        use tokio::io::AsyncReadExt;
-
-        // Small delay to ensure frontend event listeners are fully registered
-        // This prevents a race condition where events are emitted before listeners are ready
-        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-
-        let mut stdout_reader = stdout;
-        let mut buffer = Vec::new();
-
-        if let Ok(_) = stdout_reader.read_to_end(&mut buffer).await {
-            let output = String::from_utf8_lossy(&buffer).to_string();
-
-            if !output.trim().is_empty() {
-                // Try to parse as single JSON first (Claude format)
-                if let Ok(json_value) = serde_json::from_str::<Value>(&output) {
-                    // Handle --output-format json: extract "result" field
-                    if let Some(result_text) = json_value.get("result").and_then(|v| v.as_str()) {
-                        let _ = stdout_app.emit(
-                            "ai-content",
-                            AIContentEvent {
-                                process_id: stdout_process_id.clone(),
-                                event_type: "text_delta".to_string(),
-                                text: result_text.to_string(),
-                            },
-                        );
-                    } else if let Some(content) = json_value.get("content").and_then(|v| v.as_array()) {
-                        // Handle content array format
-                        for item in content {
-                            if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                                let _ = stdout_app.emit(
-                                    "ai-content",
-                                    AIContentEvent {
-                                        process_id: stdout_process_id.clone(),
-                                        event_type: "text_delta".to_string(),
-                                        text: text.to_string(),
-                                    },
-                                );
-                            }
-                        }
-                    } else {
-                        // Unknown JSON structure, emit raw
-                        let _ = stdout_app.emit(
-                            "ai-content",
-                            AIContentEvent {
-                                process_id: stdout_process_id.clone(),
-                                event_type: "text_delta".to_string(),
-                                text: output,
-                            },
-                        );
-                    }
-                } else {
-                    // Try parsing as JSONL (Codex format: one JSON per line)
-                    let mut extracted_text = String::new();
-                    for line in output.lines() {
-                        if let Ok(json_value) = serde_json::from_str::<Value>(line) {
-                            // Codex format: {"type":"item.completed","item":{"type":"agent_message","text":"..."}}
-                            if let Some(item) = json_value.get("item") {
-                                if item.get("type").and_then(|v| v.as_str()) == Some("agent_message") {
-                                    if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                                        extracted_text.push_str(text);
-                                        extracted_text.push('\n');
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    if !extracted_text.is_empty() {
-                        let _ = stdout_app.emit(
-                            "ai-content",
-                            AIContentEvent {
-                                process_id: stdout_process_id.clone(),
-                                event_type: "text_delta".to_string(),
-                                text: extracted_text.trim().to_string(),
-                            },
-                        );
-                    } else {
-                        // Fallback: emit raw output
-                        let _ = stdout_app.emit(
-                            "ai-content",
-                            AIContentEvent {
-                                process_id: stdout_process_id.clone(),
-                                event_type: "text_delta".to_string(),
-                                text: output,
-                            },
-                        );
+        let transcripts = stdout_app.state::<Transcripts>();
+        let mut reader = BufReader::new(stdout);
+        let mut line = Vec::new();
+
+        // Parse each line as it arrives so thinking and text stream to the UI live
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    stdout_clock.touch();
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.trim_end_matches(['\r', '\n']);
+                    transcripts.record(
+                        &stdout_process_id,
+                        TranscriptRecord::Stdout {
+                            line: text.to_string(),
+                        },
+                    );
+                    for event in parser.parse_line(text) {
+                        emit_event(&stdout_app, &stdout_process_id, event);
                     }
                 }
             }
         }
-        let _ = stdout_done_tx.send(());
+        for event in parser.finish() {
+            emit_event(&stdout_app, &stdout_process_id, event);
+        }
+        let _ = stdout_done_tx.send(parser.error_output());
     });
     abort_handles.push(stdout_task.abort_handle());
 
     // Stderr reader task
     let stderr_process_id = process_id.clone();
     let stderr_app = app.clone();
+    let stderr_clock = clock.clone();
     let stderr_task = tokio::spawn(async move {
-        // Small delay to ensure frontend event listeners are fully registered
-        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-
         let mut reader = BufReader::new(stderr).lines();
+        let mut stderr_tail = String::new();
         while let Ok(Some(line)) = reader.next_line().await {
-            let _ = stderr_app.emit(
-                "ai-stream",
-                AIStreamEvent {
-                    process_id: stderr_process_id.clone(),
-                    event_type: "stderr".to_string(),
-                    data: line,
-                },
-            );
+            stderr_clock.touch();
+            // Keep the last few KB for error classification
+            stderr_tail.push_str(&line);
+            stderr_tail.push('\n');
+            if stderr_tail.len() > 8192 {
+                let cut = stderr_tail.len() - 4096;
+                let cut = (cut..stderr_tail.len())
+                    .find(|i| stderr_tail.is_char_boundary(*i))
+                    .unwrap_or(cut);
+                stderr_tail.drain(..cut);
+            }
+            emit_event(&stderr_app, &stderr_process_id, AIEvent::Stderr { line });
         }
-        let _ = stderr_done_tx.send(());
+        let _ = stderr_done_tx.send(stderr_tail);
     });
     abort_handles.push(stderr_task.abort_handle());
 
     // Store process handle in state
-    let processes = state.processes.clone();
     {
         let mut map = processes.lock().await;
         map.insert(process_id.clone(), ProcessHandle {
@@ -417,26 +1129,21 @@ async fn start_ai_stream(
         let exit_status = tokio::select! {
             status = child.wait() => Some(status),
             _ = cancel_rx => {
-                // Kill the entire process group on Unix
-                #[cfg(unix)]
+                kill_process_tree(&mut child, child_pid).await;
+                None
+            }
+            reason = watchdog(timeouts, clock) => {
+                log::warn!("AI stream {} timed out: {}", complete_process_id, reason);
+                kill_process_tree(&mut child, child_pid).await;
                 if let Some(pid) = child_pid {
-                    // Kill the process group (negative PID)
-                    unsafe {
-                        libc::kill(-(pid as i32), libc::SIGTERM);
-                    }
-                    // Give it a moment to terminate gracefully
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    // Force kill if still running
-                    unsafe {
-                        libc::kill(-(pid as i32), libc::SIGKILL);
-                    }
+                    groups.unregister(pid);
                 }
-                #[cfg(not(unix))]
                 {
-                    let _ = child.kill().await;
+                    let mut map = processes_for_cleanup.lock().await;
+                    map.remove(&complete_process_id);
                 }
-                let _ = child.wait().await;
-                None
+                emit_event(&complete_app, &complete_process_id, AIEvent::Timeout { reason });
+                return JobOutcome::TimedOut;
             }
         };
 
@@ -452,17 +1159,26 @@ async fn start_ai_stream(
                 }
                 status
             }
-            None => return,
+            None => {
+                if let Some(pid) = child_pid {
+                    groups.unregister(pid);
+                }
+                return JobOutcome::Cancelled;
+            }
         };
+        if let Some(pid) = child_pid {
+            groups.unregister(pid);
+        }
 
         // Wait for stdout and stderr readers to finish (with timeout)
-        let _ = tokio::time::timeout(
+        let error_output = tokio::time::timeout(
             tokio::time::Duration::from_secs(5),
             async {
-                let _ = stdout_done_rx.await;
-                let _ = stderr_done_rx.await;
+                let stdout_errors = stdout_done_rx.await.ok().flatten().unwrap_or_default();
+                let stderr_tail = stderr_done_rx.await.unwrap_or_default();
+                format!("{}\n{}", stdout_errors, stderr_tail)
             }
-        ).await;
+        ).await.unwrap_or_default();
 
         // Remove from process map
         {
@@ -473,35 +1189,192 @@ async fn start_ai_stream(
         // Emit completion event
         match exit_status {
             Ok(status) => {
+                complete_app
+                    .state::<Transcripts>()
+                    .record(&complete_process_id, TranscriptRecord::Exit { code: status.code() });
                 let exit_code = status.code().unwrap_or(-1);
-                let event_type = if status.success() { "complete" } else { "error" };
-                let _ = complete_app.emit(
-                    "ai-stream",
-                    AIStreamEvent {
-                        process_id: complete_process_id.clone(),
-                        event_type: event_type.to_string(),
-                        data: format!("Process exited with code {}", exit_code),
-                    },
-                );
+                let (event, outcome) = if status.success() {
+                    (
+                        AIEvent::Complete {
+                            message: format!("Process exited with code {}", exit_code),
+                        },
+                        JobOutcome::Completed,
+                    )
+                } else {
+                    let kind = provider.classify_error(status.code(), &error_output);
+                    (
+                        AIEvent::Error {
+                            message: format!(
+                                "{}: Process exited with code {}",
+                                kind.describe(provider.command()),
+                                exit_code
+                            ),
+                        },
+                        JobOutcome::Failed,
+                    )
+                };
+                emit_event(&complete_app, &complete_process_id, event);
+                outcome
             }
             Err(e) => {
-                let _ = complete_app.emit(
-                    "ai-stream",
-                    AIStreamEvent {
-                        process_id: complete_process_id.clone(),
-                        event_type: "error".to_string(),
-                        data: format!("Error waiting for process: {}", e),
+                emit_event(
+                    &complete_app,
+                    &complete_process_id,
+                    AIEvent::Error {
+                        message: format!("Error waiting for process: {}", e),
                     },
                 );
+                JobOutcome::Failed
+            }
+        }
+    });
+
+    // The completion task isn't in the abort handles since it has to run to completion
+    // to reap the process; the job waits on it to free the slot
+    Ok(completion_task)
+}
+
+/// Stream a completion from an HTTP provider, emitting the same events as a CLI process.
+/// The request task is registered in the process map so `cancel_ai_stream` can abort it.
+async fn spawn_http_stream(
+    provider: Box<dyn HttpProvider>,
+    http_request: reqwest::RequestBuilder,
+    process_id: String,
+    timeouts: AITimeouts,
+    app: AppHandle,
+    processes: ProcessMap,
+) -> tokio::task::JoinHandle<JobOutcome> {
+    log::info!("Starting AI stream {} with provider {}", process_id, provider.id());
+
+    // The transcript records the endpoint and body; headers are left out since they hold the API key
+    if let Some(built) = http_request.try_clone().and_then(|builder| builder.build().ok()) {
+        let body = built
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+            .unwrap_or_default();
+        let model = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body.get("model").and_then(|v| v.as_str()).map(str::to_string));
+        app.state::<Transcripts>().begin(
+            &process_id,
+            provider.id(),
+            &format!("{} {}", built.method(), built.url()),
+            &[body],
+            model.as_deref(),
+        );
+    }
+
+    // Hold the map lock until the handle is inserted so the task can't finish and
+    // remove itself before it's registered
+    let mut map = processes.lock().await;
+
+    let task_process_id = process_id.clone();
+    let task_processes = processes.clone();
+    let task = tokio::spawn(async move {
+        let mut parser = provider.output_parser();
+        let clock = ActivityClock::new();
+        let stream_clock = clock.clone();
+
+        let streamed = async {
+            let mut response = http_request
+                .send()
+                .await
+                .map_err(|e| {
+                    let reason = if e.is_connect() { "Failed to connect" } else { "Request failed" };
+                    (None, format!("{}: {}", reason, e))
+                })?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err((Some(status.as_u16()), format!("HTTP {}: {}", status, body)));
+            }
+
+            // Server-sent events arrive in arbitrary chunks; split them into lines
+            let mut pending: Vec<u8> = Vec::new();
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| (None, format!("Stream interrupted: {}", e)))?
+            {
+                stream_clock.touch();
+                pending.extend_from_slice(&chunk);
+                while let Some(newline) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=newline).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches(['\r', '\n']);
+                    app.state::<Transcripts>().record(
+                        &task_process_id,
+                        TranscriptRecord::Stdout {
+                            line: line.to_string(),
+                        },
+                    );
+                    for event in parser.parse_line(line) {
+                        emit_event(&app, &task_process_id, event);
+                    }
+                }
             }
+            if !pending.is_empty() {
+                for event in parser.parse_line(&String::from_utf8_lossy(&pending)) {
+                    emit_event(&app, &task_process_id, event);
+                }
+            }
+            Ok::<(), (Option<u16>, String)>(())
+        };
+        let (result, timed_out) = tokio::select! {
+            result = streamed => (result, None),
+            reason = watchdog(timeouts, clock) => (Ok(()), Some(reason)),
+        };
+
+        for event in parser.finish() {
+            emit_event(&app, &task_process_id, event);
+        }
+
+        // Errors can also arrive inside a successful stream
+        let result = match (result, parser.error_output()) {
+            (Ok(()), Some(message)) => Err((None, message)),
+            (result, _) => result,
+        };
+
+        {
+            let mut map = task_processes.lock().await;
+            map.remove(&task_process_id);
         }
+
+        if let Some(reason) = timed_out {
+            log::warn!("AI stream {} timed out: {}", task_process_id, reason);
+            emit_event(&app, &task_process_id, AIEvent::Timeout { reason });
+            return JobOutcome::TimedOut;
+        }
+
+        let (event, outcome) = match result {
+            Ok(()) => (
+                AIEvent::Complete {
+                    message: "Stream finished".to_string(),
+                },
+                JobOutcome::Completed,
+            ),
+            Err((status, message)) => {
+                let kind = provider.classify_error(status, &message);
+                (
+                    AIEvent::Error {
+                        message: format!("{}: {}", kind.describe_http(provider.id()), message),
+                    },
+                    JobOutcome::Failed,
+                )
+            }
+        };
+        emit_event(&app, &task_process_id, event);
+        outcome
     });
 
-    // We don't store the completion task abort handle since we want it to run to completion
-    // But we could add a timeout if needed
-    drop(completion_task);
+    map.insert(process_id, ProcessHandle {
+        abort_handles: vec![task.abort_handle()],
+        cancel_tx: None,
+    });
 
-    Ok(process_id_clone)
+    task
 }
 
 #[tauri::command]
@@ -510,33 +1383,186 @@ async fn cancel_ai_stream(
     app: AppHandle,
     state: State<'_, AIProcessState>,
 ) -> Result<(), String> {
+    // A job that hasn't started yet only needs to leave the queue
+    if state.jobs.cancel_queued(&app, &process_id) {
+        emit_event(
+            &app,
+            &process_id,
+            AIEvent::Cancelled {
+                message: "Review cancelled before it started".to_string(),
+            },
+        );
+        return Ok(());
+    }
+
     let mut processes = state.processes.lock().await;
+    if !cancel_registered(&app, &mut processes, &process_id) {
+        // A job that has its slot but hasn't registered its process yet is cancelled once it
+        // does. Holding the map lock keeps this from racing with the registration. If the
+        // job isn't running either, it has already completed, which is okay.
+        state.jobs.request_cancel(&process_id);
+    }
+    Ok(())
+}
 
-    if let Some(handle) = processes.remove(&process_id) {
-        // Abort all associated tasks
-        for abort_handle in handle.abort_handles {
-            abort_handle.abort();
-        }
+/// Abort a run in the process map: its tasks and, for CLI runs, the process itself.
+/// Returns false if the run isn't registered.
+fn cancel_registered(
+    app: &AppHandle,
+    processes: &mut HashMap<String, ProcessHandle>,
+    process_id: &str,
+) -> bool {
+    let handle = match processes.remove(process_id) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    // Abort all associated tasks
+    for abort_handle in handle.abort_handles {
+        abort_handle.abort();
+    }
 
-        // Signal completion task to terminate the process
-        if let Some(cancel_tx) = handle.cancel_tx {
-            let _ = cancel_tx.send(());
-        }
+    // Signal completion task to terminate the process
+    if let Some(cancel_tx) = handle.cancel_tx {
+        let _ = cancel_tx.send(());
+    }
 
-        let _ = app.emit(
-            "ai-stream",
-            AIStreamEvent {
-                process_id: process_id.clone(),
-                event_type: "cancelled".to_string(),
-                data: "Process cancelled by user".to_string(),
-            },
-        );
+    emit_event(
+        app,
+        process_id,
+        AIEvent::Cancelled {
+            message: "Process cancelled by user".to_string(),
+        },
+    );
+    true
+}
 
-        Ok(())
-    } else {
-        // Process might have already completed, that's okay
-        Ok(())
+#[tauri::command]
+async fn list_ai_streams(streams: State<'_, StreamBuffers>) -> Result<Vec<StreamInfo>, String> {
+    Ok(streams.list())
+}
+
+/// Replay a run's buffered events over `on_event`, then keep sending live ones to it
+#[tauri::command]
+async fn attach_ai_stream(
+    process_id: String,
+    on_event: Channel<AIEventEnvelope>,
+    streams: State<'_, StreamBuffers>,
+) -> Result<AttachResult, String> {
+    streams.attach(&process_id, on_event)
+}
+
+#[tauri::command]
+async fn list_ai_transcripts(
+    transcripts: State<'_, Transcripts>,
+) -> Result<Vec<TranscriptInfo>, String> {
+    transcripts.list()
+}
+
+/// Re-send a transcript's events over `on_event`. `speed` scales the original timing
+/// (2.0 replays twice as fast); 0 sends everything at once. Defaults to real time.
+#[tauri::command]
+async fn replay_ai_transcript(
+    transcript_id: String,
+    speed: Option<f64>,
+    on_event: Channel<AIEventEnvelope>,
+    transcripts: State<'_, Transcripts>,
+) -> Result<(), String> {
+    let speed = speed.unwrap_or(1.0);
+    if !speed.is_finite() || speed < 0.0 {
+        return Err(format!("Invalid replay speed: {}", speed));
     }
+    let lines = transcripts.read(&transcript_id)?;
+    tokio::spawn(transcripts::replay(transcript_id, lines, speed, on_event));
+    Ok(())
+}
+
+/// Extract the structured review from a run's full output, repairing malformed JSON
+#[tauri::command]
+fn parse_ai_review(output: String) -> ParsedReview {
+    review_result::parse_review(&output)
+}
+
+/// Check AI comments against the PR's parsed diff before posting: comments are moved onto
+/// the nearest matching diff line where they narrowly miss one, and the rest are collected
+/// into the body of a general PR comment
+#[tauri::command]
+fn anchor_review_comments(
+    files: Vec<DiffFileInput>,
+    comments: Vec<AnchorComment>,
+) -> AnchorResult {
+    let files: Vec<diff::FileDiff> = files.into_iter().map(Into::into).collect();
+    anchoring::anchor_comments(&files, comments)
+}
+
+/// Post comments as one pending review, created in a single API call and deleted again if
+/// it can't be verified or submitted
+#[tauri::command]
+async fn post_review_batch(request: BatchReviewRequest) -> Result<BatchReviewResult, String> {
+    review_batch::post(request).await
+}
+
+/// `gh` commands AI agents tried to run that the read-only proxy refused, oldest first,
+/// optionally only those of one run
+#[tauri::command]
+fn list_blocked_gh_commands(
+    process_id: Option<String>,
+    shim: State<'_, GhShim>,
+) -> Result<Vec<BlockedCommand>, String> {
+    shim.blocked(process_id.as_deref())
+}
+
+/// Token usage and cost of finished runs started within `[since, until)` (milliseconds
+/// since the Unix epoch), broken down by provider, model, repository and UTC day
+#[tauri::command]
+async fn get_ai_usage_summary(
+    since: Option<u64>,
+    until: Option<u64>,
+    usage: State<'_, UsageLog>,
+) -> Result<UsageSummary, String> {
+    usage.summary(since, until)
+}
+
+#[tauri::command]
+async fn get_ai_budgets(budgets: State<'_, Budgets>) -> Result<BudgetConfig, String> {
+    Ok(budgets.config())
+}
+
+/// Replace the budget config; held runs the new limits allow are released
+#[tauri::command]
+async fn set_ai_budgets(
+    config: BudgetConfig,
+    app: AppHandle,
+    budgets: State<'_, Budgets>,
+    usage: State<'_, UsageLog>,
+    state: State<'_, AIProcessState>,
+) -> Result<(), String> {
+    budgets.set_config(config)?;
+    budgets.recheck(&app, &usage, &state.jobs);
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_ai_jobs(state: State<'_, AIProcessState>) -> Result<Vec<JobInfo>, String> {
+    Ok(state.jobs.list())
+}
+
+#[tauri::command]
+async fn reprioritize_ai_job(
+    job_id: String,
+    priority: i32,
+    app: AppHandle,
+    state: State<'_, AIProcessState>,
+) -> Result<JobInfo, String> {
+    state.jobs.reprioritize(&app, &job_id, priority)
+}
+
+#[tauri::command]
+async fn set_ai_job_concurrency(
+    max_concurrency: usize,
+    app: AppHandle,
+    state: State<'_, AIProcessState>,
+) -> Result<(), String> {
+    state.jobs.set_max_concurrency(&app, max_concurrency)
 }
 
 #[tauri::command]
@@ -839,12 +1865,48 @@ pub fn run() {
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_process::init())
         .manage(AIProcessState::default())
+        .manage(CustomProviderState::default())
+        .manage(StreamBuffers::default())
+        .manage(Transcripts::default())
+        .manage(UsageLog::default())
+        .manage(Budgets::default())
+        .manage(ReviewHistory::default())
+        .manage(GhShim::default())
+        .manage(ReviewRules::default())
         .invoke_handler(tauri::generate_handler![
             run_gh_command,
             run_gh_command_with_input,
             run_shell_command,
+            list_ai_providers,
+            reload_ai_providers,
+            check_ai_provider,
             start_ai_stream,
             cancel_ai_stream,
+            list_ai_streams,
+            attach_ai_stream,
+            list_ai_transcripts,
+            replay_ai_transcript,
+            run_ensemble_review,
+            run_map_reduce_review,
+            record_ai_review,
+            get_last_ai_review,
+            run_incremental_review,
+            parse_ai_review,
+            anchor_review_comments,
+            post_review_batch,
+            list_blocked_gh_commands,
+            build_review_context,
+            scan_pr_secrets,
+            get_review_rules,
+            set_review_rules,
+            run_review_rules,
+            get_review_policy,
+            get_ai_usage_summary,
+            get_ai_budgets,
+            set_ai_budgets,
+            list_ai_jobs,
+            reprioritize_ai_job,
+            set_ai_job_concurrency,
             set_tray_badge,
             update_tray_menu,
             check_update_preflight
@@ -872,6 +1934,53 @@ pub fn run() {
             setup_tray(app)?;
             setup_app_menu(app)?;
 
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    app.state::<AIProcessState>()
+                        .groups
+                        .init(dir.join("ai-process-groups.json"));
+                    app.state::<Transcripts>().init(dir.join("transcripts"));
+                    app.state::<UsageLog>().init(dir.join("ai-usage.jsonl"));
+                    app.state::<Budgets>().init(dir.join("ai-budgets.json"));
+                    app.state::<ReviewHistory>().init(dir.join("ai-reviews.jsonl"));
+                    app.state::<ReviewRules>().init(dir.join("review-rules.json"));
+                    // The script goes in the cache dir, whose path has no spaces on macOS,
+                    // so agents can be allowed to run it by its full path
+                    let shim_dir = app
+                        .path()
+                        .app_cache_dir()
+                        .unwrap_or_else(|_| dir.clone())
+                        .join("gh-shim");
+                    if let Err(e) = app
+                        .state::<GhShim>()
+                        .init(shim_dir, dir.join("gh-shim-blocked.jsonl"))
+                    {
+                        log::warn!("Failed to install the gh proxy for AI agents: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to resolve app data dir: {}", e),
+            }
+
+            // Runs held over budget are released once the daily spend resets
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    let jobs = handle.state::<AIProcessState>().jobs.clone();
+                    handle
+                        .state::<Budgets>()
+                        .recheck(&handle, &handle.state::<UsageLog>(), &jobs);
+                }
+            });
+
+            match load_custom_providers(app.handle()) {
+                Ok(set) => {
+                    let state = app.state::<CustomProviderState>();
+                    *state.providers.blocking_lock() = set;
+                }
+                Err(e) => log::warn!("Failed to load custom providers: {}", e),
+            }
+
             #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
             {
                 use tauri_plugin_deep_link::DeepLinkExt;
@@ -880,6 +1989,12 @@ pub fn run() {
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Covers the tray and menu "Quit Lyon" items as well as closing the last window
+            if let tauri::RunEvent::Exit = event {
+                app.state::<AIProcessState>().groups.kill_all();
+            }
+        });
 }
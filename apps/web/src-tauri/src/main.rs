@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.first().map(String::as_str) == Some(app_lib::GH_SHIM_FLAG) {
+    std::process::exit(app_lib::run_gh_shim(args[1..].to_vec()));
+  }
   app_lib::run();
 }
@@ -0,0 +1,588 @@
+//! Map-reduce reviews for PRs whose diff doesn't fit one prompt. The diff is split into
+//! chunks of whole files (or hunks of a very large file) under a token budget, each chunk is
+//! reviewed as its own queued run with the diff inlined, and a final run writes the summary.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::ipc::Channel;
+use tauri::AppHandle;
+use tokio::sync::Semaphore;
+
+use crate::budgets::estimate_tokens;
+use crate::diff::{parse_unified_diff, FileDiff, Side};
+use crate::jobs::JobOutcome;
+use crate::providers::ProviderRequest;
+use crate::review_batch::fence_for;
+use crate::review_result::{parse_review, ReviewComment, ReviewSuggestion};
+use crate::streams::OutputCollector;
+use crate::{launch_ai_run, run_gh_command, AITimeouts};
+
+pub const DEFAULT_CHUNK_TOKENS: u64 = 24_000;
+pub const DEFAULT_MAX_PARALLEL: usize = 3;
+/// PR descriptions longer than this are cut in chunk prompts
+const MAX_DESCRIPTION_CHARS: usize = 2_000;
+
+pub const REVIEW_FORMAT: &str = r#"Respond with ONLY a valid JSON object (no markdown, no code blocks, no extra text):
+
+{
+  "summary": "Brief summary of the changes and your overall assessment",
+  "overallScore": 8,
+  "comments": [
+    {
+      "path": "path/to/file.ts",
+      "line": 42,
+      "severity": "critical|warning|info|suggestion",
+      "category": "security|performance|best-practices|code-style|documentation|testing|architecture",
+      "body": "Your comment explaining the issue",
+      "suggestion": "Optional: code fix suggestion"
+    }
+  ],
+  "suggestions": []
+}
+
+If there are no issues, use an empty comments array. Start your response with { and end with }"#;
+
+/// A slice of the PR diff reviewed by one run
+pub struct Chunk {
+    /// Paths covered; a path appears with a part suffix when a large file was split
+    pub files: Vec<String>,
+    pub diff: String,
+    pub estimated_tokens: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkInfo {
+    pub index: usize,
+    pub files: Vec<String>,
+    pub estimated_tokens: u64,
+}
+
+/// Sent over the progress channel of `run_map_reduce_review`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MapReduceProgress {
+    Planned {
+        chunks: Vec<ChunkInfo>,
+    },
+    ChunkStarted {
+        index: usize,
+        process_id: String,
+    },
+    ChunkFinished {
+        index: usize,
+        outcome: Option<JobOutcome>,
+        comment_count: usize,
+        error: Option<String>,
+    },
+    Summarizing {
+        process_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkResult {
+    pub index: usize,
+    pub files: Vec<String>,
+    pub process_id: String,
+    /// `None` if the chunk never ran
+    pub outcome: Option<JobOutcome>,
+    pub error: Option<String>,
+    pub summary: Option<String>,
+    pub comment_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapReduceReview {
+    pub summary: String,
+    pub overall_score: Option<f64>,
+    pub comments: Vec<ReviewComment>,
+    pub suggestions: Vec<ReviewSuggestion>,
+    pub chunks: Vec<ChunkResult>,
+    pub warnings: Vec<String>,
+}
+
+pub struct MapReduceParams {
+    pub provider: String,
+    /// Model, reasoning effort and repository; the prompt is built per chunk
+    pub request: ProviderRequest,
+    pub pr_number: u64,
+    pub system_prompt: String,
+    pub chunk_tokens: u64,
+    pub max_parallel: usize,
+    pub review_id: String,
+    pub priority: i32,
+    pub timeouts: AITimeouts,
+}
+
+/// Group files into chunks under `budget` tokens, keeping diff order so neighbouring files
+/// stay together. A file over the budget on its own is split between hunks.
+pub fn plan_chunks(files: &[FileDiff], budget: u64) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current = Chunk {
+        files: Vec::new(),
+        diff: String::new(),
+        estimated_tokens: 0,
+    };
+    let flush = |current: &mut Chunk, chunks: &mut Vec<Chunk>| {
+        if !current.files.is_empty() {
+            chunks.push(std::mem::replace(
+                current,
+                Chunk {
+                    files: Vec::new(),
+                    diff: String::new(),
+                    estimated_tokens: 0,
+                },
+            ));
+        }
+    };
+
+    for file in files {
+        let diff = file.render();
+        let tokens = estimate_tokens(&diff);
+
+        if tokens > budget && file.hunks.len() > 1 {
+            flush(&mut current, &mut chunks);
+            let parts = split_hunks(file, budget);
+            let count = parts.len();
+            for (i, diff) in parts.into_iter().enumerate() {
+                chunks.push(Chunk {
+                    files: vec![format!("{} (part {}/{})", file.path, i + 1, count)],
+                    estimated_tokens: estimate_tokens(&diff),
+                    diff,
+                });
+            }
+            continue;
+        }
+
+        if current.estimated_tokens + tokens > budget {
+            flush(&mut current, &mut chunks);
+        }
+        current.files.push(file.path.clone());
+        current.diff.push_str(&diff);
+        current.estimated_tokens += tokens;
+    }
+    flush(&mut current, &mut chunks);
+    chunks
+}
+
+/// A file's diff as several pieces, each with the file header and as many whole hunks as fit
+fn split_hunks(file: &FileDiff, budget: u64) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (i, hunk) in file.hunks.iter().enumerate() {
+        let hunk_tokens = estimate_tokens(&hunk.render());
+        if i > start && tokens + hunk_tokens > budget {
+            parts.push(file.render_hunks(&file.hunks[start..i]));
+            start = i;
+            tokens = 0;
+        }
+        tokens += hunk_tokens;
+    }
+    parts.push(file.render_hunks(&file.hunks[start..]));
+    parts
+}
+
+struct PrContext {
+    number: u64,
+    repository: String,
+    title: String,
+    description: String,
+}
+
+fn chunk_prompt(
+    system_prompt: &str,
+    pr: &PrContext,
+    index: usize,
+    total: usize,
+    chunk: &Chunk,
+) -> String {
+    let mut description = pr.description.trim().to_string();
+    if description.len() > MAX_DESCRIPTION_CHARS {
+        let cut = (0..=MAX_DESCRIPTION_CHARS)
+            .rev()
+            .find(|&i| description.is_char_boundary(i))
+            .unwrap_or(0);
+        description.truncate(cut);
+        description.push_str("...");
+    }
+    let files = chunk
+        .files
+        .iter()
+        .map(|file| format!("- {}", file))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "{system_prompt}
+
+Review part {part} of {total} of Pull Request #{number} in repository {repository}.
+Title: {title}
+Description:
+{description}
+
+This part covers the files below. The rest of the PR is reviewed separately, so only comment on these files. The diff is included here; do not fetch it yourself.
+{files}
+
+{fence}diff
+{diff}{fence}
+
+{format}",
+        part = index + 1,
+        number = pr.number,
+        repository = pr.repository,
+        title = pr.title,
+        diff = chunk.diff,
+        fence = fence_for(&chunk.diff),
+        format = REVIEW_FORMAT,
+    )
+}
+
+fn summary_prompt(pr: &PrContext, chunks: &[ChunkResult], comments: &[ReviewComment]) -> String {
+    let summaries = chunks
+        .iter()
+        .filter_map(|chunk| {
+            let summary = chunk.summary.as_deref()?;
+            Some(format!(
+                "Part {} ({}):\n{}",
+                chunk.index + 1,
+                chunk.files.join(", "),
+                summary
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let findings = comments
+        .iter()
+        .map(|comment| {
+            let first_line = comment.body.lines().next().unwrap_or_default();
+            format!(
+                "- [{}] {}:{} {}",
+                comment.severity, comment.path, comment.line, first_line
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "A review of Pull Request #{number} in repository {repository} (\"{title}\") was split into {total} parts. Combine the results below into one assessment of the whole PR.
+
+Part summaries:
+{summaries}
+
+Findings:
+{findings}
+
+Respond with ONLY a valid JSON object (no markdown, no code blocks, no extra text):
+
+{{
+  \"summary\": \"Summary of the changes and your overall assessment of the whole PR\",
+  \"overallScore\": 8
+}}",
+        number = pr.number,
+        repository = pr.repository,
+        title = pr.title,
+        total = chunks.len(),
+        findings = if findings.is_empty() { "None".to_string() } else { findings },
+    )
+}
+
+async fn fetch_pr(number: u64, repository: &str) -> Result<(PrContext, String), String> {
+    let number_arg = number.to_string();
+    let diff = run_gh_command(
+        ["pr", "diff", &number_arg, "--repo", repository]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+    )
+    .await?;
+
+    // Title and description only add context; a failure here doesn't stop the review
+    let view = run_gh_command(
+        [
+            "pr",
+            "view",
+            &number_arg,
+            "--repo",
+            repository,
+            "--json",
+            "title,body",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    )
+    .await
+    .ok()
+    .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok());
+    let field = |key: &str| {
+        view.as_ref()
+            .and_then(|v| v.get(key))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    Ok((
+        PrContext {
+            number,
+            repository: repository.to_string(),
+            title: field("title"),
+            description: field("body"),
+        },
+        diff,
+    ))
+}
+
+/// Launch one run, collecting its output rather than streaming it, and wait for it. Returns
+/// its outcome, the error it ended with and its text output.
+async fn run_to_completion(
+    app: &AppHandle,
+    params: &MapReduceParams,
+    process_id: &str,
+    prompt: String,
+) -> (Option<JobOutcome>, Option<String>, Option<String>) {
+    let request = ProviderRequest {
+        prompt,
+        ..params.request.clone()
+    };
+    let collector = OutputCollector::default();
+    let outcome_rx = match launch_ai_run(
+        app,
+        params.provider.clone(),
+        request,
+        process_id.to_string(),
+        params.priority,
+        params.timeouts,
+        vec![collector.channel()],
+    )
+    .await
+    {
+        Ok(outcome_rx) => outcome_rx,
+        Err(e) => return (None, Some(e), None),
+    };
+
+    let outcome = outcome_rx.await.unwrap_or(JobOutcome::Cancelled);
+    let output = collector.take();
+    (Some(outcome), output.error(), Some(output.text))
+}
+
+pub async fn run(
+    app: AppHandle,
+    params: MapReduceParams,
+    progress: Channel<MapReduceProgress>,
+) -> Result<MapReduceReview, String> {
+    let repository = params
+        .request
+        .repository
+        .clone()
+        .ok_or("A repository is required for a map-reduce review")?;
+    let (pr, diff) = fetch_pr(params.pr_number, &repository).await?;
+
+    let files = parse_unified_diff(&diff);
+    if files.is_empty() {
+        return Err("The PR has no changes to review".to_string());
+    }
+    let chunks = plan_chunks(&files, params.chunk_tokens.max(1));
+    let total = chunks.len();
+    let _ = progress.send(MapReduceProgress::Planned {
+        chunks: chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| ChunkInfo {
+                index,
+                files: chunk.files.clone(),
+                estimated_tokens: chunk.estimated_tokens,
+            })
+            .collect(),
+    });
+
+    let params = Arc::new(params);
+    let pr = Arc::new(pr);
+    let permits = Arc::new(Semaphore::new(params.max_parallel.max(1)));
+    // Set when a chunk is cancelled, so chunks still waiting for a permit don't start
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let mut tasks = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let (app, params, pr, permits, stopped, progress) = (
+            app.clone(),
+            params.clone(),
+            pr.clone(),
+            permits.clone(),
+            stopped.clone(),
+            progress.clone(),
+        );
+        tasks.push(tokio::spawn(async move {
+            let process_id = format!("{}-chunk-{}", params.review_id, index);
+            let mut result = ChunkResult {
+                index,
+                files: chunk.files.clone(),
+                process_id: process_id.clone(),
+                outcome: None,
+                error: None,
+                summary: None,
+                comment_count: 0,
+            };
+
+            let _permit = permits.acquire().await;
+            if stopped.load(Ordering::SeqCst) {
+                result.error = Some("Review cancelled".to_string());
+                return (result, None);
+            }
+
+            let prompt = chunk_prompt(&params.system_prompt, &pr, index, total, &chunk);
+            let _ = progress.send(MapReduceProgress::ChunkStarted {
+                index,
+                process_id: process_id.clone(),
+            });
+            let (outcome, error, output) =
+                run_to_completion(&app, &params, &process_id, prompt).await;
+            if outcome == Some(JobOutcome::Cancelled) {
+                stopped.store(true, Ordering::SeqCst);
+            }
+
+            let parsed = match (outcome, output) {
+                (Some(JobOutcome::Completed), Some(output)) => Some(parse_review(&output)),
+                _ => None,
+            };
+            result.outcome = outcome;
+            result.error = error;
+            if let Some(parsed) = &parsed {
+                result.summary = parsed.summary.clone();
+                result.comment_count = parsed.comments.len();
+            }
+            let _ = progress.send(MapReduceProgress::ChunkFinished {
+                index,
+                outcome,
+                comment_count: result.comment_count,
+                error: result.error.clone(),
+            });
+            (result, parsed)
+        }));
+    }
+
+    let mut results = Vec::new();
+    let mut comments = Vec::new();
+    let mut suggestions = Vec::new();
+    let mut warnings = Vec::new();
+    let mut scores = Vec::new();
+    for task in tasks {
+        let (result, parsed) = task
+            .await
+            .map_err(|e| format!("Chunk task failed: {}", e))?;
+        match parsed {
+            Some(parsed) => {
+                let label = format!("Part {}", result.index + 1);
+                warnings.extend(parsed.warnings.iter().map(|w| format!("{}: {}", label, w)));
+                scores.extend(parsed.overall_score);
+                comments.extend(parsed.comments);
+                suggestions.extend(parsed.suggestions);
+            }
+            None => warnings.push(format!(
+                "Part {} ({}) did not finish: {}",
+                result.index + 1,
+                result.files.join(", "),
+                result.error.as_deref().unwrap_or("no output")
+            )),
+        }
+        results.push(result);
+    }
+
+    if stopped.load(Ordering::SeqCst) {
+        return Err("Review cancelled".to_string());
+    }
+    if results
+        .iter()
+        .all(|result| result.outcome != Some(JobOutcome::Completed))
+    {
+        return Err(format!(
+            "Every part of the review failed: {}",
+            warnings.join("; ")
+        ));
+    }
+    comments.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    // Models sometimes cite lines from memory rather than from the diff they were given
+    for comment in &comments {
+        let side = if comment.side == "LEFT" {
+            Side::Left
+        } else {
+            Side::Right
+        };
+        let in_diff = files.iter().any(|file| {
+            file.path == comment.path
+                && file
+                    .commentable_lines(side)
+                    .iter()
+                    .any(|(line, _)| u64::from(*line) == comment.line)
+        });
+        if !in_diff {
+            warnings.push(format!(
+                "Comment on {}:{} ({}) is not on a line of the diff",
+                comment.path, comment.line, comment.side
+            ));
+        }
+    }
+    suggestions.sort_by(|a, b| a.path.cmp(&b.path).then(a.start_line.cmp(&b.start_line)));
+
+    let mean_score = if scores.is_empty() {
+        None
+    } else {
+        Some(scores.iter().sum::<f64>() / scores.len() as f64)
+    };
+    let joined_summaries = || {
+        results
+            .iter()
+            .filter_map(|result| {
+                let summary = result.summary.as_deref()?;
+                Some(format!("**Part {}:** {}", result.index + 1, summary))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+
+    // A single chunk already reviewed the whole PR
+    let (summary, overall_score) = if total == 1 {
+        (results[0].summary.clone().unwrap_or_default(), mean_score)
+    } else {
+        let process_id = format!("{}-summary", params.review_id);
+        let _ = progress.send(MapReduceProgress::Summarizing {
+            process_id: process_id.clone(),
+        });
+        let prompt = summary_prompt(&pr, &results, &comments);
+        match run_to_completion(&app, &params, &process_id, prompt).await {
+            (Some(JobOutcome::Completed), _, Some(output)) => {
+                let parsed = parse_review(&output);
+                match parsed.summary {
+                    Some(summary) if parsed.found_json => {
+                        (summary, parsed.overall_score.or(mean_score))
+                    }
+                    _ => {
+                        warnings.push("The summary pass returned no summary".to_string());
+                        (joined_summaries(), mean_score)
+                    }
+                }
+            }
+            (_, error, _) => {
+                warnings.push(format!(
+                    "The summary pass failed: {}",
+                    error.as_deref().unwrap_or("no output")
+                ));
+                (joined_summaries(), mean_score)
+            }
+        }
+    };
+
+    Ok(MapReduceReview {
+        summary,
+        overall_score,
+        comments,
+        suggestions,
+        chunks: results,
+        warnings,
+    })
+}
@@ -0,0 +1,236 @@
+//! Repository review policy from `.github/lyon.yml` on the PR's base branch, so a team's
+//! review norms travel with the repository instead of living in each reviewer's settings.
+//! Reading it from the base branch means a PR can't loosen the policy it is reviewed under.
+//!
+//! Precedence when merged with personal settings: the policy's instructions are appended
+//! after the personal system prompt; its focus areas and severity threshold replace the
+//! personal ones when set; ignore globs are combined; and its rules are added to the local
+//! rules, replacing local rules with the same id.
+//!
+//! ```yaml
+//! ignore: ["**/*.lock", "dist/**"]
+//! focus_areas: [security, testing]
+//! instructions: |
+//!   Public API changes need a CHANGELOG entry.
+//! severity_threshold: warning
+//! rules:
+//!   - id: no-console
+//!     type: pattern
+//!     pattern: console\.log
+//!     message: Remove {match}
+//! ```
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::review_context::encode_path;
+use crate::rules::{self, glob_regex, ReviewRule};
+use crate::run_gh_command;
+
+pub const POLICY_PATH: &str = ".github/lyon.yml";
+
+/// Severities from least to most severe
+const SEVERITY_ORDER: &[&str] = &["info", "suggestion", "warning", "critical"];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoPolicy {
+    /// Globs of files left out of reviews
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub focus_areas: Vec<String>,
+    /// Added to the review prompt
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// Findings below this severity are dropped
+    #[serde(default)]
+    pub severity_threshold: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<ReviewRule>,
+}
+
+/// Review configuration after the repository policy is applied
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewPolicy {
+    /// System prompt for the review
+    pub instructions: String,
+    pub focus_areas: Vec<String>,
+    pub ignore: Vec<String>,
+    pub severity_threshold: Option<String>,
+    pub rules: Vec<ReviewRule>,
+    /// Where the repository policy came from, if there is one
+    pub source: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ReviewPolicy {
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignore
+            .iter()
+            .filter_map(|glob| glob_regex(glob).ok())
+            .any(|regex: Regex| regex.is_match(path))
+    }
+}
+
+fn severity_rank(severity: &str) -> Option<usize> {
+    SEVERITY_ORDER.iter().position(|s| *s == severity)
+}
+
+/// Whether a finding of `severity` is reported under `threshold`
+pub fn meets_threshold(severity: &str, threshold: Option<&str>) -> bool {
+    match (threshold.and_then(severity_rank), severity_rank(severity)) {
+        (Some(threshold), Some(severity)) => severity >= threshold,
+        _ => true,
+    }
+}
+
+async fn base_ref(repository: &str, pr_number: u64) -> Result<String, String> {
+    let json = run_gh_command(
+        [
+            "pr",
+            "view",
+            &pr_number.to_string(),
+            "--repo",
+            repository,
+            "--json",
+            "baseRefName",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    )
+    .await?;
+    serde_json::from_str::<Value>(&json)
+        .ok()
+        .and_then(|value| value.get("baseRefName")?.as_str().map(str::to_string))
+        .filter(|base| !base.is_empty())
+        .ok_or_else(|| format!("PR #{} has no base branch", pr_number))
+}
+
+/// The policy on the PR's base branch, with where it was read from. `None` if the
+/// repository has no policy file.
+pub async fn fetch(
+    repository: &str,
+    pr_number: u64,
+) -> Result<Option<(RepoPolicy, String)>, String> {
+    let base = base_ref(repository, pr_number).await?;
+    let contents = run_gh_command(vec![
+        "api".to_string(),
+        // Branch names may contain characters like '#', '&' or '+' that would end the ref
+        format!(
+            "repos/{}/contents/{}?ref={}",
+            repository,
+            POLICY_PATH,
+            encode_path(&base)
+        ),
+        "-H".to_string(),
+        "Accept: application/vnd.github.raw".to_string(),
+    ])
+    .await;
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) if e.contains("404") || e.contains("Not Found") => return Ok(None),
+        Err(e) => return Err(format!("Failed to fetch {}: {}", POLICY_PATH, e)),
+    };
+    let source = format!("{}@{}:{}", repository, base, POLICY_PATH);
+    if contents.trim().is_empty() {
+        return Ok(Some((RepoPolicy::default(), source)));
+    }
+    let policy =
+        serde_yaml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", source, e))?;
+    Ok(Some((policy, source)))
+}
+
+/// Apply a repository policy to the personal instructions and local rules
+pub fn merge(
+    instructions: &str,
+    local_rules: Vec<ReviewRule>,
+    repo: Option<(RepoPolicy, String)>,
+) -> ReviewPolicy {
+    let mut merged = ReviewPolicy {
+        instructions: instructions.to_string(),
+        focus_areas: Vec::new(),
+        ignore: Vec::new(),
+        severity_threshold: None,
+        rules: local_rules,
+        source: None,
+        warnings: Vec::new(),
+    };
+    let (repo, source) = match repo {
+        Some(repo) => repo,
+        None => return merged,
+    };
+
+    for glob in repo.ignore {
+        match glob_regex(&glob) {
+            Ok(_) => merged.ignore.push(glob),
+            Err(e) => merged.warnings.push(format!("{}: {}", source, e)),
+        }
+    }
+    merged.focus_areas = repo.focus_areas;
+    match repo.severity_threshold {
+        Some(threshold) if severity_rank(&threshold).is_none() => merged.warnings.push(format!(
+            "{}: unknown severity_threshold {}",
+            source, threshold
+        )),
+        threshold => merged.severity_threshold = threshold,
+    }
+    for rule in repo.rules {
+        if let Err(e) = rules::validate(std::slice::from_ref(&rule)) {
+            merged.warnings.push(format!("{}: {}", source, e));
+            continue;
+        }
+        merged.rules.retain(|local| local.id != rule.id);
+        merged.rules.push(rule);
+    }
+
+    let mut policy_prompt = String::new();
+    if !merged.focus_areas.is_empty() {
+        policy_prompt.push_str(&format!(
+            "Focus the review on: {}.\n",
+            merged.focus_areas.join(", ")
+        ));
+    }
+    if let Some(threshold) = &merged.severity_threshold {
+        policy_prompt.push_str(&format!(
+            "Only report findings of severity {} or higher.\n",
+            threshold
+        ));
+    }
+    if let Some(extra) = repo.instructions.filter(|extra| !extra.trim().is_empty()) {
+        policy_prompt.push_str(extra.trim());
+        policy_prompt.push('\n');
+    }
+    if !policy_prompt.is_empty() {
+        merged.instructions = format!(
+            "{}\n\nThis repository's review policy ({}):\n{}",
+            merged.instructions.trim_end(),
+            POLICY_PATH,
+            policy_prompt.trim_end()
+        );
+    }
+    merged.source = Some(source);
+    merged
+}
+
+/// The review configuration for a PR: personal instructions and local rules with the
+/// repository policy applied. A missing or broken policy file is reported as a warning
+/// rather than stopping the review.
+pub async fn resolve(
+    repository: &str,
+    pr_number: u64,
+    instructions: &str,
+    local_rules: Vec<ReviewRule>,
+) -> ReviewPolicy {
+    match fetch(repository, pr_number).await {
+        Ok(repo) => merge(instructions, local_rules, repo),
+        Err(e) => {
+            let mut merged = merge(instructions, local_rules, None);
+            merged.warnings.push(e);
+            merged
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Record of AI process groups spawned by `start_ai_stream`, persisted so they can be
+//! killed when the app quits and reaped on the next launch if the app crashed.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GroupRecord {
+    pgid: i32,
+    /// Provider command, used to check a leftover group is still ours before killing it
+    command: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct GroupFile {
+    groups: Vec<GroupRecord>,
+}
+
+#[derive(Default)]
+pub struct ProcessGroupRegistry {
+    /// State file in the app data dir; nothing is persisted until it is set
+    path: OnceLock<PathBuf>,
+    groups: Mutex<Vec<GroupRecord>>,
+}
+
+impl ProcessGroupRegistry {
+    /// Set the state file location and kill any groups a previous session left behind
+    pub fn init(&self, path: PathBuf) {
+        let leftovers = read_group_file(&path);
+        if !leftovers.is_empty() {
+            reap_leftovers(&leftovers);
+        }
+        let _ = self.path.set(path);
+        self.save(&self.groups.lock().unwrap());
+    }
+
+    pub fn register(&self, pgid: u32, command: &str) {
+        let mut groups = self.groups.lock().unwrap();
+        groups.push(GroupRecord {
+            pgid: pgid as i32,
+            command: command.to_string(),
+        });
+        self.save(&groups);
+    }
+
+    pub fn unregister(&self, pgid: u32) {
+        let mut groups = self.groups.lock().unwrap();
+        groups.retain(|group| group.pgid != pgid as i32);
+        self.save(&groups);
+    }
+
+    /// SIGTERM every running group, then SIGKILL whatever is left. Blocks briefly; meant for app exit.
+    pub fn kill_all(&self) {
+        let mut groups = self.groups.lock().unwrap();
+        if groups.is_empty() {
+            return;
+        }
+        log::info!("Killing {} AI process group(s) on exit", groups.len());
+        kill_groups(groups.iter().map(|group| group.pgid));
+        groups.clear();
+        self.save(&groups);
+    }
+
+    fn save(&self, groups: &[GroupRecord]) {
+        let path = match self.path.get() {
+            Some(path) => path,
+            None => return,
+        };
+        let file = GroupFile {
+            groups: groups.to_vec(),
+        };
+        if let Err(e) = write_group_file(path, &file) {
+            log::warn!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn read_group_file(path: &Path) -> Vec<GroupRecord> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<GroupFile>(&contents).ok())
+        .map(|file| file.groups)
+        .unwrap_or_default()
+}
+
+fn write_group_file(path: &Path, file: &GroupFile) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string(file).map_err(|e| e.to_string())?;
+    // Write then rename so a crash mid-write never leaves a truncated file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+#[cfg(unix)]
+fn kill_groups(pgids: impl Iterator<Item = i32> + Clone) {
+    for pgid in pgids.clone() {
+        unsafe {
+            libc::kill(-pgid, libc::SIGTERM);
+        }
+    }
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    for pgid in pgids {
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_groups(_pgids: impl Iterator<Item = i32> + Clone) {}
+
+/// Kill recorded groups that are still running a provider command. Process group ids
+/// can be reused after a reboot, so a group is only killed if one of its processes
+/// still mentions the command it was recorded with.
+#[cfg(unix)]
+fn reap_leftovers(leftovers: &[GroupRecord]) {
+    let output = match std::process::Command::new("ps")
+        .args(["-A", "-o", "pgid=,args="])
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => {
+            log::warn!("Failed to list processes for orphan cleanup: {}", e);
+            return;
+        }
+    };
+
+    let orphans: Vec<i32> = leftovers
+        .iter()
+        .filter(|group| {
+            let name = Path::new(&group.command)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| group.command.clone());
+            output.lines().any(|line| {
+                let mut parts = line.trim_start().splitn(2, ' ');
+                let pgid = parts.next().and_then(|pgid| pgid.parse::<i32>().ok());
+                let args = parts.next().unwrap_or_default();
+                pgid == Some(group.pgid) && args.contains(&name)
+            })
+        })
+        .map(|group| group.pgid)
+        .collect();
+
+    if !orphans.is_empty() {
+        log::warn!(
+            "Killing {} AI process group(s) left over from a previous session",
+            orphans.len()
+        );
+        kill_groups(orphans.into_iter());
+    }
+}
+
+#[cfg(not(unix))]
+fn reap_leftovers(_leftovers: &[GroupRecord]) {}
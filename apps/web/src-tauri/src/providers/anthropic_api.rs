@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use super::{BlockWriter, ContentEvent, HttpProvider, OutputParser, ProviderRequest, TokenUsage};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_MODEL: &str = "claude-sonnet-4-5";
+const MAX_OUTPUT_TOKENS: u64 = 16000;
+
+/// Anthropic Messages API called directly with an API key, no CLI involved
+pub struct AnthropicApiProvider;
+
+/// Map the shared reasoning effort levels onto an extended thinking budget
+fn thinking_budget(effort: &str) -> Option<u64> {
+    match effort {
+        "low" => Some(2048),
+        "medium" => Some(8192),
+        "high" => Some(16000),
+        "xhigh" => Some(32000),
+        _ => None,
+    }
+}
+
+impl HttpProvider for AnthropicApiProvider {
+    fn id(&self) -> &'static str {
+        "anthropic-api"
+    }
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &ProviderRequest,
+    ) -> Result<reqwest::RequestBuilder, String> {
+        let api_key = request
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("ANTHROPIC_API_KEY").ok())
+            .filter(|key| !key.is_empty())
+            .ok_or("An Anthropic API key is required (set one in settings or ANTHROPIC_API_KEY)")?;
+        let base_url = request
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/');
+
+        let mut body = json!({
+            "model": request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+            "max_tokens": MAX_OUTPUT_TOKENS,
+            "stream": true,
+            "messages": [{ "role": "user", "content": request.prompt }],
+        });
+        if let Some(budget) = request
+            .reasoning_effort
+            .as_deref()
+            .and_then(thinking_budget)
+        {
+            // max_tokens must leave room for the answer after the thinking budget
+            body["max_tokens"] = json!(budget + MAX_OUTPUT_TOKENS);
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        }
+
+        Ok(client
+            .post(format!("{}/v1/messages", base_url))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Accept", "text/event-stream")
+            .json(&body))
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(MessagesParser::default())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockKind {
+    Thinking,
+    Text,
+    Other,
+}
+
+#[derive(Default)]
+struct MessagesParser {
+    writer: BlockWriter,
+    open_blocks: HashMap<u64, BlockKind>,
+    usage: Option<TokenUsage>,
+    errors: Vec<String>,
+}
+
+impl OutputParser for MessagesParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        // The event name is repeated in the payload's `type`, so `event:` lines can be skipped
+        let data = match line.trim().strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => return Vec::new(),
+        };
+        let event = match serde_json::from_str::<Value>(data) {
+            Ok(value) => value,
+            Err(_) => return Vec::new(),
+        };
+        let index = event.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+
+        match event.get("type").and_then(|v| v.as_str()) {
+            Some("message_start") => {
+                if let Some(usage) = event.get("message").and_then(|m| m.get("usage")) {
+                    self.merge_usage(usage);
+                }
+                Vec::new()
+            }
+            Some("content_block_start") => {
+                let block_type = event
+                    .get("content_block")
+                    .and_then(|b| b.get("type"))
+                    .and_then(|v| v.as_str());
+                match block_type {
+                    Some("thinking") => {
+                        self.open_blocks.insert(index, BlockKind::Thinking);
+                        vec![ContentEvent::ThinkingStart]
+                    }
+                    Some("text") => {
+                        self.open_blocks.insert(index, BlockKind::Text);
+                        self.writer.open_text()
+                    }
+                    _ => {
+                        self.open_blocks.insert(index, BlockKind::Other);
+                        Vec::new()
+                    }
+                }
+            }
+            Some("content_block_delta") => {
+                let delta = match event.get("delta") {
+                    Some(delta) => delta,
+                    None => return Vec::new(),
+                };
+                match delta.get("type").and_then(|v| v.as_str()) {
+                    Some("thinking_delta") => delta
+                        .get("thinking")
+                        .and_then(|v| v.as_str())
+                        .map(|text| vec![ContentEvent::ThinkingDelta(text.to_string())])
+                        .unwrap_or_default(),
+                    Some("text_delta") => delta
+                        .get("text")
+                        .and_then(|v| v.as_str())
+                        .map(|text| self.writer.text_delta(text))
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                }
+            }
+            Some("content_block_stop") => match self.open_blocks.remove(&index) {
+                Some(BlockKind::Thinking) | Some(BlockKind::Text) => vec![ContentEvent::BlockStop],
+                _ => Vec::new(),
+            },
+            // Output token counts are cumulative on message_delta
+            Some("message_delta") => {
+                if let Some(usage) = event.get("usage") {
+                    self.merge_usage(usage);
+                }
+                Vec::new()
+            }
+            Some("message_stop") => self.take_usage(),
+            Some("error") => {
+                let message = event
+                    .get("error")
+                    .and_then(|e| e.get("message"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Anthropic API returned an error");
+                self.errors.push(message.to_string());
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        let open = self
+            .open_blocks
+            .drain()
+            .any(|(_, kind)| kind != BlockKind::Other);
+        let mut events = if open {
+            vec![ContentEvent::BlockStop]
+        } else {
+            Vec::new()
+        };
+        // Report whatever usage arrived if the stream ended without message_stop
+        events.extend(self.take_usage());
+        events
+    }
+
+    fn error_output(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
+        }
+    }
+}
+
+impl MessagesParser {
+    fn merge_usage(&mut self, value: &Value) {
+        let usage = self.usage.get_or_insert_with(TokenUsage::default);
+        let field = |name: &str| value.get(name).and_then(|v| v.as_u64());
+        if let Some(tokens) = field("input_tokens") {
+            usage.input_tokens = tokens;
+        }
+        if let Some(tokens) = field("output_tokens") {
+            usage.output_tokens = tokens;
+        }
+        if let Some(tokens) = field("cache_creation_input_tokens") {
+            usage.cache_creation_input_tokens = tokens;
+        }
+        if let Some(tokens) = field("cache_read_input_tokens") {
+            usage.cache_read_input_tokens = tokens;
+        }
+    }
+
+    fn take_usage(&mut self) -> Vec<ContentEvent> {
+        self.usage
+            .take()
+            .map(ContentEvent::Usage)
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::exchange;
+    use crate::providers::ProviderErrorKind;
+
+    /// Recorded from the Messages API with extended thinking
+    const STREAM: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-5","content":[],"usage":{"input_tokens":25,"cache_creation_input_tokens":0,"cache_read_input_tokens":100,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Looking at the diff."}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"abc"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}
+
+event: ping
+data: {"type":"ping"}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"{\"summary\":\"ok\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"output_tokens":42}}
+
+event: message_stop
+data: {"type":"message_stop"}
+"#;
+
+    fn request() -> ProviderRequest {
+        ProviderRequest {
+            prompt: "Review this".to_string(),
+            api_key: Some("sk-ant-test".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn parse(stream: &str) -> (Vec<ContentEvent>, Option<String>) {
+        let mut parser = MessagesParser::default();
+        let mut events: Vec<ContentEvent> = stream
+            .lines()
+            .flat_map(|line| parser.parse_line(line))
+            .collect();
+        events.extend(parser.finish());
+        (events, parser.error_output())
+    }
+
+    #[test]
+    fn parses_blocks_and_usage() {
+        let (events, error) = parse(STREAM);
+        assert_eq!(
+            events,
+            vec![
+                ContentEvent::ThinkingStart,
+                ContentEvent::ThinkingDelta("Looking at the diff.".to_string()),
+                ContentEvent::BlockStop,
+                ContentEvent::TextDelta("{\"summary\":\"ok\"}".to_string()),
+                ContentEvent::BlockStop,
+                // Input and cache tokens from message_start, output from message_delta
+                ContentEvent::Usage(TokenUsage {
+                    input_tokens: 25,
+                    output_tokens: 42,
+                    cache_read_input_tokens: 100,
+                    ..Default::default()
+                }),
+            ]
+        );
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn reports_usage_when_the_stream_ends_early() {
+        let cut = STREAM.split("event: content_block_stop").next().unwrap();
+        let (events, _) = parse(cut);
+        assert_eq!(events[events.len() - 2], ContentEvent::BlockStop);
+        assert_eq!(
+            events.last(),
+            Some(&ContentEvent::Usage(TokenUsage {
+                input_tokens: 25,
+                output_tokens: 1,
+                cache_read_input_tokens: 100,
+                ..Default::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn reports_error_events() {
+        let (events, error) = parse(
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n",
+        );
+        assert!(events.is_empty());
+        assert_eq!(error.as_deref(), Some("Overloaded"));
+    }
+
+    #[test]
+    fn sends_the_api_key_header() {
+        let built = AnthropicApiProvider
+            .build_request(&reqwest::Client::new(), &request())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            built.url().as_str(),
+            "https://api.anthropic.com/v1/messages"
+        );
+        let headers = built.headers();
+        assert_eq!(headers["x-api-key"], "sk-ant-test");
+        assert_eq!(headers["anthropic-version"], ANTHROPIC_VERSION);
+        assert!(headers.get("authorization").is_none());
+    }
+
+    #[test]
+    fn budgets_thinking_from_reasoning_effort() {
+        let request = ProviderRequest {
+            reasoning_effort: Some("medium".to_string()),
+            ..request()
+        };
+        let built = AnthropicApiProvider
+            .build_request(&reqwest::Client::new(), &request)
+            .unwrap()
+            .build()
+            .unwrap();
+        let body: Value =
+            serde_json::from_slice(built.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["thinking"]["budget_tokens"], 8192);
+        assert_eq!(body["max_tokens"], 8192 + MAX_OUTPUT_TOKENS);
+        assert_eq!(body["model"], DEFAULT_MODEL);
+    }
+
+    #[tokio::test]
+    async fn streams_from_a_server() {
+        let exchange = exchange(&AnthropicApiProvider, &request(), "200 OK", STREAM).await;
+        assert!(exchange.request.starts_with("POST /v1/messages HTTP/1.1"));
+        assert!(exchange
+            .request
+            .to_lowercase()
+            .contains("x-api-key: sk-ant-test"));
+        assert_eq!(exchange.events, parse(STREAM).0);
+        assert_eq!(exchange.error, None);
+    }
+
+    #[tokio::test]
+    async fn classifies_error_responses() {
+        let cases = [
+            (
+                "401 Unauthorized",
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+                ProviderErrorKind::NotAuthenticated,
+            ),
+            (
+                "429 Too Many Requests",
+                r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of request tokens has exceeded your rate limit"}}"#,
+                ProviderErrorKind::RateLimited,
+            ),
+            (
+                "400 Bad Request",
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#,
+                ProviderErrorKind::ContextTooLong,
+            ),
+        ];
+        for (status, body, kind) in cases {
+            let exchange = exchange(&AnthropicApiProvider, &request(), status, body).await;
+            assert_eq!(exchange.error_kind, Some(kind), "{}", status);
+            assert_eq!(exchange.error.as_deref(), Some(body));
+        }
+    }
+}
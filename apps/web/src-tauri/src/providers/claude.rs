@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{
+    classify_common_error, AiProvider, BlockWriter, ContentEvent, OutputParser, ProviderErrorKind,
+    ProviderRequest, TokenUsage,
+};
+
+/// Claude Code CLI in print mode with `--output-format stream-json`
+pub struct ClaudeProvider;
+
+impl AiProvider for ClaudeProvider {
+    fn id(&self) -> &str {
+        "claude"
+    }
+
+    fn command(&self) -> &str {
+        "claude"
+    }
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(model) = &request.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+        // Without a prompt argument, print mode reads the prompt from stdin
+        args.push("-p".to_string());
+        if !request.prompt_on_stdin {
+            args.push(request.prompt.clone());
+        }
+        // Only the gh proxy is allowed, by its full path, so the shell's PATH can't swap in
+        // the real gh; without the proxy no command is allowed at all
+        if let Some(gh) = &request.gh_command {
+            args.push("--allowedTools".to_string());
+            args.push(format!("Bash({}:*)", gh));
+            args.push("--append-system-prompt".to_string());
+            args.push(format!(
+                "Run the GitHub CLI by its full path, {}; plain `gh` is not permitted.",
+                gh
+            ));
+        }
+        // Partial messages make thinking and text arrive incrementally
+        // (stream-json requires --verbose in print mode)
+        args.extend(
+            [
+                "--output-format",
+                "stream-json",
+                "--verbose",
+                "--include-partial-messages",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+        args
+    }
+
+    fn stdin_input(&self, request: &ProviderRequest) -> Option<String> {
+        request.prompt_on_stdin.then(|| request.prompt.clone())
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(ClaudeParser::default())
+    }
+
+    fn classify_error(&self, _exit_code: Option<i32>, output: &str) -> ProviderErrorKind {
+        let message = output.to_lowercase();
+        if message.contains("/login") || message.contains("oauth token") {
+            ProviderErrorKind::NotAuthenticated
+        } else {
+            classify_common_error(output)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockKind {
+    Thinking,
+    Text,
+    Other,
+}
+
+#[derive(Default)]
+struct ClaudeParser {
+    writer: BlockWriter,
+    /// Set once a `stream_event` arrives; full `assistant` messages then only repeat deltas
+    partial_messages: bool,
+    /// Block kinds of the currently open content blocks, keyed by block index
+    open_blocks: HashMap<u64, BlockKind>,
+    error: Option<String>,
+}
+
+impl OutputParser for ClaudeParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Vec::new();
+        }
+
+        let json_value = match serde_json::from_str::<Value>(trimmed) {
+            Ok(value) => value,
+            Err(_) => return self.writer.raw_line(line),
+        };
+
+        match json_value.get("type").and_then(|v| v.as_str()) {
+            Some("stream_event") => match json_value.get("event") {
+                Some(event) => self.parse_stream_event(event),
+                None => Vec::new(),
+            },
+            Some("assistant") => self.parse_assistant(&json_value),
+            Some("result") => self.parse_result(&json_value),
+            _ => Vec::new(),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        let open = self
+            .open_blocks
+            .drain()
+            .any(|(_, kind)| kind != BlockKind::Other);
+        if open {
+            vec![ContentEvent::BlockStop]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn error_output(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
+
+impl ClaudeParser {
+    // --include-partial-messages: raw Messages API streaming events
+    fn parse_stream_event(&mut self, event: &Value) -> Vec<ContentEvent> {
+        self.partial_messages = true;
+        let index = event.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+
+        match event.get("type").and_then(|v| v.as_str()) {
+            Some("content_block_start") => {
+                let block_type = event
+                    .get("content_block")
+                    .and_then(|b| b.get("type"))
+                    .and_then(|v| v.as_str());
+                match block_type {
+                    Some("thinking") => {
+                        self.open_blocks.insert(index, BlockKind::Thinking);
+                        vec![ContentEvent::ThinkingStart]
+                    }
+                    Some("text") => {
+                        self.open_blocks.insert(index, BlockKind::Text);
+                        self.writer.open_text()
+                    }
+                    _ => {
+                        self.open_blocks.insert(index, BlockKind::Other);
+                        Vec::new()
+                    }
+                }
+            }
+            Some("content_block_delta") => {
+                let delta = match event.get("delta") {
+                    Some(delta) => delta,
+                    None => return Vec::new(),
+                };
+                match delta.get("type").and_then(|v| v.as_str()) {
+                    Some("thinking_delta") => delta
+                        .get("thinking")
+                        .and_then(|v| v.as_str())
+                        .map(|text| vec![ContentEvent::ThinkingDelta(text.to_string())])
+                        .unwrap_or_default(),
+                    Some("text_delta") => delta
+                        .get("text")
+                        .and_then(|v| v.as_str())
+                        .map(|text| self.writer.text_delta(text))
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                }
+            }
+            Some("content_block_stop") => match self.open_blocks.remove(&index) {
+                Some(BlockKind::Thinking) | Some(BlockKind::Text) => vec![ContentEvent::BlockStop],
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // Without partial messages: one event per completed assistant message
+    fn parse_assistant(&mut self, value: &Value) -> Vec<ContentEvent> {
+        if self.partial_messages {
+            return Vec::new();
+        }
+
+        let content = match value
+            .get("message")
+            .and_then(|m| m.get("content"))
+            .and_then(|c| c.as_array())
+        {
+            Some(content) => content,
+            None => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+        for block in content {
+            match block.get("type").and_then(|v| v.as_str()) {
+                Some("thinking") => {
+                    if let Some(text) = block.get("thinking").and_then(|v| v.as_str()) {
+                        events.extend(self.writer.thinking_block(text));
+                    }
+                }
+                Some("text") => {
+                    if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+                        events.extend(self.writer.text_block(text));
+                    }
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+
+    // Final result; its text is only used when nothing was streamed (e.g. --output-format json)
+    fn parse_result(&mut self, value: &Value) -> Vec<ContentEvent> {
+        let result = value.get("result").and_then(|v| v.as_str());
+        let mut events = Vec::new();
+
+        if value.get("is_error").and_then(|v| v.as_bool()) == Some(true) {
+            self.error = Some(result.unwrap_or("Claude reported an error").to_string());
+        } else if !self.writer.has_text() {
+            if let Some(text) = result.filter(|text| !text.is_empty()) {
+                events.extend(self.writer.text_block(text));
+            }
+        }
+
+        // {"usage":{"input_tokens":...,"output_tokens":...},"total_cost_usd":0.012}
+        if let Some(usage) = value.get("usage") {
+            let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+            events.push(ContentEvent::Usage(TokenUsage {
+                input_tokens: tokens("input_tokens"),
+                output_tokens: tokens("output_tokens"),
+                cache_creation_input_tokens: tokens("cache_creation_input_tokens"),
+                cache_read_input_tokens: tokens("cache_read_input_tokens"),
+                cost_usd: value.get("total_cost_usd").and_then(|v| v.as_f64()),
+            }));
+        }
+        events
+    }
+}
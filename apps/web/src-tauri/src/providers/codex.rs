@@ -0,0 +1,146 @@
+use serde_json::Value;
+
+use super::{
+    classify_common_error, AiProvider, BlockWriter, ContentEvent, OutputParser, ProviderErrorKind,
+    ProviderRequest, TokenUsage,
+};
+
+/// Codex CLI in non-interactive `exec --json` mode
+pub struct CodexProvider;
+
+impl AiProvider for CodexProvider {
+    fn id(&self) -> &str {
+        "codex"
+    }
+
+    fn command(&self) -> &str {
+        "codex"
+    }
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String> {
+        // The read-only sandbox has no network access, so the agent can't take the user's
+        // gh credentials to GitHub itself; gh still works because the backend's read-only
+        // proxy is reached over a Unix socket
+        let mut args: Vec<String> = [
+            "exec",
+            "--json",
+            "--skip-git-repo-check",
+            "--sandbox",
+            "read-only",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        if let Some(model) = &request.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+        if let Some(effort) = &request.reasoning_effort {
+            args.push("-c".to_string());
+            args.push(format!("model_reasoning_effort=\"{}\"", effort));
+        }
+        // `-` makes exec read the prompt from stdin
+        if request.prompt_on_stdin {
+            args.push("-".to_string());
+        } else {
+            args.push(request.prompt.clone());
+        }
+        args
+    }
+
+    fn stdin_input(&self, request: &ProviderRequest) -> Option<String> {
+        request.prompt_on_stdin.then(|| request.prompt.clone())
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(CodexParser::default())
+    }
+
+    fn classify_error(&self, _exit_code: Option<i32>, output: &str) -> ProviderErrorKind {
+        let message = output.to_lowercase();
+        if message.contains("codex login") {
+            ProviderErrorKind::NotAuthenticated
+        } else {
+            classify_common_error(output)
+        }
+    }
+}
+
+#[derive(Default)]
+struct CodexParser {
+    writer: BlockWriter,
+    errors: Vec<String>,
+}
+
+impl OutputParser for CodexParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Vec::new();
+        }
+
+        let json_value = match serde_json::from_str::<Value>(trimmed) {
+            Ok(value) => value,
+            Err(_) => return self.writer.raw_line(line),
+        };
+
+        match json_value.get("type").and_then(|v| v.as_str()) {
+            // {"type":"item.completed","item":{"type":"agent_message","text":"..."}}
+            Some("item.completed") => {
+                let item = match json_value.get("item") {
+                    Some(item) => item,
+                    None => return Vec::new(),
+                };
+                let text = match item.get("text").and_then(|v| v.as_str()) {
+                    Some(text) => text,
+                    None => return Vec::new(),
+                };
+                match item.get("type").and_then(|v| v.as_str()) {
+                    Some("reasoning") => self.writer.thinking_block(text),
+                    Some("agent_message") => self.writer.text_block(text),
+                    _ => Vec::new(),
+                }
+            }
+            Some("error") => {
+                if let Some(message) = json_value.get("message").and_then(|v| v.as_str()) {
+                    self.errors.push(message.to_string());
+                }
+                Vec::new()
+            }
+            // {"type":"turn.completed","usage":{"input_tokens":...,"cached_input_tokens":...,"output_tokens":...}}
+            Some("turn.completed") => match json_value.get("usage") {
+                Some(usage) => {
+                    let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+                    let cached = tokens("cached_input_tokens");
+                    vec![ContentEvent::Usage(TokenUsage {
+                        // Codex counts cached tokens as part of the input; keep them separate
+                        input_tokens: tokens("input_tokens").saturating_sub(cached),
+                        output_tokens: tokens("output_tokens"),
+                        cache_read_input_tokens: cached,
+                        ..Default::default()
+                    })]
+                }
+                None => Vec::new(),
+            },
+            Some("turn.failed") => {
+                if let Some(message) = json_value
+                    .get("error")
+                    .and_then(|e| e.get("message"))
+                    .and_then(|v| v.as_str())
+                {
+                    self.errors.push(message.to_string());
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn error_output(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
+        }
+    }
+}
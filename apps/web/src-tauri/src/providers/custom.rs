@@ -0,0 +1,350 @@
+//! User-defined providers wrapping arbitrary CLIs, declared in `custom-providers.json`:
+//!
+//! ```json
+//! { "providers": [{
+//!     "id": "team-llm",
+//!     "name": "Team LLM",
+//!     "command": "/usr/local/bin/llm-wrap",
+//!     "args": ["--model", "{model}", "{prompt}"],
+//!     "promptOnStdin": false,
+//!     "output": { "format": "jsonl", "pointer": "/item/text",
+//!                 "filter": { "pointer": "/type", "equals": "message" } }
+//! }] }
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{AiProvider, BlockWriter, ContentEvent, OutputParser, ProviderRequest};
+
+const PLACEHOLDERS: &[&str] = &["prompt", "model"];
+
+/// How text is pulled out of a custom provider's stdout
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum OutputRule {
+    /// Forward stdout as-is
+    #[default]
+    Text,
+    /// Stdout is a single JSON document; `pointer` selects the text
+    Json { pointer: String },
+    /// Stdout is one JSON object per line; `pointer` selects each line's text
+    Jsonl {
+        pointer: String,
+        #[serde(default)]
+        filter: Option<LineFilter>,
+    },
+}
+
+/// Only use JSONL lines where the value at `pointer` equals `equals`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LineFilter {
+    pub pointer: String,
+    pub equals: Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CustomProviderConfig {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub command: String,
+    /// Argument template; `{prompt}` and `{model}` are substituted per run
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub prompt_on_stdin: bool,
+    #[serde(default)]
+    pub default_model: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub output: OutputRule,
+}
+
+/// Custom providers loaded from disk, with the definitions that failed validation
+#[derive(Debug, Clone, Default)]
+pub struct CustomProviderSet {
+    pub providers: Vec<CustomProviderConfig>,
+    pub errors: Vec<String>,
+}
+
+/// Load and validate custom providers. A missing file is not an error; an invalid
+/// definition is reported and skipped without affecting the others.
+pub fn load_custom_providers(path: &Path, reserved_ids: &[&str]) -> CustomProviderSet {
+    let mut set = CustomProviderSet::default();
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return set,
+        Err(e) => {
+            set.errors
+                .push(format!("Failed to read {}: {}", path.display(), e));
+            return set;
+        }
+    };
+
+    let root: Value = match serde_json::from_str(&contents) {
+        Ok(root) => root,
+        Err(e) => {
+            set.errors
+                .push(format!("Failed to parse {}: {}", path.display(), e));
+            return set;
+        }
+    };
+    let entries = match root.get("providers").and_then(|v| v.as_array()) {
+        Some(entries) => entries,
+        None => {
+            set.errors
+                .push(format!("{} has no \"providers\" array", path.display()));
+            return set;
+        }
+    };
+
+    for (index, entry) in entries.iter().enumerate() {
+        let config = match serde_json::from_value::<CustomProviderConfig>(entry.clone()) {
+            Ok(config) => config,
+            Err(e) => {
+                set.errors.push(format!("Provider #{}: {}", index + 1, e));
+                continue;
+            }
+        };
+
+        let taken = reserved_ids.contains(&config.id.as_str())
+            || set.providers.iter().any(|p| p.id == config.id);
+        let result = if taken {
+            Err(format!("id \"{}\" is already in use", config.id))
+        } else {
+            validate(&config)
+        };
+
+        match result {
+            Ok(()) => set.providers.push(config),
+            Err(e) => set
+                .errors
+                .push(format!("Provider #{} ({}): {}", index + 1, config.id, e)),
+        }
+    }
+
+    set
+}
+
+fn validate(config: &CustomProviderConfig) -> Result<(), String> {
+    let valid_id = !config.id.is_empty()
+        && config
+            .id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid_id {
+        return Err("id must be non-empty lowercase letters, digits, '-' or '_'".to_string());
+    }
+    if config.command.trim().is_empty() {
+        return Err("command is empty".to_string());
+    }
+
+    for arg in &config.args {
+        for placeholder in placeholders(arg) {
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in \"{}\"",
+                    placeholder, arg
+                ));
+            }
+        }
+    }
+    let prompt_in_args = config.args.iter().any(|arg| arg.contains("{prompt}"));
+    if !config.prompt_on_stdin && !prompt_in_args {
+        return Err(
+            "the prompt is never passed: add {prompt} to args or set promptOnStdin".to_string(),
+        );
+    }
+
+    let pointers: Vec<&str> = match &config.output {
+        OutputRule::Text => Vec::new(),
+        OutputRule::Json { pointer } => vec![pointer],
+        OutputRule::Jsonl { pointer, filter } => {
+            let mut pointers = vec![pointer.as_str()];
+            if let Some(filter) = filter {
+                pointers.push(&filter.pointer);
+            }
+            pointers
+        }
+    };
+    for pointer in pointers {
+        if !pointer.is_empty() && !pointer.starts_with('/') {
+            return Err(format!(
+                "\"{}\" is not a JSON pointer (must start with '/')",
+                pointer
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Identifier-like names inside `{...}` in an argument template; other braces (e.g. inline JSON) are left alone
+fn placeholders(arg: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        match rest[start + 1..].find('}') {
+            Some(end) => {
+                let name = &rest[start + 1..start + 1 + end];
+                if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    names.push(name);
+                }
+                rest = &rest[start + 2 + end..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+pub struct CustomProvider {
+    config: CustomProviderConfig,
+}
+
+impl CustomProvider {
+    pub fn new(config: CustomProviderConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl AiProvider for CustomProvider {
+    fn id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn command(&self) -> &str {
+        &self.config.command
+    }
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String> {
+        let model = request
+            .model
+            .as_deref()
+            .or(self.config.default_model.as_deref());
+
+        let mut args: Vec<String> = Vec::new();
+        for arg in &self.config.args {
+            if arg.contains("{model}") && model.is_none() {
+                // Without a model, drop the argument along with a flag right before it
+                if arg == "{model}" && args.last().is_some_and(|prev| prev.starts_with('-')) {
+                    args.pop();
+                }
+                continue;
+            }
+            args.push(
+                arg.replace("{model}", model.unwrap_or_default())
+                    .replace("{prompt}", &request.prompt),
+            );
+        }
+        args
+    }
+
+    fn env(&self, _request: &ProviderRequest) -> Vec<(String, String)> {
+        self.config
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn stdin_input(&self, request: &ProviderRequest) -> Option<String> {
+        if self.config.prompt_on_stdin {
+            Some(request.prompt.clone())
+        } else {
+            None
+        }
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(RuleParser {
+            rule: self.config.output.clone(),
+            writer: BlockWriter::default(),
+            buffer: String::new(),
+            in_text_block: false,
+        })
+    }
+}
+
+struct RuleParser {
+    rule: OutputRule,
+    writer: BlockWriter,
+    /// Whole stdout for the `json` rule, which can only be parsed at the end
+    buffer: String,
+    in_text_block: bool,
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl OutputParser for RuleParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        match &self.rule {
+            OutputRule::Text => {
+                let mut events = Vec::new();
+                if !self.in_text_block {
+                    self.in_text_block = true;
+                    events.extend(self.writer.open_text());
+                }
+                events.extend(self.writer.text_delta(&format!("{}\n", line)));
+                events
+            }
+            OutputRule::Json { .. } => {
+                self.buffer.push_str(line);
+                self.buffer.push('\n');
+                Vec::new()
+            }
+            OutputRule::Jsonl { pointer, filter } => {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    return Vec::new();
+                }
+                let value = match serde_json::from_str::<Value>(trimmed) {
+                    Ok(value) => value,
+                    Err(_) => return self.writer.raw_line(line),
+                };
+                if let Some(filter) = filter {
+                    if value.pointer(&filter.pointer) != Some(&filter.equals) {
+                        return Vec::new();
+                    }
+                }
+                match value.pointer(pointer).map(value_text) {
+                    Some(text) if !text.is_empty() => self.writer.text_block(&text),
+                    _ => Vec::new(),
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        match &self.rule {
+            OutputRule::Text if self.in_text_block => {
+                self.in_text_block = false;
+                vec![ContentEvent::BlockStop]
+            }
+            OutputRule::Json { pointer } => {
+                let output = std::mem::take(&mut self.buffer);
+                if output.trim().is_empty() {
+                    return Vec::new();
+                }
+                let text = serde_json::from_str::<Value>(&output)
+                    .ok()
+                    .and_then(|value| value.pointer(pointer).map(value_text));
+                // Fall back to the raw output when the pointer doesn't match
+                self.writer.text_block(text.as_deref().unwrap_or(&output))
+            }
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use serde_json::Value;
+
+use super::{
+    classify_common_error, AiProvider, BlockWriter, ContentEvent, OutputParser, ProviderErrorKind,
+    ProviderRequest, TokenUsage,
+};
+
+/// Gemini CLI in headless mode with `--output-format stream-json`
+pub struct GeminiProvider;
+
+impl AiProvider for GeminiProvider {
+    fn id(&self) -> &str {
+        "gemini"
+    }
+
+    fn command(&self) -> &str {
+        "gemini"
+    }
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(model) = &request.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+        // With the prompt on stdin, -p is appended to it and so is left empty
+        let prompt = if request.prompt_on_stdin {
+            String::new()
+        } else {
+            prompt(request)
+        };
+        args.extend([
+            "-p".to_string(),
+            prompt,
+            "--output-format".to_string(),
+            "stream-json".to_string(),
+        ]);
+        // Only the gh proxy may run without confirmation, mirroring Claude's allowed tools,
+        // and by its full path so the shell's PATH can't swap in the real gh
+        if let Some(gh) = &request.gh_command {
+            args.push("--allowed-tools".to_string());
+            args.push(format!("run_shell_command({})", gh));
+        }
+        args
+    }
+
+    fn stdin_input(&self, request: &ProviderRequest) -> Option<String> {
+        request.prompt_on_stdin.then(|| prompt(request))
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(GeminiParser::default())
+    }
+
+    fn classify_error(&self, _exit_code: Option<i32>, output: &str) -> ProviderErrorKind {
+        let message = output.to_lowercase();
+        if message.contains("gemini_api_key") || message.contains("login required") {
+            ProviderErrorKind::NotAuthenticated
+        } else if message.contains("resource_exhausted") || message.contains("quota") {
+            ProviderErrorKind::RateLimited
+        } else {
+            classify_common_error(output)
+        }
+    }
+}
+
+/// The request's prompt, prefixed with how to run the gh proxy; Gemini CLI has no flag to
+/// add to the system prompt
+fn prompt(request: &ProviderRequest) -> String {
+    match &request.gh_command {
+        Some(gh) => format!(
+            "Run the GitHub CLI by its full path, {}; plain `gh` is not permitted.\n\n{}",
+            gh, request.prompt
+        ),
+        None => request.prompt.clone(),
+    }
+}
+
+#[derive(Default)]
+struct GeminiParser {
+    writer: BlockWriter,
+    /// Assistant messages stream as deltas until a tool call or the result closes them
+    in_text_block: bool,
+    errors: Vec<String>,
+}
+
+impl OutputParser for GeminiParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Vec::new();
+        }
+
+        let json_value = match serde_json::from_str::<Value>(trimmed) {
+            Ok(value) => value,
+            Err(_) => return self.writer.raw_line(line),
+        };
+
+        match json_value.get("type").and_then(|v| v.as_str()) {
+            // {"type":"message","role":"assistant","content":"...","delta":true}
+            Some("message") => {
+                if json_value.get("role").and_then(|v| v.as_str()) != Some("assistant") {
+                    return Vec::new();
+                }
+                let content = match json_value.get("content").and_then(|v| v.as_str()) {
+                    Some(content) => content,
+                    None => return Vec::new(),
+                };
+                let mut events = Vec::new();
+                if !self.in_text_block {
+                    self.in_text_block = true;
+                    events.extend(self.writer.open_text());
+                }
+                events.extend(self.writer.text_delta(content));
+                events
+            }
+            Some("tool_use") => self.close_text(),
+            Some("error") => {
+                if let Some(message) = json_value.get("message").and_then(|v| v.as_str()) {
+                    self.errors.push(message.to_string());
+                }
+                Vec::new()
+            }
+            Some("result") => {
+                if let Some(message) = json_value
+                    .get("error")
+                    .and_then(|e| e.get("message"))
+                    .and_then(|v| v.as_str())
+                {
+                    self.errors.push(message.to_string());
+                }
+                let mut events = self.close_text();
+                // {"type":"result","stats":{"input_tokens":...,"output_tokens":...}}
+                if let Some(stats) = json_value
+                    .get("stats")
+                    .filter(|s| s.get("input_tokens").is_some())
+                {
+                    let tokens = |key: &str| stats.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+                    events.push(ContentEvent::Usage(TokenUsage {
+                        input_tokens: tokens("input_tokens"),
+                        output_tokens: tokens("output_tokens"),
+                        ..Default::default()
+                    }));
+                }
+                events
+            }
+            Some(_) => Vec::new(),
+            // --output-format json: {"response":"...","stats":{...}}
+            None => {
+                if let Some(message) = json_value
+                    .get("error")
+                    .and_then(|e| e.get("message"))
+                    .and_then(|v| v.as_str())
+                {
+                    self.errors.push(message.to_string());
+                }
+                match json_value.get("response").and_then(|v| v.as_str()) {
+                    Some(text) if !text.is_empty() => self.writer.text_block(text),
+                    _ => Vec::new(),
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        self.close_text()
+    }
+
+    fn error_output(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
+        }
+    }
+}
+
+impl GeminiParser {
+    fn close_text(&mut self) -> Vec<ContentEvent> {
+        if self.in_text_block {
+            self.in_text_block = false;
+            vec![ContentEvent::BlockStop]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_only_the_gh_proxy() {
+        let mut request = ProviderRequest {
+            prompt: "Review PR #7".to_string(),
+            model: Some("gemini-2.5-pro".to_string()),
+            gh_command: Some("/cache/gh-shim/gh".to_string()),
+            ..Default::default()
+        };
+        let args = GeminiProvider.build_args(&request);
+        assert_eq!(
+            args[..4],
+            ["--model", "gemini-2.5-pro", "-p", &prompt(&request)]
+        );
+        assert!(args[3].starts_with("Run the GitHub CLI by its full path, /cache/gh-shim/gh"));
+        assert!(args[3].ends_with("Review PR #7"));
+        assert_eq!(
+            args[4..],
+            [
+                "--output-format",
+                "stream-json",
+                "--allowed-tools",
+                "run_shell_command(/cache/gh-shim/gh)"
+            ]
+        );
+        assert_eq!(GeminiProvider.stdin_input(&request), None);
+
+        request.gh_command = None;
+        let args = GeminiProvider.build_args(&request);
+        assert!(!args.iter().any(|arg| arg.contains("allowed-tools")));
+        assert_eq!(args[3], "Review PR #7");
+    }
+
+    #[test]
+    fn sends_the_prompt_on_stdin() {
+        let request = ProviderRequest {
+            prompt: "Review PR #7".to_string(),
+            prompt_on_stdin: true,
+            gh_command: Some("/cache/gh-shim/gh".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            GeminiProvider.build_args(&request),
+            [
+                "-p",
+                "",
+                "--output-format",
+                "stream-json",
+                "--allowed-tools",
+                "run_shell_command(/cache/gh-shim/gh)"
+            ]
+        );
+        let input = GeminiProvider.stdin_input(&request).unwrap();
+        assert!(input.starts_with("Run the GitHub CLI by its full path, /cache/gh-shim/gh"));
+        assert!(input.ends_with("Review PR #7"));
+    }
+}
@@ -0,0 +1,463 @@
+//! Adapters for the AI CLIs Lyon can drive.
+//!
+//! Each provider owns how its CLI is invoked (arguments, environment, stdin), how its
+//! stdout is turned into `ContentEvent`s, and how its failures are classified.
+
+use serde::{Deserialize, Serialize};
+
+mod anthropic_api;
+mod claude;
+mod codex;
+mod custom;
+mod gemini;
+mod openai_compat;
+mod opencode;
+
+pub use anthropic_api::AnthropicApiProvider;
+pub use claude::ClaudeProvider;
+pub use codex::CodexProvider;
+pub use custom::{load_custom_providers, CustomProvider, CustomProviderConfig, CustomProviderSet};
+pub use gemini::GeminiProvider;
+pub use openai_compat::OpenAiCompatProvider;
+pub use opencode::OpencodeProvider;
+
+/// A single piece of model output, forwarded to the frontend as an `AIEvent`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentEvent {
+    ThinkingStart,
+    ThinkingDelta(String),
+    TextDelta(String),
+    BlockStop,
+    /// Token usage reported by the provider
+    Usage(TokenUsage),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub cost_usd: Option<f64>,
+}
+
+/// What the frontend asks a provider to run.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderRequest {
+    pub prompt: String,
+    pub model: Option<String>,
+    pub reasoning_effort: Option<String>,
+    /// Endpoint override for HTTP providers
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Repository under review, recorded with the run's usage
+    #[serde(default)]
+    pub repository: Option<String>,
+    /// PR under review; with `repository`, the only one the agent's `gh` may read
+    #[serde(default)]
+    pub pr_number: Option<u64>,
+    /// Send the prompt on stdin instead of as an argument, for prompts too long for the
+    /// command line
+    #[serde(default)]
+    pub prompt_on_stdin: bool,
+    /// Full path of the read-only `gh` proxy, set by the backend when it is installed
+    #[serde(skip)]
+    pub gh_command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorKind {
+    NotInstalled,
+    NotAuthenticated,
+    RateLimited,
+    ContextTooLong,
+    Failed,
+}
+
+impl ProviderErrorKind {
+    pub fn describe(&self, command: &str) -> String {
+        match self {
+            ProviderErrorKind::NotInstalled => {
+                format!("{} CLI is not installed or not available on PATH", command)
+            }
+            ProviderErrorKind::NotAuthenticated => format!(
+                "{} CLI is not authenticated. Please run \"{} auth\" to authenticate.",
+                command, command
+            ),
+            ProviderErrorKind::RateLimited => format!("{} hit a rate or usage limit", command),
+            ProviderErrorKind::ContextTooLong => {
+                format!(
+                    "The prompt is too long for the {} model's context window",
+                    command
+                )
+            }
+            ProviderErrorKind::Failed => format!("{} failed", command),
+        }
+    }
+
+    /// Like `describe`, worded for providers reached over HTTP
+    pub fn describe_http(&self, provider: &str) -> String {
+        match self {
+            ProviderErrorKind::NotInstalled => format!("Could not reach the {} server", provider),
+            ProviderErrorKind::NotAuthenticated => {
+                format!("{} rejected the request's credentials", provider)
+            }
+            _ => self.describe(provider),
+        }
+    }
+}
+
+/// Stateful, per-run parser for a provider's stdout.
+pub trait OutputParser: Send {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent>;
+
+    /// Close anything left open when the stream ends
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        Vec::new()
+    }
+
+    /// Error messages the CLI reported on stdout rather than stderr
+    fn error_output(&self) -> Option<String> {
+        None
+    }
+}
+
+pub trait AiProvider: Send + Sync {
+    /// Identifier used by the frontend, e.g. "claude"
+    fn id(&self) -> &str;
+
+    /// Executable to spawn
+    fn command(&self) -> &str;
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String>;
+
+    /// Extra environment variables; PATH is always set by the caller
+    fn env(&self, _request: &ProviderRequest) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Input written to the process's stdin, if the prompt isn't passed as an argument
+    fn stdin_input(&self, _request: &ProviderRequest) -> Option<String> {
+        None
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser>;
+
+    /// Arguments for a cheap invocation used to check the CLI is installed
+    fn version_args(&self) -> Vec<String> {
+        vec!["--version".to_string()]
+    }
+
+    /// Classify a failed run from its exit code and error output
+    fn classify_error(&self, _exit_code: Option<i32>, output: &str) -> ProviderErrorKind {
+        classify_common_error(output)
+    }
+}
+
+/// A provider reached over HTTP instead of by spawning a CLI.
+///
+/// Responses are server-sent events, fed line by line into the same `OutputParser`.
+pub trait HttpProvider: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &ProviderRequest,
+    ) -> Result<reqwest::RequestBuilder, String>;
+
+    fn output_parser(&self) -> Box<dyn OutputParser>;
+
+    /// Classify a failed request from its HTTP status and response body
+    fn classify_error(&self, status: Option<u16>, body: &str) -> ProviderErrorKind {
+        match status {
+            Some(401) | Some(403) => ProviderErrorKind::NotAuthenticated,
+            Some(429) => ProviderErrorKind::RateLimited,
+            None if body.to_lowercase().contains("connect") => ProviderErrorKind::NotInstalled,
+            _ => classify_common_error(body),
+        }
+    }
+}
+
+pub fn get_http_provider(id: &str) -> Option<Box<dyn HttpProvider>> {
+    match id {
+        "openai-compatible" => Some(Box::new(OpenAiCompatProvider)),
+        "anthropic-api" => Some(Box::new(AnthropicApiProvider)),
+        _ => None,
+    }
+}
+
+/// Built-in providers as (id, display name, kind)
+const BUILTIN_PROVIDERS: &[(&str, &str, &str)] = &[
+    ("claude", "Claude Code", "cli"),
+    ("codex", "Codex", "cli"),
+    ("gemini", "Gemini CLI", "cli"),
+    ("opencode", "opencode", "cli"),
+    ("openai-compatible", "OpenAI-compatible server", "http"),
+    ("anthropic-api", "Anthropic API", "http"),
+];
+
+pub fn builtin_provider_ids() -> Vec<&'static str> {
+    BUILTIN_PROVIDERS.iter().map(|(id, _, _)| *id).collect()
+}
+
+/// Resolve a CLI provider by id, falling back to user-defined ones
+pub fn get_provider(id: &str, custom: &[CustomProviderConfig]) -> Option<Box<dyn AiProvider>> {
+    match id {
+        "claude" => Some(Box::new(ClaudeProvider)),
+        "codex" => Some(Box::new(CodexProvider)),
+        "gemini" => Some(Box::new(GeminiProvider)),
+        "opencode" => Some(Box::new(OpencodeProvider)),
+        _ => custom
+            .iter()
+            .find(|config| config.id == id)
+            .map(|config| Box::new(CustomProvider::new(config.clone())) as Box<dyn AiProvider>),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: String,
+    pub name: String,
+    /// "cli", "http" or "custom"
+    pub kind: String,
+    pub command: Option<String>,
+}
+
+/// Providers available to the UI, plus any custom definitions that failed to load
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderList {
+    pub providers: Vec<ProviderInfo>,
+    pub errors: Vec<String>,
+}
+
+pub fn list_providers(custom: &CustomProviderSet) -> ProviderList {
+    let mut providers: Vec<ProviderInfo> = BUILTIN_PROVIDERS
+        .iter()
+        .map(|(id, name, kind)| ProviderInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            kind: kind.to_string(),
+            command: if *kind == "cli" {
+                Some(id.to_string())
+            } else {
+                None
+            },
+        })
+        .collect();
+    providers.extend(custom.providers.iter().map(|config| ProviderInfo {
+        id: config.id.clone(),
+        name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+        kind: "custom".to_string(),
+        command: Some(config.command.clone()),
+    }));
+
+    ProviderList {
+        providers,
+        errors: custom.errors.clone(),
+    }
+}
+
+/// Result of probing a provider's CLI, mirroring the frontend `AIProviderStatus`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderStatus {
+    pub installed: bool,
+    pub authenticated: bool,
+    pub version: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Error patterns shared by most CLIs
+pub fn classify_common_error(output: &str) -> ProviderErrorKind {
+    let message = output.to_lowercase();
+    if message.contains("os error 2")
+        || message.contains("no such file or directory")
+        || message.contains("cannot find the file")
+        || message.contains("not recognized")
+    {
+        ProviderErrorKind::NotInstalled
+    } else if message.contains("not logged in")
+        || message.contains("unauthorized")
+        || message.contains("invalid api key")
+        || message.contains("authentication")
+    {
+        ProviderErrorKind::NotAuthenticated
+    } else if message.contains("rate limit")
+        || message.contains("rate_limit")
+        || message.contains("usage limit")
+        || message.contains("too many requests")
+    {
+        ProviderErrorKind::RateLimited
+    } else if message.contains("context window")
+        || message.contains("context length")
+        || message.contains("prompt is too long")
+    {
+        ProviderErrorKind::ContextTooLong
+    } else {
+        ProviderErrorKind::Failed
+    }
+}
+
+/// Tracks text output across blocks so separate messages don't run together.
+#[derive(Default)]
+pub struct BlockWriter {
+    emitted_text: bool,
+}
+
+impl BlockWriter {
+    pub fn has_text(&self) -> bool {
+        self.emitted_text
+    }
+
+    /// A complete text block, separated from earlier text output
+    pub fn text_block(&mut self, text: &str) -> Vec<ContentEvent> {
+        let text = if self.emitted_text {
+            format!("\n\n{}", text)
+        } else {
+            text.to_string()
+        };
+        self.emitted_text = true;
+        vec![ContentEvent::TextDelta(text), ContentEvent::BlockStop]
+    }
+
+    /// Start of a text block whose content arrives as deltas
+    pub fn open_text(&mut self) -> Vec<ContentEvent> {
+        if self.emitted_text {
+            vec![ContentEvent::TextDelta("\n\n".to_string())]
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn text_delta(&mut self, text: &str) -> Vec<ContentEvent> {
+        self.emitted_text = true;
+        vec![ContentEvent::TextDelta(text.to_string())]
+    }
+
+    pub fn thinking_block(&self, text: &str) -> Vec<ContentEvent> {
+        vec![
+            ContentEvent::ThinkingStart,
+            ContentEvent::ThinkingDelta(text.to_string()),
+            ContentEvent::BlockStop,
+        ]
+    }
+
+    /// Non-JSON output: forward raw so nothing is silently dropped
+    pub fn raw_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        self.emitted_text = true;
+        vec![ContentEvent::TextDelta(format!("{}\n", line))]
+    }
+}
+
+/// A one-request HTTP server standing in for a provider's API, so HTTP providers can be
+/// tested end to end without network access
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use super::{ContentEvent, HttpProvider, ProviderErrorKind, ProviderRequest};
+
+    /// What happened when a provider's request was sent to the stand-in server
+    pub struct Exchange {
+        /// The raw request the server received, headers and body
+        pub request: String,
+        pub events: Vec<ContentEvent>,
+        /// The parser's error output, or the body of a failed response
+        pub error: Option<String>,
+        /// Classification of a failed response
+        pub error_kind: Option<ProviderErrorKind>,
+    }
+
+    /// Serve `body` with `status` (e.g. "200 OK") to the first connection; the thread
+    /// returns the request it received
+    fn serve_once(status: &str, body: &str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind a local port");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let content_type = if status.starts_with('2') {
+            "text/event-stream"
+        } else {
+            "application/json"
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        );
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("accept a connection");
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8_lossy(&body));
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (base_url, handle)
+    }
+
+    /// Send `request` to a server answering with `status` and `body`, parsing the response
+    /// line by line the way `spawn_http_stream` does. `request.base_url` is replaced with
+    /// the server's address.
+    pub async fn exchange(
+        provider: &dyn HttpProvider,
+        request: &ProviderRequest,
+        status: &str,
+        body: &str,
+    ) -> Exchange {
+        let (base_url, server) = serve_once(status, body);
+        let request = ProviderRequest {
+            base_url: Some(base_url),
+            ..request.clone()
+        };
+        let response = provider
+            .build_request(&reqwest::Client::new(), &request)
+            .expect("request builds")
+            .send()
+            .await
+            .expect("server responds");
+        let status = response.status();
+        let text = response.text().await.expect("response body");
+        let received = server.join().expect("server thread");
+
+        if !status.is_success() {
+            return Exchange {
+                request: received,
+                events: Vec::new(),
+                error_kind: Some(provider.classify_error(Some(status.as_u16()), &text)),
+                error: Some(text),
+            };
+        }
+        let mut parser = provider.output_parser();
+        let mut events: Vec<ContentEvent> = text
+            .lines()
+            .flat_map(|line| parser.parse_line(line))
+            .collect();
+        events.extend(parser.finish());
+        Exchange {
+            request: received,
+            events,
+            error: parser.error_output(),
+            error_kind: None,
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use serde_json::{json, Value};
+
+use super::{BlockWriter, ContentEvent, HttpProvider, OutputParser, ProviderRequest, TokenUsage};
+
+/// Ollama's OpenAI-compatible API; llama.cpp server and vLLM use `http://localhost:8000/v1` etc.
+const DEFAULT_BASE_URL: &str = "http://localhost:11434/v1";
+
+/// Any server implementing OpenAI's streaming `/v1/chat/completions`
+pub struct OpenAiCompatProvider;
+
+impl HttpProvider for OpenAiCompatProvider {
+    fn id(&self) -> &'static str {
+        "openai-compatible"
+    }
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &ProviderRequest,
+    ) -> Result<reqwest::RequestBuilder, String> {
+        let model = request
+            .model
+            .as_deref()
+            .ok_or("A model is required for OpenAI-compatible providers")?;
+        let base_url = request
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/');
+
+        let mut body = json!({
+            "model": model,
+            "stream": true,
+            // Adds a final chunk with token usage
+            "stream_options": { "include_usage": true },
+            "messages": [{ "role": "user", "content": request.prompt }],
+        });
+        if let Some(effort) = &request.reasoning_effort {
+            body["reasoning_effort"] = json!(effort);
+        }
+
+        let mut builder = client
+            .post(format!("{}/chat/completions", base_url))
+            .header("Accept", "text/event-stream")
+            .json(&body);
+        // Local servers usually don't need a key
+        if let Some(api_key) = &request.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        Ok(builder)
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(ChatCompletionsParser::default())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum OpenBlock {
+    #[default]
+    None,
+    Thinking,
+    Text,
+}
+
+#[derive(Default)]
+struct ChatCompletionsParser {
+    writer: BlockWriter,
+    open_block: OpenBlock,
+    errors: Vec<String>,
+}
+
+impl OutputParser for ChatCompletionsParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        // SSE: only `data:` lines carry payloads; comments and `event:` lines are ignored
+        let data = match line.trim().strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => return Vec::new(),
+        };
+        if data.is_empty() || data == "[DONE]" {
+            return Vec::new();
+        }
+
+        let json_value = match serde_json::from_str::<Value>(data) {
+            Ok(value) => value,
+            Err(_) => return Vec::new(),
+        };
+
+        if let Some(error) = json_value.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| error.to_string());
+            self.errors.push(message);
+            return Vec::new();
+        }
+
+        // The usage chunk has no choices: {"choices":[],"usage":{"prompt_tokens":...,"completion_tokens":...}}
+        let has_choices = json_value
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .is_some_and(|c| !c.is_empty());
+        let usage = json_value.get("usage").filter(|u| u.is_object());
+        if let (false, Some(usage)) = (has_choices, usage) {
+            let tokens = |value: Option<&Value>| value.and_then(|v| v.as_u64()).unwrap_or(0);
+            let cached = tokens(
+                usage
+                    .get("prompt_tokens_details")
+                    .and_then(|d| d.get("cached_tokens")),
+            );
+            return vec![ContentEvent::Usage(TokenUsage {
+                input_tokens: tokens(usage.get("prompt_tokens")).saturating_sub(cached),
+                output_tokens: tokens(usage.get("completion_tokens")),
+                cache_read_input_tokens: cached,
+                ..Default::default()
+            })];
+        }
+
+        let delta = match json_value
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("delta"))
+        {
+            Some(delta) => delta,
+            None => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+
+        // Thinking models expose reasoning as `reasoning_content` (vLLM, llama.cpp) or `reasoning` (Ollama)
+        let reasoning = delta
+            .get("reasoning_content")
+            .or_else(|| delta.get("reasoning"))
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty());
+        if let Some(reasoning) = reasoning {
+            if self.open_block != OpenBlock::Thinking {
+                events.extend(self.close_block());
+                self.open_block = OpenBlock::Thinking;
+                events.push(ContentEvent::ThinkingStart);
+            }
+            events.push(ContentEvent::ThinkingDelta(reasoning.to_string()));
+        }
+
+        let content = delta
+            .get("content")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty());
+        if let Some(content) = content {
+            if self.open_block != OpenBlock::Text {
+                events.extend(self.close_block());
+                self.open_block = OpenBlock::Text;
+                events.extend(self.writer.open_text());
+            }
+            events.extend(self.writer.text_delta(content));
+        }
+
+        events
+    }
+
+    fn finish(&mut self) -> Vec<ContentEvent> {
+        self.close_block()
+    }
+
+    fn error_output(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
+        }
+    }
+}
+
+impl ChatCompletionsParser {
+    fn close_block(&mut self) -> Vec<ContentEvent> {
+        if self.open_block == OpenBlock::None {
+            return Vec::new();
+        }
+        self.open_block = OpenBlock::None;
+        vec![ContentEvent::BlockStop]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::exchange;
+    use crate::providers::ProviderErrorKind;
+
+    /// Recorded from Ollama with a thinking model; `: ping` is an SSE comment
+    const STREAM: &str = r#": ping
+data: {"id":"1","choices":[{"index":0,"delta":{"role":"assistant","reasoning":"Checking the diff."}}]}
+
+data: {"id":"1","choices":[{"index":0,"delta":{"content":"{\"summary\":"}}]}
+
+data: {"id":"1","choices":[{"index":0,"delta":{"content":"\"ok\"}"}}]}
+
+data: {"id":"1","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}
+
+data: {"id":"1","choices":[],"usage":{"prompt_tokens":120,"completion_tokens":30,"prompt_tokens_details":{"cached_tokens":20}}}
+
+data: [DONE]
+"#;
+
+    fn request() -> ProviderRequest {
+        ProviderRequest {
+            prompt: "Review this".to_string(),
+            model: Some("qwen3".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn parse(stream: &str) -> (Vec<ContentEvent>, Option<String>) {
+        let mut parser = ChatCompletionsParser::default();
+        let mut events: Vec<ContentEvent> = stream
+            .lines()
+            .flat_map(|line| parser.parse_line(line))
+            .collect();
+        events.extend(parser.finish());
+        (events, parser.error_output())
+    }
+
+    #[test]
+    fn parses_reasoning_text_and_usage() {
+        let (events, error) = parse(STREAM);
+        assert_eq!(
+            events,
+            vec![
+                ContentEvent::ThinkingStart,
+                ContentEvent::ThinkingDelta("Checking the diff.".to_string()),
+                ContentEvent::BlockStop,
+                ContentEvent::TextDelta("{\"summary\":".to_string()),
+                ContentEvent::TextDelta("\"ok\"}".to_string()),
+                ContentEvent::Usage(TokenUsage {
+                    input_tokens: 100,
+                    output_tokens: 30,
+                    cache_read_input_tokens: 20,
+                    ..Default::default()
+                }),
+                ContentEvent::BlockStop,
+            ]
+        );
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn ignores_done_and_non_data_lines() {
+        let (events, error) = parse("data: [DONE]\nevent: message\n\ndata:\nid: 3\n");
+        assert!(events.is_empty());
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn reports_errors_sent_in_the_stream() {
+        let (events, error) = parse(
+            "data: {\"error\":{\"message\":\"model 'qwen3' not found\",\"type\":\"api_error\"}}\n\ndata: [DONE]\n",
+        );
+        assert!(events.is_empty());
+        assert_eq!(error.as_deref(), Some("model 'qwen3' not found"));
+    }
+
+    #[test]
+    fn requires_a_model() {
+        let request = ProviderRequest {
+            model: None,
+            ..request()
+        };
+        assert!(OpenAiCompatProvider
+            .build_request(&reqwest::Client::new(), &request)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_from_a_server() {
+        let request = ProviderRequest {
+            api_key: Some("sk-local".to_string()),
+            reasoning_effort: Some("high".to_string()),
+            ..request()
+        };
+        let exchange = exchange(&OpenAiCompatProvider, &request, "200 OK", STREAM).await;
+
+        assert!(exchange
+            .request
+            .starts_with("POST /chat/completions HTTP/1.1"));
+        assert!(exchange
+            .request
+            .to_lowercase()
+            .contains("authorization: bearer sk-local"));
+        let body: Value =
+            serde_json::from_str(exchange.request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["model"], "qwen3");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert_eq!(body["reasoning_effort"], "high");
+        assert_eq!(body["messages"][0]["content"], "Review this");
+
+        assert_eq!(exchange.events, parse(STREAM).0);
+        assert_eq!(exchange.error, None);
+    }
+
+    #[tokio::test]
+    async fn classifies_error_responses() {
+        let cases = [
+            (
+                "401 Unauthorized",
+                r#"{"error":{"message":"Incorrect API key provided"}}"#,
+                ProviderErrorKind::NotAuthenticated,
+            ),
+            (
+                "429 Too Many Requests",
+                r#"{"error":{"message":"Rate limit reached"}}"#,
+                ProviderErrorKind::RateLimited,
+            ),
+            (
+                "400 Bad Request",
+                r#"{"error":{"message":"This model's maximum context length is 8192 tokens"}}"#,
+                ProviderErrorKind::ContextTooLong,
+            ),
+            (
+                "404 Not Found",
+                r#"{"error":{"message":"model \"qwen3\" not found"}}"#,
+                ProviderErrorKind::Failed,
+            ),
+        ];
+        for (status, body, kind) in cases {
+            let exchange = exchange(&OpenAiCompatProvider, &request(), status, body).await;
+            assert_eq!(exchange.error_kind, Some(kind), "{}", status);
+            assert_eq!(exchange.error.as_deref(), Some(body));
+        }
+    }
+}
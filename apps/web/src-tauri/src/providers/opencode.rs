@@ -0,0 +1,175 @@
+use serde_json::Value;
+
+use super::{
+    classify_common_error, AiProvider, BlockWriter, ContentEvent, OutputParser, ProviderErrorKind,
+    ProviderRequest, TokenUsage,
+};
+
+/// opencode CLI via `opencode run --format json`
+pub struct OpencodeProvider;
+
+impl AiProvider for OpencodeProvider {
+    fn id(&self) -> &str {
+        "opencode"
+    }
+
+    fn command(&self) -> &str {
+        "opencode"
+    }
+
+    fn build_args(&self, request: &ProviderRequest) -> Vec<String> {
+        let mut args: Vec<String> = ["run", "--format", "json"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        // opencode models are "provider/model", e.g. "anthropic/claude-sonnet-4-5"
+        if let Some(model) = &request.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+        // Without a message argument, run reads the message from stdin
+        if !request.prompt_on_stdin {
+            args.push(request.prompt.clone());
+        }
+        args
+    }
+
+    fn stdin_input(&self, request: &ProviderRequest) -> Option<String> {
+        request.prompt_on_stdin.then(|| request.prompt.clone())
+    }
+
+    fn output_parser(&self) -> Box<dyn OutputParser> {
+        Box::new(OpencodeParser::default())
+    }
+
+    fn classify_error(&self, _exit_code: Option<i32>, output: &str) -> ProviderErrorKind {
+        let message = output.to_lowercase();
+        if message.contains("providerauth") || message.contains("opencode auth") {
+            ProviderErrorKind::NotAuthenticated
+        } else {
+            classify_common_error(output)
+        }
+    }
+}
+
+#[derive(Default)]
+struct OpencodeParser {
+    writer: BlockWriter,
+    errors: Vec<String>,
+}
+
+impl OutputParser for OpencodeParser {
+    fn parse_line(&mut self, line: &str) -> Vec<ContentEvent> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Vec::new();
+        }
+
+        let json_value = match serde_json::from_str::<Value>(trimmed) {
+            Ok(value) => value,
+            Err(_) => return self.writer.raw_line(line),
+        };
+
+        // Parts arrive complete: {"type":"text","part":{"type":"text","text":"..."}}
+        let part_text = json_value
+            .get("part")
+            .and_then(|p| p.get("text"))
+            .and_then(|v| v.as_str());
+
+        match json_value.get("type").and_then(|v| v.as_str()) {
+            Some("text") => match part_text {
+                Some(text) if !text.is_empty() => self.writer.text_block(text),
+                _ => Vec::new(),
+            },
+            Some("reasoning") => match part_text {
+                Some(text) if !text.is_empty() => self.writer.thinking_block(text),
+                _ => Vec::new(),
+            },
+            // {"type":"step_finish","part":{"cost":0.01,"tokens":{"input":...,"output":...,"reasoning":...,"cache":{"read":...,"write":...}}}}
+            Some("step_finish") => {
+                let part = json_value.get("part");
+                match part.and_then(|p| p.get("tokens")) {
+                    Some(tokens) => {
+                        let count =
+                            |value: Option<&Value>| value.and_then(|v| v.as_u64()).unwrap_or(0);
+                        let cache = tokens.get("cache");
+                        vec![ContentEvent::Usage(TokenUsage {
+                            input_tokens: count(tokens.get("input")),
+                            // Reasoning tokens are billed as output
+                            output_tokens: count(tokens.get("output"))
+                                + count(tokens.get("reasoning")),
+                            cache_creation_input_tokens: count(cache.and_then(|c| c.get("write"))),
+                            cache_read_input_tokens: count(cache.and_then(|c| c.get("read"))),
+                            cost_usd: part.and_then(|p| p.get("cost")).and_then(|v| v.as_f64()),
+                        })]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            Some("error") => {
+                // {"type":"error","error":{"name":"...","data":{"message":"..."}}}
+                let error = json_value.get("error");
+                let message = error
+                    .and_then(|e| e.get("data"))
+                    .and_then(|d| d.get("message"))
+                    .or_else(|| error.and_then(|e| e.get("message")))
+                    .and_then(|v| v.as_str());
+                let name = error.and_then(|e| e.get("name")).and_then(|v| v.as_str());
+                match (name, message) {
+                    (Some(name), Some(message)) => {
+                        self.errors.push(format!("{}: {}", name, message))
+                    }
+                    (None, Some(message)) => self.errors.push(message.to_string()),
+                    (Some(name), None) => self.errors.push(name.to_string()),
+                    (None, None) => {}
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn error_output(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_prompt_as_an_argument_or_on_stdin() {
+        let mut request = ProviderRequest {
+            prompt: "Review PR #7".to_string(),
+            model: Some("openai/gpt-5".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            OpencodeProvider.build_args(&request),
+            [
+                "run",
+                "--format",
+                "json",
+                "--model",
+                "openai/gpt-5",
+                "Review PR #7"
+            ]
+        );
+        assert_eq!(OpencodeProvider.stdin_input(&request), None);
+
+        request.prompt_on_stdin = true;
+        assert_eq!(
+            OpencodeProvider.build_args(&request),
+            ["run", "--format", "json", "--model", "openai/gpt-5"]
+        );
+        assert_eq!(
+            OpencodeProvider.stdin_input(&request).as_deref(),
+            Some("Review PR #7")
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Posting a batch of review comments as one pending review. The review and all of its
+//! comments are created by a single REST call; if checking or submitting it fails
+//! afterwards, the pending review is deleted so nothing is left half-posted.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{run_gh_command, run_gh_command_with_input};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchComment {
+    pub path: String,
+    /// Last line of the range the comment is on
+    pub line: u64,
+    #[serde(default = "default_side")]
+    pub side: String,
+    /// First line of a multi-line range
+    #[serde(default)]
+    pub start_line: Option<u64>,
+    #[serde(default)]
+    pub start_side: Option<String>,
+    /// Markdown, as posted
+    pub body: String,
+    /// Replacement for the commented lines, posted as a ```suggestion block
+    #[serde(default)]
+    pub suggestion: Option<String>,
+}
+
+fn default_side() -> String {
+    "RIGHT".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewEvent {
+    Comment,
+    Approve,
+    RequestChanges,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReviewRequest {
+    pub repository: String,
+    pub pr_number: u64,
+    /// Head commit the comment lines refer to
+    pub commit_id: String,
+    /// Review body, e.g. the comments that couldn't be anchored to the diff
+    #[serde(default)]
+    pub body: Option<String>,
+    pub comments: Vec<BatchComment>,
+    /// Submit the review right away; it stays pending otherwise
+    #[serde(default)]
+    pub event: Option<ReviewEvent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReviewResult {
+    pub review_id: u64,
+    /// GraphQL id, as used for the frontend's pending review
+    pub node_id: String,
+    pub state: String,
+    pub html_url: Option<String>,
+    pub comment_count: usize,
+}
+
+/// A code fence longer than any backtick run in `code`, so the code can't close it early
+pub fn fence_for(code: &str) -> String {
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn comment_body(comment: &BatchComment) -> String {
+    let suggestion = match &comment.suggestion {
+        Some(suggestion) => suggestion.trim_end_matches('\n'),
+        None => return comment.body.clone(),
+    };
+    let fence = fence_for(suggestion);
+    // Suggested changes only apply to the new side of the diff
+    let language = if comment.side == "RIGHT" {
+        "suggestion"
+    } else {
+        ""
+    };
+    format!(
+        "{}\n\n{}{}\n{}\n{}",
+        comment.body.trim_end(),
+        fence,
+        language,
+        suggestion,
+        fence
+    )
+}
+
+fn comment_payload(comment: &BatchComment) -> Result<Value, String> {
+    let side = comment.side.to_uppercase();
+    if side != "LEFT" && side != "RIGHT" {
+        return Err(format!(
+            "Invalid side {} for {}:{}",
+            comment.side, comment.path, comment.line
+        ));
+    }
+    let comment = BatchComment {
+        side,
+        ..comment.clone()
+    };
+
+    let mut payload = json!({
+        "path": comment.path,
+        "line": comment.line,
+        "side": comment.side,
+        "body": comment_body(&comment),
+    });
+    // GitHub wants the range in order and rejects single-line ranges
+    let range = comment
+        .start_line
+        .filter(|&start| start != comment.line)
+        .map(|start| (start.min(comment.line), start.max(comment.line)));
+    if let Some((start, end)) = range {
+        payload["start_line"] = json!(start);
+        payload["line"] = json!(end);
+        payload["start_side"] = json!(comment
+            .start_side
+            .as_deref()
+            .map(str::to_uppercase)
+            .unwrap_or_else(|| comment.side.clone()));
+    }
+    Ok(payload)
+}
+
+async fn gh_api(method: &str, endpoint: String, payload: Option<&Value>) -> Result<String, String> {
+    let mut args = vec![
+        "api".to_string(),
+        "--method".to_string(),
+        method.to_string(),
+        "-H".to_string(),
+        "Accept: application/vnd.github+json".to_string(),
+        endpoint,
+    ];
+    match payload {
+        Some(payload) => {
+            args.extend(["--input".to_string(), "-".to_string()]);
+            run_gh_command_with_input(args, payload.to_string()).await
+        }
+        None => run_gh_command(args).await,
+    }
+}
+
+/// Number of comments GitHub stored on a review
+async fn count_review_comments(reviews: &str, review_id: u64) -> Result<usize, String> {
+    let output = run_gh_command(vec![
+        "api".to_string(),
+        "--paginate".to_string(),
+        format!("{}/{}/comments?per_page=100", reviews, review_id),
+        "--jq".to_string(),
+        "length".to_string(),
+    ])
+    .await?;
+    // One count per page
+    Ok(output
+        .lines()
+        .filter_map(|line| line.trim().parse::<usize>().ok())
+        .sum())
+}
+
+/// Delete a review that is still pending, adding the outcome to `error`
+async fn roll_back(reviews: &str, review_id: u64, error: String) -> String {
+    match gh_api("DELETE", format!("{}/{}", reviews, review_id), None).await {
+        Ok(_) => format!("{}. The pending review was deleted.", error),
+        Err(e) => format!(
+            "{}. The pending review {} could not be deleted: {}",
+            error, review_id, e
+        ),
+    }
+}
+
+pub async fn post(request: BatchReviewRequest) -> Result<BatchReviewResult, String> {
+    let has_body = request
+        .body
+        .as_deref()
+        .is_some_and(|body| !body.trim().is_empty());
+    if request.comments.is_empty() && !has_body {
+        return Err("Nothing to post: no comments and no review body".to_string());
+    }
+    if request.commit_id.trim().is_empty() {
+        return Err("Posting a review needs the PR's head commit".to_string());
+    }
+
+    let comments = request
+        .comments
+        .iter()
+        .map(comment_payload)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut payload = json!({
+        "commit_id": request.commit_id,
+        "comments": comments,
+    });
+    if has_body {
+        payload["body"] = json!(request.body);
+    }
+
+    let reviews = format!(
+        "repos/{}/pulls/{}/reviews",
+        request.repository, request.pr_number
+    );
+    // Without an event the review is created pending, together with all its comments
+    let created = gh_api("POST", reviews.clone(), Some(&payload))
+        .await
+        .map_err(|e| {
+            if e.contains("one pending review") {
+                "You already have a pending review on this PR; submit or discard it first"
+                    .to_string()
+            } else {
+                format!("Failed to create the review: {}", e)
+            }
+        })?;
+    let created: Value = serde_json::from_str(&created)
+        .map_err(|e| format!("Failed to parse the created review: {}", e))?;
+    let review_id = created
+        .get("id")
+        .and_then(|v| v.as_u64())
+        .ok_or("GitHub returned a review without an id")?;
+    let field =
+        |value: &Value, key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+
+    match count_review_comments(&reviews, review_id).await {
+        Ok(count) if count == comments.len() => {}
+        Ok(count) => {
+            let error = format!(
+                "GitHub stored {} of {} comments on the review",
+                count,
+                comments.len()
+            );
+            return Err(roll_back(&reviews, review_id, error).await);
+        }
+        Err(e) => {
+            let error = format!("Failed to check the review's comments: {}", e);
+            return Err(roll_back(&reviews, review_id, error).await);
+        }
+    }
+
+    let mut state = field(&created, "state").unwrap_or_else(|| "PENDING".to_string());
+    if let Some(event) = request.event {
+        let mut submit = json!({ "event": event });
+        if has_body {
+            submit["body"] = json!(request.body);
+        }
+        match gh_api(
+            "POST",
+            format!("{}/{}/events", reviews, review_id),
+            Some(&submit),
+        )
+        .await
+        {
+            Ok(submitted) => {
+                state = serde_json::from_str::<Value>(&submitted)
+                    .ok()
+                    .and_then(|submitted| field(&submitted, "state"))
+                    .unwrap_or(state);
+            }
+            Err(e) => {
+                let error = format!("Failed to submit the review: {}", e);
+                return Err(roll_back(&reviews, review_id, error).await);
+            }
+        }
+    }
+
+    Ok(BatchReviewResult {
+        review_id,
+        node_id: field(&created, "node_id").unwrap_or_default(),
+        state,
+        html_url: field(&created, "html_url"),
+        comment_count: comments.len(),
+    })
+}
@@ -0,0 +1,505 @@
+//! Review prompts with the PR already in them. Instead of asking the model to run
+//! `gh pr view` and `gh pr diff` itself, the backend fetches the PR's metadata, diff, linked
+//! issues and the full changed files at head, and fits them into a token budget by priority:
+//! metadata and the response format always, then the diff, then linked issues, then whole
+//! files. The prompt is long, so it is sent to the provider on stdin.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::budgets::estimate_tokens;
+use crate::diff::{parse_unified_diff, FileDiff};
+use crate::map_reduce::REVIEW_FORMAT;
+use crate::review_batch::fence_for;
+use crate::rules::glob_regex;
+use crate::run_gh_command;
+use crate::secrets::{self, SecretFinding};
+
+pub const DEFAULT_CONTEXT_TOKENS: u64 = 80_000;
+/// PR descriptions and issue bodies longer than this are cut
+const MAX_BODY_CHARS: usize = 4_000;
+const MAX_LINKED_ISSUES: usize = 5;
+/// Files larger than this are left out even when the budget would allow them
+const MAX_FILE_BYTES: usize = 200_000;
+/// Whole files are fetched in batches of this size
+const FETCH_BATCH: usize = 4;
+/// Below this many tokens left, no more whole files are fetched
+const MIN_FILE_TOKENS: u64 = 200;
+
+const CLOSING_KEYWORDS: &[&str] = &[
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextOptions {
+    /// Token budget for the whole prompt
+    pub token_budget: Option<u64>,
+    /// Full content of changed files at head; on by default
+    pub include_file_contents: Option<bool>,
+    /// Bodies of the issues the PR closes; on by default
+    pub include_linked_issues: Option<bool>,
+    /// Globs of files left out entirely, e.g. from the repository's review policy
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionKind {
+    Metadata,
+    Diff,
+    LinkedIssue,
+    FileContent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextSection {
+    pub kind: SectionKind,
+    /// File path or issue reference
+    pub label: String,
+    pub estimated_tokens: u64,
+    /// Whether it fit the budget
+    pub included: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewContext {
+    pub prompt: String,
+    pub head_sha: String,
+    pub estimated_tokens: u64,
+    pub token_budget: u64,
+    pub sections: Vec<ContextSection>,
+    /// Secrets the diff adds; they are redacted from the prompt
+    pub secret_findings: Vec<SecretFinding>,
+    pub warnings: Vec<String>,
+}
+
+struct PrMetadata {
+    title: String,
+    body: String,
+    author: String,
+    base_ref: String,
+    head_ref: String,
+    head_sha: String,
+    /// `gh issue view` selectors: URLs from GitHub, or numbers found in the description
+    linked_issues: Vec<String>,
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}\n[... truncated]", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// `#123` references after a closing keyword, e.g. "Fixes #123" or "closes: #7"
+fn closing_references(body: &str) -> Vec<u64> {
+    let words: Vec<String> = body
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    let mut numbers = Vec::new();
+    for pair in words.windows(2) {
+        if !CLOSING_KEYWORDS.contains(&pair[0].trim_end_matches(':')) {
+            continue;
+        }
+        let number = pair[1]
+            .strip_prefix('#')
+            .map(|rest| rest.trim_end_matches(|c: char| !c.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<u64>().ok());
+        if let Some(number) = number {
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+    }
+    numbers
+}
+
+async fn fetch_metadata(
+    number: u64,
+    repository: &str,
+    warnings: &mut Vec<String>,
+) -> Result<PrMetadata, String> {
+    let view = |fields: &str| {
+        run_gh_command(
+            [
+                "pr",
+                "view",
+                &number.to_string(),
+                "--repo",
+                repository,
+                "--json",
+                fields,
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        )
+    };
+    let fields = "title,body,author,baseRefName,headRefName,headRefOid";
+    // Older gh versions don't know closingIssuesReferences; the description is searched
+    // for closing keywords instead
+    let (json, has_references) = match view(&format!("{},closingIssuesReferences", fields)).await {
+        Ok(json) => (json, true),
+        Err(_) => (view(fields).await?, false),
+    };
+    let value: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse PR #{}: {}", number, e))?;
+    let field = |key: &str| {
+        value
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let mut linked_issues: Vec<String> = value
+        .get("closingIssuesReferences")
+        .and_then(|v| v.as_array())
+        .map(|issues| {
+            issues
+                .iter()
+                .filter_map(|issue| issue.get("url").and_then(|v| v.as_str()))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let body = field("body");
+    if !has_references {
+        linked_issues.extend(closing_references(&body).iter().map(u64::to_string));
+    }
+    if linked_issues.len() > MAX_LINKED_ISSUES {
+        warnings.push(format!(
+            "Only the first {} of {} linked issues are included",
+            MAX_LINKED_ISSUES,
+            linked_issues.len()
+        ));
+        linked_issues.truncate(MAX_LINKED_ISSUES);
+    }
+
+    let head_sha = field("headRefOid");
+    if head_sha.is_empty() {
+        return Err(format!("PR #{} has no head commit", number));
+    }
+    Ok(PrMetadata {
+        title: field("title"),
+        body,
+        author: value
+            .get("author")
+            .and_then(|author| author.get("login"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        base_ref: field("baseRefName"),
+        head_ref: field("headRefName"),
+        head_sha,
+        linked_issues,
+    })
+}
+
+/// `#number title` and body of an issue
+async fn fetch_issue(selector: &str, repository: &str) -> Result<(String, String), String> {
+    let mut args = vec![
+        "issue".to_string(),
+        "view".to_string(),
+        selector.to_string(),
+    ];
+    if !selector.starts_with("https://") {
+        args.extend(["--repo".to_string(), repository.to_string()]);
+    }
+    args.extend(["--json".to_string(), "number,title,body".to_string()]);
+    let json = run_gh_command(args).await?;
+    let value: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse issue {}: {}", selector, e))?;
+    let number = value.get("number").and_then(|v| v.as_u64()).unwrap_or(0);
+    let title = value
+        .get("title")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let body = value
+        .get("body")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    Ok((format!("#{} {}", number, title), body.to_string()))
+}
+
+/// Percent-encode a repository path or ref for the contents API, keeping the slashes
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+async fn fetch_file(repository: &str, path: &str, sha: &str) -> Result<String, String> {
+    run_gh_command(vec![
+        "api".to_string(),
+        format!(
+            "repos/{}/contents/{}?ref={}",
+            repository,
+            encode_path(path),
+            sha
+        ),
+        "-H".to_string(),
+        "Accept: application/vnd.github.raw".to_string(),
+    ])
+    .await
+}
+
+fn header(instructions: &str, number: u64, repository: &str, pr: &PrMetadata) -> String {
+    let description = if pr.body.trim().is_empty() {
+        "(no description)".to_string()
+    } else {
+        truncate(pr.body.trim(), MAX_BODY_CHARS)
+    };
+    format!(
+        "{instructions}
+
+Review Pull Request #{number} in repository {repository}. Everything you need is included below; do not fetch the PR with the gh CLI.
+
+## Pull request
+Title: {title}
+Author: {author}
+Branch: {head_ref} -> {base_ref}
+Head commit: {head_sha}
+
+{description}
+",
+        title = pr.title,
+        author = pr.author,
+        head_ref = pr.head_ref,
+        base_ref = pr.base_ref,
+        head_sha = pr.head_sha,
+    )
+}
+
+/// Fetch a PR and assemble a review prompt for it under the options' token budget.
+/// `instructions` is the system prompt the context is appended to.
+pub async fn build(
+    repository: &str,
+    pr_number: u64,
+    instructions: &str,
+    options: &ContextOptions,
+) -> Result<ReviewContext, String> {
+    let budget = options.token_budget.unwrap_or(DEFAULT_CONTEXT_TOKENS);
+    let mut warnings = Vec::new();
+    let mut sections = Vec::new();
+
+    let pr = fetch_metadata(pr_number, repository, &mut warnings).await?;
+    let diff = run_gh_command(
+        ["pr", "diff", &pr_number.to_string(), "--repo", repository]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+    )
+    .await?;
+    let ignore = options
+        .ignore
+        .iter()
+        .map(|glob| glob_regex(glob))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (ignored, files): (Vec<FileDiff>, Vec<FileDiff>) = parse_unified_diff(&diff)
+        .into_iter()
+        .partition(|file| ignore.iter().any(|glob| glob.is_match(&file.path)));
+
+    let head = header(instructions, pr_number, repository, &pr);
+    let footer = format!("\n{}", REVIEW_FORMAT);
+    let required = estimate_tokens(&head) + estimate_tokens(&footer);
+    sections.push(ContextSection {
+        kind: SectionKind::Metadata,
+        label: format!("#{}", pr_number),
+        estimated_tokens: required,
+        included: true,
+    });
+    let mut remaining = budget.saturating_sub(required);
+    if required > budget {
+        warnings.push(format!(
+            "The instructions and PR description alone take about {} tokens, over the budget of {}",
+            required, budget
+        ));
+    }
+
+    // The diff is what is being reviewed, so it gets the budget first, file by file
+    let mut diff_text = String::new();
+    let mut omitted = Vec::new();
+    for file in &files {
+        let rendered = file.render();
+        let tokens = estimate_tokens(&rendered);
+        let included = tokens <= remaining;
+        if included {
+            remaining -= tokens;
+            diff_text.push_str(&rendered);
+        } else {
+            omitted.push(file.path.clone());
+        }
+        sections.push(ContextSection {
+            kind: SectionKind::Diff,
+            label: file.path.clone(),
+            estimated_tokens: tokens,
+            included,
+        });
+    }
+    if !omitted.is_empty() {
+        warnings.push(format!(
+            "The diff of {} of {} files didn't fit the budget; use a chunked review for this PR",
+            omitted.len(),
+            files.len()
+        ));
+    }
+
+    let mut issues_text = String::new();
+    if options.include_linked_issues.unwrap_or(true) {
+        for selector in &pr.linked_issues {
+            let (label, body) = match fetch_issue(selector, repository).await {
+                Ok(issue) => issue,
+                Err(e) => {
+                    warnings.push(format!("Failed to fetch linked issue {}: {}", selector, e));
+                    continue;
+                }
+            };
+            let text = format!(
+                "\n### {}\n{}\n",
+                label,
+                truncate(body.trim(), MAX_BODY_CHARS)
+            );
+            let tokens = estimate_tokens(&text);
+            let included = tokens <= remaining;
+            if included {
+                remaining -= tokens;
+                issues_text.push_str(&text);
+            }
+            sections.push(ContextSection {
+                kind: SectionKind::LinkedIssue,
+                label,
+                estimated_tokens: tokens,
+                included,
+            });
+        }
+    }
+
+    let mut files_text = String::new();
+    if options.include_file_contents.unwrap_or(true) {
+        files_text = add_file_contents(
+            repository,
+            &pr.head_sha,
+            &files,
+            &mut remaining,
+            &mut sections,
+            &mut warnings,
+        )
+        .await;
+    }
+
+    let mut prompt = head;
+    if !issues_text.is_empty() {
+        prompt.push_str("\n## Linked issues\n");
+        prompt.push_str(&issues_text);
+    }
+    // A diff that contains ``` itself (e.g. of a markdown file) must not end the fence early
+    let fence = fence_for(&diff_text);
+    prompt.push_str(&format!("\n## Diff\n{fence}diff\n{diff_text}{fence}\n"));
+    if !omitted.is_empty() {
+        prompt.push_str(&format!(
+            "\nThe diff of these files was left out for length: {}\n",
+            omitted.join(", ")
+        ));
+    }
+    if !ignored.is_empty() {
+        let paths: Vec<&str> = ignored.iter().map(|file| file.path.as_str()).collect();
+        prompt.push_str(&format!(
+            "\nThese files are excluded from review and were left out: {}\n",
+            paths.join(", ")
+        ));
+    }
+    if !files_text.is_empty() {
+        prompt.push_str("\n## Changed files at head, for context\n");
+        prompt.push_str(&files_text);
+    }
+    prompt.push_str(&footer);
+
+    let secret_findings = secrets::scan_diff(&files);
+    let (prompt, redacted) = secrets::redact(&prompt);
+    if redacted > 0 {
+        warnings.push(format!(
+            "Redacted {} secret(s) from the review context",
+            redacted
+        ));
+    }
+
+    Ok(ReviewContext {
+        estimated_tokens: estimate_tokens(&prompt),
+        prompt,
+        head_sha: pr.head_sha,
+        token_budget: budget,
+        sections,
+        secret_findings,
+        warnings,
+    })
+}
+
+/// Whole changed files, most-changed first, each added only if it fits what is left
+async fn add_file_contents(
+    repository: &str,
+    head_sha: &str,
+    files: &[FileDiff],
+    remaining: &mut u64,
+    sections: &mut Vec<ContextSection>,
+    warnings: &mut Vec<String>,
+) -> String {
+    let mut candidates: Vec<&FileDiff> = files
+        .iter()
+        .filter(|file| !file.binary && !file.deleted())
+        .collect();
+    candidates.sort_by_key(|file| std::cmp::Reverse(file.additions + file.deletions));
+
+    let mut text = String::new();
+    for batch in candidates.chunks(FETCH_BATCH) {
+        if *remaining < MIN_FILE_TOKENS {
+            break;
+        }
+        let fetches: Vec<_> = batch
+            .iter()
+            .map(|file| {
+                let repository = repository.to_string();
+                let path = file.path.clone();
+                let sha = head_sha.to_string();
+                tokio::spawn(async move { fetch_file(&repository, &path, &sha).await })
+            })
+            .collect();
+        for (file, fetch) in batch.iter().zip(fetches) {
+            let content = match fetch.await.map_err(|e| e.to_string()).and_then(|r| r) {
+                Ok(content) => content,
+                Err(e) => {
+                    warnings.push(format!("Failed to fetch {} at head: {}", file.path, e));
+                    continue;
+                }
+            };
+            // The whole file may hold secrets the diff doesn't touch, e.g. in a .env file
+            let (content, _) = secrets::redact_file(&file.path, &content);
+            let fence = fence_for(&content);
+            let section = format!("\n### {}\n{}\n{}\n{}\n", file.path, fence, content, fence);
+            let tokens = estimate_tokens(&section);
+            let included = content.len() <= MAX_FILE_BYTES && tokens <= *remaining;
+            if included {
+                *remaining -= tokens;
+                text.push_str(&section);
+            }
+            sections.push(ContextSection {
+                kind: SectionKind::FileContent,
+                label: file.path.clone(),
+                estimated_tokens: tokens,
+                included,
+            });
+        }
+    }
+    text
+}
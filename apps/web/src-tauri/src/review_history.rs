@@ -0,0 +1,100 @@
+//! The head commit each finished AI review was run against, with its findings, so a later
+//! review of the same PR can be limited to the commits pushed since. One record per review
+//! is appended to `ai-reviews.jsonl` in the app data dir.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::review_result::{ReviewComment, ReviewSuggestion};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewRecord {
+    pub review_id: String,
+    pub repository: String,
+    pub pr_number: u64,
+    /// Commit the review saw
+    pub head_sha: String,
+    pub provider: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Milliseconds since the Unix epoch; set when the record is added
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub overall_score: Option<f64>,
+    /// Findings still open at `head_sha`
+    #[serde(default)]
+    pub comments: Vec<ReviewComment>,
+    #[serde(default)]
+    pub suggestions: Vec<ReviewSuggestion>,
+}
+
+#[derive(Default)]
+pub struct ReviewHistory {
+    /// History file; nothing is written until it is set
+    path: OnceLock<PathBuf>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl ReviewHistory {
+    pub fn init(&self, path: PathBuf) {
+        let _ = self.path.set(path);
+    }
+
+    pub fn add(&self, mut record: ReviewRecord) -> Result<(), String> {
+        if record.head_sha.trim().is_empty() {
+            return Err("A review record needs the head commit it was run against".to_string());
+        }
+        record.created_at = now_ms();
+
+        let path = match self.path.get() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut json = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+        json.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .map_err(|e| format!("Failed to record review: {}", e))
+    }
+
+    /// The most recent review of a PR
+    pub fn latest(&self, repository: &str, pr_number: u64) -> Result<Option<ReviewRecord>, String> {
+        let path = match self.path.get() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        // Records are appended in order, so the last match is the newest
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<ReviewRecord>(&line).ok())
+            .filter(|record| record.repository == repository && record.pr_number == pr_number)
+            .last())
+    }
+}
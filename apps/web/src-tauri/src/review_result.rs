@@ -0,0 +1,671 @@
+//! Extraction of the structured review from a model's free-form output. The text is searched
+//! for the JSON object the review prompt asks for; common defects are repaired and the result
+//! is validated against the shape of the frontend's `AIReviewResult`, with a warning recorded
+//! for every repair or dropped entry.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const SEVERITIES: &[&str] = &["critical", "warning", "info", "suggestion"];
+const CATEGORIES: &[&str] = &[
+    "security",
+    "performance",
+    "best-practices",
+    "code-style",
+    "documentation",
+    "testing",
+    "architecture",
+];
+const DEFAULT_CATEGORY: &str = "best-practices";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewComment {
+    pub path: String,
+    pub line: u64,
+    pub side: String,
+    pub severity: String,
+    pub category: String,
+    pub body: String,
+    pub suggestion: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewSuggestion {
+    pub path: String,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub original_code: String,
+    pub suggested_code: String,
+    pub explanation: String,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedReview {
+    /// False when no JSON object could be recovered; `summary` then holds the raw output
+    pub found_json: bool,
+    pub summary: Option<String>,
+    pub overall_score: Option<f64>,
+    pub comments: Vec<ReviewComment>,
+    pub suggestions: Vec<ReviewSuggestion>,
+    /// Repairs applied and entries dropped while parsing
+    pub warnings: Vec<String>,
+    /// Top-level fields outside the review schema, for prompts that ask for more
+    #[serde(skip)]
+    pub extra: Map<String, Value>,
+}
+
+/// Parse a review from model output that may contain thinking, prose and code fences
+pub fn parse_review(output: &str) -> ParsedReview {
+    let mut warnings = Vec::new();
+
+    let candidates = find_candidates(output);
+    // The answer comes last, after any thinking that might quote example JSON, and
+    // objects that look like a review beat arbitrary ones
+    let ordered = candidates
+        .iter()
+        .rev()
+        .filter(|c| c.looks_like_review)
+        .chain(candidates.iter().rev().filter(|c| !c.looks_like_review));
+
+    for candidate in ordered {
+        let mut repairs = Vec::new();
+        if let Some(Value::Object(object)) = parse_with_repairs(&candidate.text, &mut repairs) {
+            if candidate.fenced {
+                warnings.push("Removed markdown code fence around the JSON".to_string());
+            }
+            warnings.extend(repairs);
+            return validate(object, warnings);
+        }
+    }
+
+    warnings.push("No JSON object found in the response".to_string());
+    let trimmed = output.trim();
+    ParsedReview {
+        found_json: false,
+        summary: Some(if trimmed.is_empty() {
+            "No response received from AI".to_string()
+        } else {
+            trimmed.to_string()
+        }),
+        warnings,
+        ..Default::default()
+    }
+}
+
+struct Candidate {
+    text: String,
+    /// Offset in the output; an object inside a fenced block takes the fence's
+    position: usize,
+    fenced: bool,
+    looks_like_review: bool,
+}
+
+impl Candidate {
+    fn new(text: &str, position: usize, fenced: bool) -> Self {
+        Self {
+            looks_like_review: text.contains("\"summary\"") || text.contains("\"comments\""),
+            text: text.to_string(),
+            position,
+            fenced,
+        }
+    }
+}
+
+/// Fenced blocks that hold an object and every top-level `{...}` in the text, in the
+/// order they appear. A fenced block comes after the objects inside it, so it is tried
+/// first from the end.
+fn find_candidates(output: &str) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut fences = Vec::new();
+
+    let mut rest = output;
+    while let Some(open) = rest.find("```") {
+        let fence_start = output.len() - rest.len() + open;
+        let after_fence = &rest[open + 3..];
+        // Skip the info string (e.g. "json") up to the end of the line
+        let body_start = after_fence
+            .find('\n')
+            .map(|i| i + 1)
+            .unwrap_or(after_fence.len());
+        let body = &after_fence[body_start..];
+        let (content, next) = match body.find("```") {
+            Some(close) => (&body[..close], &body[close + 3..]),
+            // An unclosed fence usually means the output was cut off
+            None => (body, ""),
+        };
+        if content.trim_start().starts_with('{') {
+            candidates.push(Candidate::new(content.trim(), fence_start, true));
+            fences.push(fence_start..output.len() - next.len());
+        }
+        rest = next;
+    }
+
+    for (start, span) in object_spans(output) {
+        let position = fences
+            .iter()
+            .find(|fence| fence.contains(&start))
+            .map_or(start, |fence| fence.start);
+        candidates.push(Candidate::new(span, position, false));
+    }
+    candidates.sort_by_key(|c| (c.position, c.fenced));
+    candidates
+}
+
+/// Top-level `{...}` spans and their offsets, ignoring braces inside JSON strings. An
+/// object still open at the end of the text runs to the end, so truncated output can
+/// still be repaired; the search then restarts after its brace, which may have been
+/// stray prose in front of the answer.
+fn object_spans(text: &str) -> Vec<(usize, &str)> {
+    let mut spans = Vec::new();
+    let mut from = 0;
+    loop {
+        let mut depth = 0usize;
+        let mut start = from;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for (i, c) in text[from..].char_indices() {
+            let i = from + i;
+            if depth == 0 {
+                if c == '{' {
+                    depth = 1;
+                    start = i;
+                }
+                continue;
+            }
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        spans.push((start, &text[start..=i]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if depth == 0 {
+            return spans;
+        }
+        spans.push((start, &text[start..]));
+        from = start + 1;
+    }
+}
+
+fn parse_with_repairs(text: &str, repairs: &mut Vec<String>) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(text) {
+        return Some(value);
+    }
+
+    let mut repaired = text.to_string();
+    if let Some(fixed) = escape_control_chars(&repaired) {
+        repairs.push("Escaped raw line breaks inside strings".to_string());
+        repaired = fixed;
+    }
+    if let Some(fixed) = remove_trailing_commas(&repaired) {
+        repairs.push("Removed trailing commas".to_string());
+        repaired = fixed;
+    }
+    if let Ok(value) = serde_json::from_str(&repaired) {
+        return Some(value);
+    }
+
+    for (fixed, repair) in close_truncated(&repaired) {
+        let fixed = remove_trailing_commas(&fixed).unwrap_or(fixed);
+        if let Ok(value) = serde_json::from_str(&fixed) {
+            repairs.push(repair);
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Raw newlines and tabs inside strings, which JSON forbids but models often emit
+fn escape_control_chars(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                '\n' | '\r' | '\t' => {
+                    changed = true;
+                    out.push_str(match c {
+                        '\n' => "\\n",
+                        '\r' => "\\r",
+                        _ => "\\t",
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        }
+        out.push(c);
+    }
+    changed.then_some(out)
+}
+
+fn remove_trailing_commas(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                changed = true;
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    changed.then_some(out)
+}
+
+/// Ways to complete JSON that was cut off: close everything where it stops, or drop the
+/// unfinished trailing entry and close the rest
+fn close_truncated(text: &str) -> Vec<(String, String)> {
+    let mut stack: Vec<char> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    // Last position where everything before it is a complete list of entries
+    let mut safe_cut: Option<(usize, Vec<char>)> = None;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => stack.push(c),
+            '}' | ']' => {
+                stack.pop();
+                if stack.is_empty() {
+                    // Balanced, so not truncated
+                    return Vec::new();
+                }
+                safe_cut = Some((i + c.len_utf8(), stack.clone()));
+            }
+            ',' => safe_cut = Some((i, stack.clone())),
+            _ => {}
+        }
+    }
+    if stack.is_empty() {
+        return Vec::new();
+    }
+
+    let closers = |stack: &[char]| -> String {
+        stack
+            .iter()
+            .rev()
+            .map(|open| if *open == '{' { '}' } else { ']' })
+            .collect()
+    };
+
+    let mut attempts = Vec::new();
+    let mut in_place = text.trim_end().to_string();
+    if in_string {
+        in_place.push('"');
+    }
+    in_place.push_str(&closers(&stack));
+    attempts.push((in_place, "Closed JSON that was cut off".to_string()));
+
+    if let Some((cut, stack)) = safe_cut {
+        let mut dropped = text[..cut].to_string();
+        dropped.push_str(&closers(&stack));
+        attempts.push((
+            dropped,
+            "Dropped an incomplete entry from JSON that was cut off".to_string(),
+        ));
+    }
+    attempts
+}
+
+fn validate(mut object: Map<String, Value>, mut warnings: Vec<String>) -> ParsedReview {
+    let summary = match object.remove("summary") {
+        Some(Value::String(summary)) => Some(summary),
+        Some(Value::Null) | None => {
+            warnings.push("Missing summary".to_string());
+            None
+        }
+        Some(other) => {
+            warnings.push("Summary was not a string".to_string());
+            Some(other.to_string())
+        }
+    };
+
+    let overall_score = match object.remove("overallScore") {
+        Some(value) => match as_f64(&value) {
+            Some(score) if (0.0..=10.0).contains(&score) => Some(score),
+            Some(score) => {
+                warnings.push(format!("Clamped overallScore {} to 0-10", score));
+                Some(score.clamp(0.0, 10.0))
+            }
+            None => {
+                warnings.push("Ignored non-numeric overallScore".to_string());
+                None
+            }
+        },
+        None => None,
+    };
+
+    let comments = match object.remove("comments") {
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, item)| match comment_from_value(item, &mut warnings) {
+                Ok(comment) => Some(comment),
+                Err(e) => {
+                    warnings.push(format!("Dropped comment #{}: {}", i + 1, e));
+                    None
+                }
+            })
+            .collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(_) => {
+            warnings.push("Ignored comments that were not an array".to_string());
+            Vec::new()
+        }
+    };
+
+    let suggestions = match object.remove("suggestions") {
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .enumerate()
+            .filter_map(
+                |(i, item)| match suggestion_from_value(item, &mut warnings) {
+                    Ok(suggestion) => Some(suggestion),
+                    Err(e) => {
+                        warnings.push(format!("Dropped suggestion #{}: {}", i + 1, e));
+                        None
+                    }
+                },
+            )
+            .collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(_) => {
+            warnings.push("Ignored suggestions that were not an array".to_string());
+            Vec::new()
+        }
+    };
+
+    ParsedReview {
+        found_json: true,
+        summary,
+        overall_score,
+        comments,
+        suggestions,
+        warnings,
+        extra: object,
+    }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_line(value: Option<&Value>) -> Option<u64> {
+    value
+        .and_then(as_f64)
+        .filter(|n| *n >= 1.0)
+        .map(|n| n as u64)
+}
+
+fn string_field(object: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .find_map(|key| object.get(*key).and_then(|v| v.as_str()))
+        .map(str::to_string)
+}
+
+fn comment_from_value(value: Value, warnings: &mut Vec<String>) -> Result<ReviewComment, String> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err("not an object".to_string()),
+    };
+    let path = string_field(&object, &["path", "file"])
+        .filter(|path| !path.trim().is_empty())
+        .ok_or("missing path")?;
+    let line = as_line(object.get("line").or_else(|| object.get("startLine")))
+        .ok_or("missing or invalid line")?;
+    let body = string_field(&object, &["body", "comment", "message"])
+        .filter(|body| !body.trim().is_empty())
+        .ok_or("missing body")?;
+
+    let side = match string_field(&object, &["side"]).map(|s| s.to_uppercase()) {
+        Some(side) if side == "LEFT" => side,
+        _ => "RIGHT".to_string(),
+    };
+
+    Ok(ReviewComment {
+        severity: normalize_severity(string_field(&object, &["severity"]), warnings),
+        category: normalize_category(string_field(&object, &["category"]), warnings),
+        suggestion: string_field(&object, &["suggestion"]).filter(|s| !s.trim().is_empty()),
+        path,
+        line,
+        side,
+        body,
+    })
+}
+
+fn suggestion_from_value(
+    value: Value,
+    warnings: &mut Vec<String>,
+) -> Result<ReviewSuggestion, String> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err("not an object".to_string()),
+    };
+    let path = string_field(&object, &["path", "file"])
+        .filter(|path| !path.trim().is_empty())
+        .ok_or("missing path")?;
+    let start_line = as_line(object.get("startLine").or_else(|| object.get("line")))
+        .ok_or("missing or invalid startLine")?;
+    let end_line = as_line(object.get("endLine"))
+        .unwrap_or(start_line)
+        .max(start_line);
+    let suggested_code =
+        string_field(&object, &["suggestedCode", "suggestion"]).ok_or("missing suggestedCode")?;
+
+    Ok(ReviewSuggestion {
+        original_code: string_field(&object, &["originalCode"]).unwrap_or_default(),
+        explanation: string_field(&object, &["explanation", "body"]).unwrap_or_default(),
+        category: normalize_category(string_field(&object, &["category"]), warnings),
+        path,
+        start_line,
+        end_line,
+        suggested_code,
+    })
+}
+
+fn normalize_severity(severity: Option<String>, warnings: &mut Vec<String>) -> String {
+    let raw = match severity {
+        Some(raw) => raw,
+        None => return "info".to_string(),
+    };
+    let lower = raw.trim().to_lowercase();
+    if SEVERITIES.contains(&lower.as_str()) {
+        return lower;
+    }
+    let mapped = match lower.as_str() {
+        "error" | "high" | "blocker" | "major" | "severe" | "critical-security" => "critical",
+        "warn" | "medium" | "moderate" | "caution" => "warning",
+        "low" | "minor" | "note" | "information" | "informational" => "info",
+        "nit" | "nitpick" | "style" | "improvement" | "optional" | "enhancement" => "suggestion",
+        _ => {
+            warnings.push(format!("Unknown severity \"{}\" treated as info", raw));
+            return "info".to_string();
+        }
+    };
+    warnings.push(format!("Mapped severity \"{}\" to {}", raw, mapped));
+    mapped.to_string()
+}
+
+fn normalize_category(category: Option<String>, warnings: &mut Vec<String>) -> String {
+    let raw = match category {
+        Some(raw) => raw,
+        None => return DEFAULT_CATEGORY.to_string(),
+    };
+    let normalized = raw.trim().to_lowercase().replace(['_', ' '], "-");
+    if CATEGORIES.contains(&normalized.as_str()) {
+        if normalized != raw {
+            warnings.push(format!("Mapped category \"{}\" to {}", raw, normalized));
+        }
+        return normalized;
+    }
+    let mapped = match normalized.as_str() {
+        "style" | "formatting" | "readability" | "naming" => "code-style",
+        "docs" | "comments" => "documentation",
+        "tests" | "test" | "test-coverage" => "testing",
+        "perf" | "efficiency" => "performance",
+        "design" | "structure" => "architecture",
+        "correctness" | "bug" | "bugs" | "logic" | "maintainability" | "error-handling" => {
+            "best-practices"
+        }
+        _ => {
+            warnings.push(format!(
+                "Unknown category \"{}\" treated as {}",
+                raw, DEFAULT_CATEGORY
+            ));
+            return DEFAULT_CATEGORY.to_string();
+        }
+    };
+    warnings.push(format!("Mapped category \"{}\" to {}", raw, mapped));
+    mapped.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn has_warning(review: &ParsedReview, warning: &str) -> bool {
+        review.warnings.iter().any(|w| w == warning)
+    }
+
+    #[test]
+    fn unwraps_fenced_json() {
+        let review = parse_review(
+            "Here is my review:\n```json\n{\"summary\": \"Looks good\", \"comments\": []}\n```\n",
+        );
+        assert!(review.found_json);
+        assert_eq!(review.summary.as_deref(), Some("Looks good"));
+        assert!(has_warning(
+            &review,
+            "Removed markdown code fence around the JSON"
+        ));
+    }
+
+    #[test]
+    fn finds_json_wrapped_in_prose() {
+        let review = parse_review(
+            "I read the diff. {\"summary\": \"Fine\", \"overallScore\": 8} Hope that helps!",
+        );
+        assert_eq!(review.summary.as_deref(), Some("Fine"));
+        assert_eq!(review.overall_score, Some(8.0));
+        assert!(review.warnings.is_empty());
+    }
+
+    #[test]
+    fn skips_a_stray_brace_before_the_answer() {
+        let review = parse_review(
+            "Replace the map with {key => value before merging.\n{\"summary\": \"Real\", \"comments\": []}",
+        );
+        assert!(review.found_json);
+        assert_eq!(review.summary.as_deref(), Some("Real"));
+    }
+
+    #[test]
+    fn removes_trailing_commas() {
+        let review = parse_review(
+            "{\"summary\": \"s\", \"comments\": [{\"path\": \"a.rs\", \"line\": 2, \"body\": \"b\",},],}",
+        );
+        assert_eq!(review.comments.len(), 1);
+        assert!(has_warning(&review, "Removed trailing commas"));
+    }
+
+    #[test]
+    fn repairs_truncated_json() {
+        let review = parse_review(
+            "{\"summary\": \"s\", \"comments\": [{\"path\": \"a.rs\", \"line\": 2, \"body\": \"ok\"}, {\"path\": \"b.rs\", \"li",
+        );
+        assert_eq!(review.summary.as_deref(), Some("s"));
+        assert_eq!(review.comments.len(), 1);
+        assert_eq!(review.comments[0].path, "a.rs");
+        assert!(review
+            .warnings
+            .iter()
+            .any(|w| w.ends_with("from JSON that was cut off")));
+    }
+
+    #[test]
+    fn escapes_raw_line_breaks_in_strings() {
+        let review = parse_review("{\"summary\": \"line one\nline two\", \"comments\": []}");
+        assert_eq!(review.summary.as_deref(), Some("line one\nline two"));
+        assert!(has_warning(
+            &review,
+            "Escaped raw line breaks inside strings"
+        ));
+    }
+
+    #[test]
+    fn prefers_the_answer_over_json_quoted_in_thinking() {
+        let review = parse_review(
+            "<thinking>The format is {\"summary\": \"example\", \"comments\": []}, so I need a summary.</thinking>\n\
+             ```json\n{\"summary\": \"Actual review\", \"comments\": []}\n```",
+        );
+        assert_eq!(review.summary.as_deref(), Some("Actual review"));
+    }
+
+    #[test]
+    fn keeps_the_output_when_there_is_no_json() {
+        let review = parse_review("  I could not review this PR.\n");
+        assert!(!review.found_json);
+        assert_eq!(
+            review.summary.as_deref(),
+            Some("I could not review this PR.")
+        );
+        assert!(has_warning(&review, "No JSON object found in the response"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Declarative review rules evaluated against a PR's diff without any model: patterns and
+//! forbidden APIs on added lines, and file-level checks such as a migration without its
+//! rollback. Findings have the shape of AI review comments, so they are shown and posted
+//! the same way. Rules are kept in `review-rules.json` in the app data dir.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{FileDiff, LineKind};
+use crate::review_result::ReviewComment;
+
+/// Findings past this many per rule are dropped with a warning
+const MAX_FINDINGS_PER_RULE: usize = 25;
+
+const SEVERITIES: &[&str] = &["critical", "warning", "info", "suggestion"];
+const CATEGORIES: &[&str] = &[
+    "security",
+    "performance",
+    "best-practices",
+    "code-style",
+    "documentation",
+    "testing",
+    "architecture",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleCheck {
+    /// Added lines matching a regex
+    Pattern { pattern: String },
+    /// Added lines using any of these names, e.g. `eval` or `child_process.exec`
+    ForbiddenApi { apis: Vec<String> },
+    /// Changed files whose added lines never match a regex, e.g. a migration without
+    /// `def down`; reported on the file's first added line
+    MissingPattern { pattern: String },
+    /// Changed files without a companion file changed in the same PR. The glob may use
+    /// `{dir}`, `{name}`, `{stem}` and `{ext}` of the changed file, e.g. `{dir}/*.down.sql`.
+    MissingFile { companion: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewRule {
+    pub id: String,
+    /// Comment body; `{match}` is replaced with the matched text
+    pub message: String,
+    #[serde(default = "default_severity")]
+    pub severity: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+    /// Globs of the files the rule applies to; all files if empty. A glob without a `/`
+    /// matches the file name in any directory.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default, alias = "exclude_paths")]
+    pub exclude_paths: Vec<String>,
+    /// "owner/name" globs of the repositories the rule applies to; all if empty
+    #[serde(default)]
+    pub repositories: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub check: RuleCheck,
+}
+
+fn default_severity() -> String {
+    "warning".to_string()
+}
+
+fn default_category() -> String {
+    "best-practices".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rules: Vec<ReviewRule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleFinding {
+    #[serde(flatten)]
+    pub comment: ReviewComment,
+    pub rule_id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleReport {
+    pub findings: Vec<RuleFinding>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ReviewRules {
+    /// Config file in the app data dir; changes aren't persisted until it is set
+    path: OnceLock<PathBuf>,
+    config: Mutex<RulesConfig>,
+}
+
+impl ReviewRules {
+    pub fn init(&self, path: PathBuf) {
+        if let Some(config) = read_config(&path) {
+            *self.config.lock().unwrap() = config;
+        }
+        let _ = self.path.set(path);
+    }
+
+    pub fn config(&self) -> RulesConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: RulesConfig) -> Result<(), String> {
+        validate(&config.rules)?;
+        if let Some(path) = self.path.get() {
+            write_config(path, &config)?;
+        }
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+}
+
+fn read_config(path: &Path) -> Option<RulesConfig> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            log::warn!("Ignoring invalid {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_config(path: &Path, config: &RulesConfig) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    // Write then rename so a crash mid-write never leaves a truncated file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to save review rules: {}", e))
+}
+
+/// A regex matching paths against a glob: `**` spans directories, `*` and `?` don't,
+/// and `{a,b}` is either alternative
+pub fn glob_regex(glob: &str) -> Result<Regex, String> {
+    let mut pattern = String::from("^");
+    if !glob.contains('/') {
+        pattern.push_str("(?:.*/)?");
+    }
+    let mut chars = glob.trim_start_matches('/').chars().peekable();
+    let mut in_braces = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '{' if !in_braces => {
+                in_braces = true;
+                pattern.push_str("(?:");
+            }
+            '}' if in_braces => {
+                in_braces = false;
+                pattern.push(')');
+            }
+            ',' if in_braces => pattern.push('|'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    if in_braces {
+        return Err(format!("Unclosed {{ in glob {}", glob));
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|e| format!("Invalid glob {}: {}", glob, e))
+}
+
+/// A regex matching any of `apis` as whole names
+fn api_regex(apis: &[String]) -> Result<Regex, String> {
+    if apis.is_empty() {
+        return Err("A forbidden_api rule needs at least one API".to_string());
+    }
+    let alternatives = apis
+        .iter()
+        .map(|api| {
+            let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            format!(
+                "{}{}{}",
+                if word(api.chars().next()) { r"\b" } else { "" },
+                regex::escape(api),
+                if word(api.chars().last()) { r"\b" } else { "" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    Regex::new(&alternatives).map_err(|e| e.to_string())
+}
+
+enum CompiledCheck {
+    Lines(Regex),
+    MissingPattern(Regex),
+    MissingFile(String),
+}
+
+struct CompiledRule<'a> {
+    rule: &'a ReviewRule,
+    paths: Vec<Regex>,
+    exclude_paths: Vec<Regex>,
+    check: CompiledCheck,
+}
+
+fn compile(rule: &ReviewRule) -> Result<CompiledRule<'_>, String> {
+    let globs = |globs: &[String]| {
+        globs
+            .iter()
+            .map(|glob| glob_regex(glob))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let regex = |pattern: &str| Regex::new(pattern).map_err(|e| e.to_string());
+    let check = match &rule.check {
+        RuleCheck::Pattern { pattern } => CompiledCheck::Lines(regex(pattern)?),
+        RuleCheck::ForbiddenApi { apis } => CompiledCheck::Lines(api_regex(apis)?),
+        RuleCheck::MissingPattern { pattern } => CompiledCheck::MissingPattern(regex(pattern)?),
+        RuleCheck::MissingFile { companion } => {
+            // Checked with placeholders filled in, but must be a valid glob either way
+            glob_regex(companion)?;
+            CompiledCheck::MissingFile(companion.clone())
+        }
+    };
+    Ok(CompiledRule {
+        rule,
+        paths: globs(&rule.paths)?,
+        exclude_paths: globs(&rule.exclude_paths)?,
+        check,
+    })
+}
+
+/// Check rules can be compiled and use known severities and categories
+pub fn validate(rules: &[ReviewRule]) -> Result<(), String> {
+    for rule in rules {
+        if rule.id.trim().is_empty() {
+            return Err("Every review rule needs an id".to_string());
+        }
+        if !SEVERITIES.contains(&rule.severity.as_str()) {
+            return Err(format!(
+                "Rule {} has unknown severity {}",
+                rule.id, rule.severity
+            ));
+        }
+        if !CATEGORIES.contains(&rule.category.as_str()) {
+            return Err(format!(
+                "Rule {} has unknown category {}",
+                rule.id, rule.category
+            ));
+        }
+        for glob in &rule.repositories {
+            glob_regex(glob).map_err(|e| format!("Rule {}: {}", rule.id, e))?;
+        }
+        compile(rule).map_err(|e| format!("Rule {}: {}", rule.id, e))?;
+    }
+    Ok(())
+}
+
+/// Placeholders of a `missing_file` companion glob for `path`
+fn companion_glob(companion: &str, path: &str) -> String {
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let (stem, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let glob = companion
+        .replace("{dir}", dir)
+        .replace("{name}", name)
+        .replace("{stem}", stem)
+        .replace("{ext}", ext);
+    glob.trim_start_matches('/').to_string()
+}
+
+fn comment(rule: &ReviewRule, path: &str, line: u32, matched: &str) -> RuleFinding {
+    RuleFinding {
+        comment: ReviewComment {
+            path: path.to_string(),
+            line: line as u64,
+            side: "RIGHT".to_string(),
+            severity: rule.severity.clone(),
+            category: rule.category.clone(),
+            body: rule.message.replace("{match}", matched),
+            suggestion: None,
+        },
+        rule_id: rule.id.clone(),
+    }
+}
+
+fn added_lines(file: &FileDiff) -> impl Iterator<Item = (u32, &str)> {
+    file.hunks
+        .iter()
+        .flat_map(|hunk| &hunk.lines)
+        .filter(|line| line.kind == LineKind::Added)
+        .filter_map(|line| Some((line.new_line?, line.content.as_str())))
+}
+
+fn evaluate_rule(rule: &CompiledRule, files: &[FileDiff]) -> Vec<RuleFinding> {
+    let mut findings = Vec::new();
+    let applies = |path: &str| {
+        (rule.paths.is_empty() || rule.paths.iter().any(|glob| glob.is_match(path)))
+            && !rule.exclude_paths.iter().any(|glob| glob.is_match(path))
+    };
+    for file in files {
+        if file.binary || file.deleted() || !applies(&file.path) {
+            continue;
+        }
+        match &rule.check {
+            CompiledCheck::Lines(regex) => {
+                for (line, content) in added_lines(file) {
+                    if let Some(found) = regex.find(content) {
+                        findings.push(comment(rule.rule, &file.path, line, found.as_str()));
+                    }
+                }
+            }
+            CompiledCheck::MissingPattern(regex) => {
+                let mut lines = added_lines(file).peekable();
+                let first = match lines.peek() {
+                    Some(&(line, _)) => line,
+                    None => continue,
+                };
+                if !lines.any(|(_, content)| regex.is_match(content)) {
+                    findings.push(comment(rule.rule, &file.path, first, ""));
+                }
+            }
+            CompiledCheck::MissingFile(companion) => {
+                let first = match added_lines(file).next() {
+                    Some((line, _)) => line,
+                    None => continue,
+                };
+                let glob = companion_glob(companion, &file.path);
+                let present = match glob_regex(&glob) {
+                    Ok(regex) => files
+                        .iter()
+                        .any(|other| other.path != file.path && regex.is_match(&other.path)),
+                    Err(_) => continue,
+                };
+                if !present {
+                    findings.push(comment(rule.rule, &file.path, first, &glob));
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// Evaluate the enabled rules that apply to `repository` against a parsed diff
+pub fn evaluate(rules: &[ReviewRule], repository: &str, files: &[FileDiff]) -> RuleReport {
+    let mut report = RuleReport::default();
+    for rule in rules.iter().filter(|rule| rule.enabled) {
+        let in_repository = rule.repositories.is_empty()
+            || rule
+                .repositories
+                .iter()
+                .any(|glob| glob_regex(glob).is_ok_and(|regex| regex.is_match(repository)));
+        if !in_repository {
+            continue;
+        }
+        let compiled = match compile(rule) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                report
+                    .warnings
+                    .push(format!("Skipped rule {}: {}", rule.id, e));
+                continue;
+            }
+        };
+        let mut findings = evaluate_rule(&compiled, files);
+        if findings.len() > MAX_FINDINGS_PER_RULE {
+            report.warnings.push(format!(
+                "Rule {} matched {} times; only the first {} are shown",
+                rule.id,
+                findings.len(),
+                MAX_FINDINGS_PER_RULE
+            ));
+            findings.truncate(MAX_FINDINGS_PER_RULE);
+        }
+        report.findings.extend(findings);
+    }
+    report
+}
@@ -0,0 +1,340 @@
+//! Delivery of AI run events to the frontend. Each run streams typed events over the
+//! `tauri::ipc::Channel` passed to `start_ai_stream`. Every event also gets a sequence
+//! number and is kept in a bounded buffer, so a reloaded webview can replay what it
+//! missed with `attach_ai_stream` and keep receiving on a new channel.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{AppHandle, Manager};
+
+use crate::budgets::BudgetDecision;
+use crate::jobs::JobInfo;
+use crate::providers::{ContentEvent, TokenUsage};
+use crate::transcripts::{TranscriptRecord, Transcripts};
+use crate::usage::UsageLog;
+
+/// Bumped whenever `AIEvent` changes in a way the frontend has to know about
+pub const AI_EVENT_VERSION: u32 = 2;
+
+/// Buffered bytes per run before the oldest events are dropped
+const MAX_BUFFER_BYTES: usize = 4 * 1024 * 1024;
+/// Finished runs kept for late attaches
+const MAX_FINISHED_STREAMS: usize = 20;
+
+/// Everything a run reports. Besides model output and how the run ended, `Job` tracks its
+/// place in the queue and `Budget` says why it was held back or released.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AIEvent {
+    ThinkingStart,
+    ThinkingDelta { text: String },
+    TextDelta { text: String },
+    BlockStop,
+    Usage { usage: TokenUsage },
+    Stderr { line: String },
+    Complete { message: String },
+    Error { message: String },
+    Cancelled { message: String },
+    Timeout { reason: String },
+    Job { info: JobInfo },
+    Budget { decision: BudgetDecision },
+}
+
+impl AIEvent {
+    /// Events that end a run
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            AIEvent::Complete { .. }
+                | AIEvent::Error { .. }
+                | AIEvent::Cancelled { .. }
+                | AIEvent::Timeout { .. }
+        )
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            AIEvent::ThinkingDelta { text } | AIEvent::TextDelta { text } => text.len(),
+            AIEvent::Stderr { line } => line.len(),
+            AIEvent::Complete { message }
+            | AIEvent::Error { message }
+            | AIEvent::Cancelled { message } => message.len(),
+            AIEvent::Timeout { reason } => reason.len(),
+            AIEvent::Job { info } => info.blocked_by.as_ref().map_or(0, String::len),
+            AIEvent::Budget { decision } => decision.reason.len(),
+            AIEvent::ThinkingStart | AIEvent::BlockStop | AIEvent::Usage { .. } => 0,
+        }
+    }
+}
+
+impl From<ContentEvent> for AIEvent {
+    fn from(event: ContentEvent) -> Self {
+        match event {
+            ContentEvent::ThinkingStart => AIEvent::ThinkingStart,
+            ContentEvent::ThinkingDelta(text) => AIEvent::ThinkingDelta { text },
+            ContentEvent::TextDelta(text) => AIEvent::TextDelta { text },
+            ContentEvent::BlockStop => AIEvent::BlockStop,
+            ContentEvent::Usage(usage) => AIEvent::Usage { usage },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIEventEnvelope {
+    pub v: u32,
+    pub process_id: String,
+    pub seq: u64,
+    pub event: AIEvent,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StreamInfo {
+    pub process_id: String,
+    pub provider: String,
+    pub started_at: u64,
+    pub finished: bool,
+    /// Sequence number of the most recent event
+    pub last_seq: u64,
+}
+
+#[derive(Serialize)]
+pub struct AttachResult {
+    pub info: StreamInfo,
+    /// True if early events were dropped to stay within the buffer limit
+    pub truncated: bool,
+}
+
+#[derive(Default)]
+pub struct RunOutput {
+    /// Concatenated text deltas, without thinking
+    pub text: String,
+    pub terminal: Option<AIEvent>,
+}
+
+impl RunOutput {
+    /// The message of a failed, cancelled or timed-out run
+    pub fn error(&self) -> Option<String> {
+        match &self.terminal {
+            Some(AIEvent::Error { message }) | Some(AIEvent::Cancelled { message }) => {
+                Some(message.clone())
+            }
+            Some(AIEvent::Timeout { reason }) => Some(reason.clone()),
+            _ => None,
+        }
+    }
+}
+
+struct StreamBuffer {
+    info: StreamInfo,
+    events: VecDeque<AIEventEnvelope>,
+    bytes: usize,
+    truncated: bool,
+    /// Channels receiving live events: the one from `start_ai_stream` plus any attaches
+    subscribers: Vec<Channel<AIEventEnvelope>>,
+}
+
+impl StreamBuffer {
+    fn push(&mut self, envelope: AIEventEnvelope) {
+        // Consecutive deltas are merged so long outputs don't need an event apiece
+        if let Some(last) = self.events.back_mut() {
+            let merged = match (&mut last.event, &envelope.event) {
+                (AIEvent::ThinkingDelta { text }, AIEvent::ThinkingDelta { text: next })
+                | (AIEvent::TextDelta { text }, AIEvent::TextDelta { text: next }) => {
+                    text.push_str(next);
+                    true
+                }
+                _ => false,
+            };
+            if merged {
+                last.seq = envelope.seq;
+                self.bytes += envelope.event.size();
+                self.trim();
+                return;
+            }
+        }
+        self.bytes += envelope.event.size();
+        self.events.push_back(envelope);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.bytes > MAX_BUFFER_BYTES && self.events.len() > 1 {
+            if let Some(envelope) = self.events.pop_front() {
+                self.bytes -= envelope.event.size();
+                self.truncated = true;
+            }
+        }
+    }
+}
+
+/// Collects a run's output from a channel subscribed to it, for backend code that needs the
+/// whole output. Unlike the replay buffer, nothing is trimmed or pruned.
+#[derive(Clone, Default)]
+pub struct OutputCollector(Arc<Mutex<RunOutput>>);
+
+impl OutputCollector {
+    /// A channel to pass to the run as a subscriber
+    pub fn channel(&self) -> Channel<AIEventEnvelope> {
+        let output = self.0.clone();
+        Channel::new(move |body| {
+            let envelope = match body {
+                InvokeResponseBody::Json(json) => serde_json::from_str::<AIEventEnvelope>(&json),
+                InvokeResponseBody::Raw(bytes) => serde_json::from_slice(&bytes),
+            };
+            if let Ok(envelope) = envelope {
+                let mut output = output.lock().unwrap();
+                match envelope.event {
+                    AIEvent::TextDelta { text } => output.text.push_str(&text),
+                    event if event.is_terminal() => output.terminal = Some(event),
+                    _ => {}
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// What was collected; complete once the run's outcome is known
+    pub fn take(&self) -> RunOutput {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+#[derive(Default)]
+pub struct StreamBuffers {
+    streams: Mutex<HashMap<String, StreamBuffer>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl StreamBuffers {
+    pub fn open(
+        &self,
+        process_id: &str,
+        provider: &str,
+        subscribers: Vec<Channel<AIEventEnvelope>>,
+    ) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.insert(
+            process_id.to_string(),
+            StreamBuffer {
+                info: StreamInfo {
+                    process_id: process_id.to_string(),
+                    provider: provider.to_string(),
+                    started_at: now_ms(),
+                    finished: false,
+                    last_seq: 0,
+                },
+                events: VecDeque::new(),
+                bytes: 0,
+                truncated: false,
+                subscribers,
+            },
+        );
+    }
+
+    /// Assign the next sequence number, record the event and send it to every subscriber
+    fn publish(&self, process_id: &str, event: AIEvent) {
+        let mut streams = self.streams.lock().unwrap();
+        let buffer = match streams.get_mut(process_id) {
+            Some(buffer) => buffer,
+            None => {
+                log::warn!("Dropping event for unknown AI stream {}", process_id);
+                return;
+            }
+        };
+
+        buffer.info.last_seq += 1;
+        let terminal = event.is_terminal();
+        let envelope = AIEventEnvelope {
+            v: AI_EVENT_VERSION,
+            process_id: process_id.to_string(),
+            seq: buffer.info.last_seq,
+            event,
+        };
+        buffer
+            .subscribers
+            .retain(|channel| channel.send(envelope.clone()).is_ok());
+        buffer.push(envelope);
+
+        if terminal {
+            buffer.info.finished = true;
+            buffer.subscribers.clear();
+            prune_finished(&mut streams);
+        }
+    }
+
+    /// Replay the buffered events to `channel`, then keep it subscribed for live ones.
+    /// Both happen under the lock, so nothing is missed or delivered twice.
+    pub fn attach(
+        &self,
+        process_id: &str,
+        channel: Channel<AIEventEnvelope>,
+    ) -> Result<AttachResult, String> {
+        let mut streams = self.streams.lock().unwrap();
+        let buffer = streams
+            .get_mut(process_id)
+            .ok_or_else(|| format!("No AI stream with id {}", process_id))?;
+
+        for envelope in &buffer.events {
+            channel
+                .send(envelope.clone())
+                .map_err(|e| format!("Failed to replay AI stream: {}", e))?;
+        }
+        if !buffer.info.finished {
+            buffer.subscribers.push(channel);
+        }
+
+        Ok(AttachResult {
+            info: buffer.info.clone(),
+            truncated: buffer.truncated,
+        })
+    }
+
+    pub fn list(&self) -> Vec<StreamInfo> {
+        let streams = self.streams.lock().unwrap();
+        let mut infos: Vec<StreamInfo> =
+            streams.values().map(|buffer| buffer.info.clone()).collect();
+        infos.sort_by_key(|info| info.started_at);
+        infos
+    }
+}
+
+fn prune_finished(streams: &mut HashMap<String, StreamBuffer>) {
+    let mut finished: Vec<(u64, String)> = streams
+        .values()
+        .filter(|buffer| buffer.info.finished)
+        .map(|buffer| (buffer.info.started_at, buffer.info.process_id.clone()))
+        .collect();
+    if finished.len() <= MAX_FINISHED_STREAMS {
+        return;
+    }
+    finished.sort();
+    for (_, process_id) in finished.iter().take(finished.len() - MAX_FINISHED_STREAMS) {
+        streams.remove(process_id);
+    }
+}
+
+/// Send an event for a run to its subscribers and record it in the run's transcript
+/// and usage
+pub fn emit_event(app: &AppHandle, process_id: &str, event: impl Into<AIEvent>) {
+    let event = event.into();
+    if let AIEvent::Usage { usage } = &event {
+        app.state::<UsageLog>().add(process_id, usage);
+    }
+    app.state::<Transcripts>().record(
+        process_id,
+        TranscriptRecord::Event {
+            event: event.clone(),
+        },
+    );
+    app.state::<StreamBuffers>().publish(process_id, event);
+}
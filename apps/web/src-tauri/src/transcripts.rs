@@ -0,0 +1,370 @@
+//! Per-run transcripts of AI reviews, written as JSON lines to `transcripts/<process_id>.jsonl`
+//! in the app data dir. Each line carries `t`, the milliseconds since the run started, so a
+//! transcript can be replayed with its original timing.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+
+use crate::jobs::JobOutcome;
+use crate::streams::{AIEvent, AIEventEnvelope, AI_EVENT_VERSION};
+
+/// Oldest transcripts beyond this many are deleted when a new run starts
+const MAX_TRANSCRIPTS: usize = 100;
+/// Slower replays are raised to this speed, so a tiny speed can't stall a replay for days
+const MIN_REPLAY_SPEED: f64 = 0.1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TranscriptRecord {
+    Start {
+        provider: String,
+        command: String,
+        args: Vec<String>,
+        model: Option<String>,
+        /// Milliseconds since the Unix epoch
+        started_at: u64,
+    },
+    /// A raw line of provider output, before parsing
+    Stdout {
+        line: String,
+    },
+    /// An event as it was sent to the frontend
+    Event {
+        event: AIEvent,
+    },
+    /// Exit code of a CLI provider process
+    Exit {
+        code: Option<i32>,
+    },
+    End {
+        outcome: JobOutcome,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptLine {
+    pub t: u64,
+    #[serde(flatten)]
+    pub record: TranscriptRecord,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptInfo {
+    pub id: String,
+    pub provider: String,
+    pub command: String,
+    pub model: Option<String>,
+    pub started_at: u64,
+    /// Run length in milliseconds, if the transcript is complete
+    pub duration_ms: Option<u64>,
+    pub exit_code: Option<i32>,
+    pub outcome: Option<JobOutcome>,
+    pub size_bytes: u64,
+}
+
+struct OpenTranscript {
+    file: File,
+    started: Instant,
+}
+
+#[derive(Default)]
+pub struct Transcripts {
+    /// Transcript directory; nothing is written until it is set
+    dir: OnceLock<PathBuf>,
+    open: Mutex<HashMap<String, OpenTranscript>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Transcript ids are process ids; anything else could escape the directory
+fn validate_id(id: &str) -> Result<(), String> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid transcript id: {}", id))
+    }
+}
+
+impl Transcripts {
+    pub fn init(&self, dir: PathBuf) {
+        let _ = self.dir.set(dir);
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf, String> {
+        validate_id(id)?;
+        let dir = self
+            .dir
+            .get()
+            .ok_or("Transcript directory is not available")?;
+        Ok(dir.join(format!("{}.jsonl", id)))
+    }
+
+    /// Create the transcript file for a run and write its start record
+    pub fn begin(
+        &self,
+        process_id: &str,
+        provider: &str,
+        command: &str,
+        args: &[String],
+        model: Option<&str>,
+    ) {
+        let result = (|| -> Result<File, String> {
+            let path = self.path(process_id)?;
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                prune(dir);
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))
+        })();
+
+        let file = match result {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!("Transcript disabled for {}: {}", process_id, e);
+                return;
+            }
+        };
+
+        let mut open = self.open.lock().unwrap();
+        open.insert(
+            process_id.to_string(),
+            OpenTranscript {
+                file,
+                started: Instant::now(),
+            },
+        );
+        drop(open);
+
+        self.record(
+            process_id,
+            TranscriptRecord::Start {
+                provider: provider.to_string(),
+                command: command.to_string(),
+                args: args.to_vec(),
+                model: model.map(str::to_string),
+                started_at: now_ms(),
+            },
+        );
+    }
+
+    /// Append a record; a no-op for runs without an open transcript
+    pub fn record(&self, process_id: &str, record: TranscriptRecord) {
+        let mut open = self.open.lock().unwrap();
+        let transcript = match open.get_mut(process_id) {
+            Some(transcript) => transcript,
+            None => return,
+        };
+        let line = TranscriptLine {
+            t: transcript.started.elapsed().as_millis() as u64,
+            record,
+        };
+        let mut json = match serde_json::to_string(&line) {
+            Ok(json) => json,
+            Err(_) => return,
+        };
+        json.push('\n');
+        // One write per line so a crash leaves at most a partial final line
+        if let Err(e) = transcript.file.write_all(json.as_bytes()) {
+            log::warn!("Failed to write transcript for {}: {}", process_id, e);
+            open.remove(process_id);
+        }
+    }
+
+    /// Write the final record and close the transcript
+    pub fn end(&self, process_id: &str, outcome: JobOutcome) {
+        self.record(process_id, TranscriptRecord::End { outcome });
+        self.open.lock().unwrap().remove(process_id);
+    }
+
+    /// Transcripts on disk, newest first
+    pub fn list(&self) -> Result<Vec<TranscriptInfo>, String> {
+        let dir = match self.dir.get() {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+        };
+
+        let mut infos: Vec<TranscriptInfo> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| read_info(&entry.path()))
+            .collect();
+        infos.sort_by_key(|info| std::cmp::Reverse(info.started_at));
+        Ok(infos)
+    }
+
+    /// All parseable lines of a transcript; a truncated last line is skipped
+    pub fn read(&self, id: &str) -> Result<Vec<TranscriptLine>, String> {
+        let path = self.path(id)?;
+        let file =
+            File::open(&path).map_err(|e| format!("Failed to open transcript {}: {}", id, e))?;
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<TranscriptLine>(&line).ok())
+            .collect())
+    }
+}
+
+/// Summarize a transcript from its first line and the last few KB
+fn read_info(path: &Path) -> Option<TranscriptInfo> {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+        return None;
+    }
+    let id = path.file_stem()?.to_str()?.to_string();
+    let mut file = File::open(path).ok()?;
+    let size_bytes = file.metadata().ok()?.len();
+
+    let mut first_line = String::new();
+    BufReader::new(&mut file).read_line(&mut first_line).ok()?;
+    let (provider, command, model, started_at) =
+        match serde_json::from_str::<TranscriptLine>(&first_line)
+            .ok()?
+            .record
+        {
+            TranscriptRecord::Start {
+                provider,
+                command,
+                model,
+                started_at,
+                ..
+            } => (provider, command, model, started_at),
+            _ => return None,
+        };
+
+    let tail_start = size_bytes.saturating_sub(8192);
+    file.seek(SeekFrom::Start(tail_start)).ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+
+    let mut info = TranscriptInfo {
+        id,
+        provider,
+        command,
+        model,
+        started_at,
+        duration_ms: None,
+        exit_code: None,
+        outcome: None,
+        size_bytes,
+    };
+    for line in String::from_utf8_lossy(&tail).lines() {
+        match serde_json::from_str::<TranscriptLine>(line) {
+            Ok(TranscriptLine {
+                t,
+                record: TranscriptRecord::End { outcome },
+            }) => {
+                info.outcome = Some(outcome);
+                info.duration_ms = Some(t);
+            }
+            Ok(TranscriptLine {
+                record: TranscriptRecord::Exit { code },
+                ..
+            }) => info.exit_code = code,
+            _ => {}
+        }
+    }
+    Some(info)
+}
+
+/// Delete the oldest transcripts so at most `MAX_TRANSCRIPTS - 1` remain before a new one
+fn prune(dir: &Path) {
+    let mut files: Vec<(SystemTime, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("jsonl"))
+            .filter_map(|path| {
+                let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, path))
+            })
+            .collect(),
+        Err(_) => return,
+    };
+    if files.len() < MAX_TRANSCRIPTS {
+        return;
+    }
+    files.sort();
+    for (_, path) in files.iter().take(files.len() + 1 - MAX_TRANSCRIPTS) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Send a transcript's events to `channel` with their original spacing divided by `speed`;
+/// a speed of 0 sends them all at once and speeds below `MIN_REPLAY_SPEED` are raised to it
+pub async fn replay(
+    id: String,
+    lines: Vec<TranscriptLine>,
+    speed: f64,
+    channel: Channel<AIEventEnvelope>,
+) {
+    let mut seq = 0;
+    let mut last_t = 0;
+    let mut finished = false;
+    let speed = if speed > 0.0 {
+        speed.max(MIN_REPLAY_SPEED)
+    } else {
+        0.0
+    };
+
+    for line in lines {
+        let event = match line.record {
+            TranscriptRecord::Event { event } => event,
+            _ => continue,
+        };
+        if speed > 0.0 {
+            let gap_ms = line.t.saturating_sub(last_t) as f64 / speed;
+            // Out-of-range gaps replay at once rather than panicking
+            let gap = Duration::try_from_secs_f64(gap_ms / 1000.0).unwrap_or_default();
+            tokio::time::sleep(gap).await;
+        }
+        last_t = line.t;
+        seq += 1;
+        finished = event.is_terminal();
+
+        let envelope = AIEventEnvelope {
+            v: AI_EVENT_VERSION,
+            process_id: id.clone(),
+            seq,
+            event,
+        };
+        if channel.send(envelope).is_err() {
+            return;
+        }
+    }
+
+    // Transcripts cut short by a crash have no terminal event
+    if !finished {
+        let _ = channel.send(AIEventEnvelope {
+            v: AI_EVENT_VERSION,
+            process_id: id,
+            seq: seq + 1,
+            event: AIEvent::Error {
+                message: "Transcript ended before the run finished".to_string(),
+            },
+        });
+    }
+}
@@ -0,0 +1,281 @@
+//! Token usage and cost of AI runs. Usage events are summed per run while it streams, and
+//! one record per finished run is appended to `ai-usage.jsonl` in the app data dir.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobOutcome;
+use crate::providers::TokenUsage;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub process_id: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub repository: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub outcome: JobOutcome,
+    /// False if the provider never reported usage for the run
+    pub reported: bool,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    pub runs: u64,
+    /// Runs whose provider reported no usage at all
+    pub unreported_runs: u64,
+    /// Runs that reported tokens but no cost, so `cost_usd` undercounts
+    pub uncosted_runs: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.runs += 1;
+        if !record.reported {
+            self.unreported_runs += 1;
+        } else if record.usage.cost_usd.is_none() {
+            self.uncosted_runs += 1;
+        }
+        self.input_tokens += record.usage.input_tokens;
+        self.output_tokens += record.usage.output_tokens;
+        self.cache_creation_input_tokens += record.usage.cache_creation_input_tokens;
+        self.cache_read_input_tokens += record.usage.cache_read_input_tokens;
+        self.cost_usd += record.usage.cost_usd.unwrap_or(0.0);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageBucket {
+    pub key: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageSummary {
+    pub total: UsageTotals,
+    /// Sorted by cost, highest first
+    pub by_provider: Vec<UsageBucket>,
+    pub by_model: Vec<UsageBucket>,
+    pub by_repository: Vec<UsageBucket>,
+    /// UTC days as `YYYY-MM-DD`, oldest first
+    pub by_day: Vec<UsageBucket>,
+}
+
+#[derive(Default)]
+pub struct UsageLog {
+    /// Usage file; nothing is written until it is set
+    path: OnceLock<PathBuf>,
+    /// Runs that are still streaming
+    running: Mutex<HashMap<String, UsageRecord>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `YYYY-MM-DD` of a Unix timestamp in UTC
+pub fn utc_day(ms: u64) -> String {
+    // Civil-from-days, from Howard Hinnant's date algorithms
+    let days = (ms / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+impl UsageLog {
+    pub fn init(&self, path: PathBuf) {
+        let _ = self.path.set(path);
+    }
+
+    pub fn begin(
+        &self,
+        process_id: &str,
+        provider: &str,
+        model: Option<&str>,
+        repository: Option<&str>,
+    ) {
+        self.running.lock().unwrap().insert(
+            process_id.to_string(),
+            UsageRecord {
+                process_id: process_id.to_string(),
+                provider: provider.to_string(),
+                model: model.map(str::to_string),
+                repository: repository.map(str::to_string),
+                started_at: now_ms(),
+                finished_at: 0,
+                outcome: JobOutcome::Completed,
+                reported: false,
+                usage: TokenUsage::default(),
+            },
+        );
+    }
+
+    /// Add a usage report to a running run. Providers that report per turn send several.
+    pub fn add(&self, process_id: &str, usage: &TokenUsage) {
+        let mut running = self.running.lock().unwrap();
+        let record = match running.get_mut(process_id) {
+            Some(record) => record,
+            None => return,
+        };
+        record.reported = true;
+        let total = &mut record.usage;
+        total.input_tokens += usage.input_tokens;
+        total.output_tokens += usage.output_tokens;
+        total.cache_creation_input_tokens += usage.cache_creation_input_tokens;
+        total.cache_read_input_tokens += usage.cache_read_input_tokens;
+        if let Some(cost) = usage.cost_usd {
+            total.cost_usd = Some(total.cost_usd.unwrap_or(0.0) + cost);
+        }
+    }
+
+    /// Finish a run and append its record to the usage file
+    pub fn end(&self, process_id: &str, outcome: JobOutcome) {
+        let mut record = match self.running.lock().unwrap().remove(process_id) {
+            Some(record) => record,
+            None => return,
+        };
+        record.finished_at = now_ms();
+        record.outcome = outcome;
+
+        let path = match self.path.get() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = (|| -> Result<(), String> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            let mut json = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+            json.push('\n');
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(json.as_bytes()))
+                .map_err(|e| e.to_string())
+        })();
+        if let Err(e) = result {
+            log::warn!("Failed to record usage for {}: {}", process_id, e);
+        }
+    }
+
+    /// Runs still streaming, with the usage they have reported so far
+    pub fn running(&self) -> Vec<UsageRecord> {
+        self.running.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Finished runs started within `[since, until)`, in milliseconds since the Unix epoch
+    pub fn records(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<Vec<UsageRecord>, String> {
+        let path = match self.path.get() {
+            Some(path) => path,
+            None => return Ok(Vec::new()),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<UsageRecord>(&line).ok())
+            .filter(|record| since.map_or(true, |since| record.started_at >= since))
+            .filter(|record| until.map_or(true, |until| record.started_at < until))
+            .collect())
+    }
+
+    pub fn summary(&self, since: Option<u64>, until: Option<u64>) -> Result<UsageSummary, String> {
+        let records = self.records(since, until)?;
+
+        let mut summary = UsageSummary::default();
+        let mut by_provider: HashMap<String, UsageTotals> = HashMap::new();
+        let mut by_model: HashMap<String, UsageTotals> = HashMap::new();
+        let mut by_repository: HashMap<String, UsageTotals> = HashMap::new();
+        let mut by_day: HashMap<String, UsageTotals> = HashMap::new();
+
+        for record in &records {
+            summary.total.add(record);
+            by_provider
+                .entry(record.provider.clone())
+                .or_default()
+                .add(record);
+            by_model
+                .entry(
+                    record
+                        .model
+                        .clone()
+                        .unwrap_or_else(|| format!("{} (default)", record.provider)),
+                )
+                .or_default()
+                .add(record);
+            by_repository
+                .entry(
+                    record
+                        .repository
+                        .clone()
+                        .unwrap_or_else(|| "(none)".to_string()),
+                )
+                .or_default()
+                .add(record);
+            by_day
+                .entry(utc_day(record.started_at))
+                .or_default()
+                .add(record);
+        }
+
+        summary.by_provider = by_cost(by_provider);
+        summary.by_model = by_cost(by_model);
+        summary.by_repository = by_cost(by_repository);
+        summary.by_day = buckets(by_day);
+        summary.by_day.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(summary)
+    }
+}
+
+fn buckets(groups: HashMap<String, UsageTotals>) -> Vec<UsageBucket> {
+    groups
+        .into_iter()
+        .map(|(key, totals)| UsageBucket { key, totals })
+        .collect()
+}
+
+fn by_cost(groups: HashMap<String, UsageTotals>) -> Vec<UsageBucket> {
+    let mut buckets = buckets(groups);
+    buckets.sort_by(|a, b| {
+        b.totals
+            .cost_usd
+            .total_cmp(&a.totals.cost_usd)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    buckets
+}
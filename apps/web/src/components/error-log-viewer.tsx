@@ -32,6 +32,8 @@ const sourceLabels: Record<ErrorLogSource | "all", string> = {
   gh: "GitHub CLI",
   "ai-claude": "Claude",
   "ai-codex": "Codex",
+  "ai-gemini": "Gemini",
+  "ai-opencode": "opencode",
   system: "System",
 };
 
@@ -39,6 +41,8 @@ const sourceColors: Record<ErrorLogSource, string> = {
   gh: "bg-amber-500/15 text-amber-600 dark:text-amber-400",
   "ai-claude": "bg-violet-500/15 text-violet-600 dark:text-violet-400",
   "ai-codex": "bg-emerald-500/15 text-emerald-600 dark:text-emerald-400",
+  "ai-gemini": "bg-sky-500/15 text-sky-600 dark:text-sky-400",
+  "ai-opencode": "bg-slate-500/15 text-slate-600 dark:text-slate-400",
   system: "bg-rose-500/15 text-rose-600 dark:text-rose-400",
 };
 
@@ -47,6 +51,8 @@ const filterItems = [
   { value: "gh", label: "GitHub CLI" },
   { value: "ai-claude", label: "Claude" },
   { value: "ai-codex", label: "Codex" },
+  { value: "ai-gemini", label: "Gemini" },
+  { value: "ai-opencode", label: "opencode" },
   { value: "system", label: "System" },
 ];
 
@@ -32,6 +32,8 @@ interface SettingsDialogProps {
 const providerOptions: Array<{ id: AIProvider; label: string; description: string }> = [
   { id: "claude", label: "Claude", description: "Fast, sharp review summaries" },
   { id: "codex", label: "Codex", description: "Deep code reasoning focus" },
+  { id: "gemini", label: "Gemini", description: "Long context for large diffs" },
+  { id: "opencode", label: "opencode", description: "Any model opencode is set up for" },
 ];
 
 const providerItems = providerOptions.map((option) => ({
@@ -2,8 +2,14 @@ import type {
   AIProvider,
   AIReviewComment,
   AIReviewResult as AIReviewResultType,
+  AIReviewSuggestion,
+} from "@/types";
+import {
+  AI_PROVIDERS,
+  CODEX_REASONING_EFFORTS,
+  DEFAULT_SYSTEM_PROMPTS,
+  MODELS_BY_PROVIDER,
 } from "@/types";
-import { CODEX_REASONING_EFFORTS, DEFAULT_SYSTEM_PROMPTS, MODELS_BY_PROVIDER } from "@/types";
 
 import { formatDistanceToNow } from "date-fns";
 import AlertCircle from "lucide-react/dist/esm/icons/circle-alert";
@@ -43,6 +49,11 @@ interface AIReviewPanelProps {
   isLoading?: boolean;
   onCommentClick?: (filePath: string, line: number) => void;
   onPostComment?: (comment: AIReviewComment) => Promise<boolean>;
+  /** Post comments and suggestions together, so either all of them are posted or none */
+  onPostAllComments?: (
+    comments: AIReviewComment[],
+    suggestions: AIReviewSuggestion[],
+  ) => Promise<boolean>;
 }
 
 function AIReviewPanel({
@@ -53,6 +64,7 @@ function AIReviewPanel({
   isLoading,
   onCommentClick,
   onPostComment,
+  onPostAllComments,
 }: AIReviewPanelProps) {
   const {
     config,
@@ -95,8 +107,6 @@ function AIReviewPanel({
     });
   }, [providerReviews]);
 
-  const providers: AIProvider[] = ["claude", "codex"];
-
   const handleProviderChange = (provider: AIProvider) => {
     setProvider(provider);
   };
@@ -151,7 +161,7 @@ function AIReviewPanel({
               AI Provider
             </label>
             <div className="flex gap-2">
-              {providers.map((provider) => (
+              {AI_PROVIDERS.map((provider) => (
                 <button
                   key={provider}
                   type="button"
@@ -311,6 +321,7 @@ function AIReviewPanel({
           review={latestReview}
           onCommentClick={onCommentClick}
           onPostComment={onPostComment}
+          onPostAllComments={onPostAllComments}
           className="min-h-0 flex-1"
         />
       )}
@@ -322,6 +333,10 @@ interface AIReviewResultCardProps {
   review: AIReviewResultType;
   onCommentClick?: (filePath: string, line: number) => void;
   onPostComment?: (comment: AIReviewComment) => Promise<boolean>;
+  onPostAllComments?: (
+    comments: AIReviewComment[],
+    suggestions: AIReviewSuggestion[],
+  ) => Promise<boolean>;
   className?: string;
 }
 
@@ -329,6 +344,7 @@ function ReviewResultCard({
   review,
   onCommentClick,
   onPostComment,
+  onPostAllComments,
   className,
 }: AIReviewResultCardProps) {
   const statusIcon =
@@ -375,6 +391,11 @@ function ReviewResultCard({
                 comments={review.comments}
                 onCommentClick={onCommentClick}
                 onPostComment={onPostComment}
+                onPostAll={
+                  onPostAllComments
+                    ? (comments) => onPostAllComments(comments, review.suggestions)
+                    : undefined
+                }
               />
             </div>
           )}
@@ -405,12 +426,15 @@ interface ReviewCommentsGroupedProps {
   comments: AIReviewComment[];
   onCommentClick?: (filePath: string, line: number) => void;
   onPostComment?: (comment: AIReviewComment) => Promise<boolean>;
+  /** Post the given comments in one go; they are all posted or none are */
+  onPostAll?: (comments: AIReviewComment[]) => Promise<boolean>;
 }
 
 function ReviewCommentsGrouped({
   comments,
   onCommentClick,
   onPostComment,
+  onPostAll,
 }: ReviewCommentsGroupedProps) {
   const [expandedFiles, setExpandedFiles] = useState<Set<string>>(new Set());
   const [postingCommentId, setPostingCommentId] = useState<string | null>(null);
@@ -457,6 +481,13 @@ function ReviewCommentsGrouped({
     if (pendingComments.length === 0) return;
 
     setIsPostingAll(true);
+    if (onPostAll) {
+      if (await onPostAll(pendingComments)) {
+        setPostedCommentIds((prev) => new Set([...prev, ...pendingComments.map((c) => c.id)]));
+      }
+      setIsPostingAll(false);
+      return;
+    }
     for (const comment of pendingComments) {
       setPostingCommentId(comment.id);
       const success = await onPostComment(comment);
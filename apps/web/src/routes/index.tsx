@@ -1,12 +1,14 @@
 import type {
   AIProvider,
   AIReviewComment,
+  AIReviewSuggestion,
   Comment,
   CommentsByLine,
   FileDiff,
   PullRequest,
   Repository,
 } from "@/types";
+import { PROVIDER_CLIS } from "@/types";
 
 import { createFileRoute } from "@tanstack/react-router";
 import AlertCircle from "lucide-react/dist/esm/icons/circle-alert";
@@ -53,6 +55,7 @@ import {
 } from "@/features/pull-requests";
 import { parseDiff } from "@/lib/parse-diff";
 import {
+  addPullRequestComment,
   addReviewComment,
   approvePullRequest,
   checkGhCliStatus,
@@ -71,6 +74,8 @@ import {
   getUserOrganizations,
   getUserRepositories,
   mergePullRequest,
+  postReviewBatch,
+  type BatchReviewComment,
   replyToReviewComment,
   resolveReviewThread,
   type PendingReview,
@@ -80,10 +85,17 @@ import {
   updateReviewComment,
 } from "@/services/github";
 import {
+  anchorAIComments,
   checkProviderStatus,
   createPendingReview,
+  getReviewPolicy,
+  meetsSeverityThreshold,
   parseAIReviewResponse,
+  recordAIReview,
+  runReviewRules,
+  scanPRSecrets,
   startStreamingAIReview,
+  type AIAnchorResult,
 } from "@/services/ai-review";
 import { usePRStore, useReviewStore } from "@/stores";
 import { useTrayMenu } from "@/hooks/use-tray";
@@ -118,6 +130,25 @@ const PR_SORT_OPTIONS = [
   { value: "size", label: "Size" },
 ];
 
+const AI_COMMENT_FOOTER = "\n\n---\n*🤖 AI Review Comment*";
+
+function formatAICommentBody(comment: AIReviewComment, includeSuggestion = true): string {
+  let body = `**[${comment.severity.toUpperCase()}]** ${comment.body}`;
+  if (includeSuggestion && comment.suggestion) {
+    body += `\n\n**Suggestion:**\n\`\`\`\n${comment.suggestion}\n\`\`\``;
+  }
+  return body + AI_COMMENT_FOOTER;
+}
+
+/** Whether `start` and `end` are in the same hunk of the new file, as a multi-line comment needs */
+function isInOneHunk(files: FileDiff[], path: string, start: number, end: number): boolean {
+  const file = files.find((f) => f.path === path);
+  return (
+    file?.hunks.some((hunk) => start >= hunk.newStart && end < hunk.newStart + hunk.newLines) ??
+    false
+  );
+}
+
 function HomeComponent() {
   const { watchedRepos, addWatchedRepo, removeWatchedRepo } = usePRStore();
 
@@ -139,18 +170,24 @@ function HomeComponent() {
   const [runningByProvider, setRunningByProvider] = useState<Record<AIProvider, boolean>>({
     claude: false,
     codex: false,
+    gemini: false,
+    opencode: false,
   });
   const [abortReviewByProvider, setAbortReviewByProvider] = useState<
     Record<AIProvider, (() => Promise<void>) | null>
   >({
     claude: null,
     codex: null,
+    gemini: null,
+    opencode: null,
   });
   const [runningReviewIdByProvider, setRunningReviewIdByProvider] = useState<
     Record<AIProvider, string | null>
   >({
     claude: null,
     codex: null,
+    gemini: null,
+    opencode: null,
   });
   const [actionLoading, setActionLoading] = useState<string | null>(null);
   const [showAddRepo, setShowAddRepo] = useState(false);
@@ -455,17 +492,13 @@ function HomeComponent() {
       // Check if provider is available
       const providerStatus = await checkProviderStatus(provider);
       if (!providerStatus.installed) {
-        const providerName = provider === "claude" ? "Claude Code" : "Codex";
-        toast.error(`${providerName} CLI not installed`, {
+        const { name, installUrl } = PROVIDER_CLIS[provider];
+        toast.error(`${name} not installed`, {
           description: `Please install the ${provider} CLI to use this provider.`,
           action: {
             label: "Learn more",
             onClick: () => {
-              const url =
-                provider === "claude"
-                  ? "https://docs.anthropic.com/en/docs/claude-code"
-                  : "https://github.com/openai/codex";
-              window.open(url, "_blank");
+              window.open(installUrl, "_blank");
             },
           },
         });
@@ -473,8 +506,7 @@ function HomeComponent() {
       }
 
       if (!providerStatus.authenticated) {
-        const providerName = provider === "claude" ? "Claude Code" : "Codex";
-        toast.error(`${providerName} not authenticated`, {
+        toast.error(`${PROVIDER_CLIS[provider].name} not authenticated`, {
           description: providerStatus.error ?? `Please authenticate the ${provider} CLI.`,
         });
         return;
@@ -490,26 +522,86 @@ function HomeComponent() {
       addReview(pendingReview);
       updateReview(pendingReview.id, { status: "running" });
       setRunningReviewIdByProvider((prev) => ({ ...prev, [provider]: pendingReview.id }));
+      // The commit being reviewed, so a later review can cover only what was pushed since
+      const headSha = selectedPR.headSha;
+      const prInfo = { number: selectedPR.number, repository: selectedPR.repository.fullName };
+      // The repository's own policy adds to the personal prompt; without one, review as before
+      const policy = await getReviewPolicy(prInfo, systemPrompt).catch((error) => {
+        console.warn("[AI Review] Failed to load review policy:", error);
+        return null;
+      });
+      if (policy?.source) {
+        toast.info("Using the repository's review policy", { description: policy.source });
+      }
+      if (policy && policy.warnings.length > 0) {
+        console.warn("[AI Review] Review policy warnings:", policy.warnings);
+      }
+      // Secrets are found without the model, so they are reported even if it misses them
+      const secretScan = scanPRSecrets(prInfo)
+        .then((comments) => {
+          if (comments.length > 0) {
+            toast.warning(`${comments.length} possible secret(s) in this PR`, {
+              description: "They were redacted before the diff was sent to the AI provider.",
+            });
+          }
+          return comments;
+        })
+        .catch((error) => {
+          console.warn("[AI Review] Secret scan failed:", error);
+          return [];
+        });
+      const ruleRun = runReviewRules(prInfo)
+        .then(({ comments, warnings }) => {
+          if (warnings.length > 0) {
+            console.warn("[AI Review] Review rule warnings:", warnings);
+          }
+          return comments;
+        })
+        .catch((error) => {
+          console.warn("[AI Review] Review rules failed:", error);
+          return [];
+        });
 
       const abort = await startStreamingAIReview(
-        { number: selectedPR.number, repository: selectedPR.repository.fullName },
-        { provider, model, systemPrompt },
+        prInfo,
+        {
+          provider,
+          model,
+          systemPrompt: policy?.instructions ?? systemPrompt,
+          contextIgnore: policy?.ignore,
+        },
         {
           onThinkingStart: () => {},
           onThinkingDelta: () => {},
           onTextDelta: () => {},
           onBlockStop: () => {},
-          onComplete: (fullOutput: string) => {
-            const parsedReview = parseAIReviewResponse(
+          onComplete: async (fullOutput: string) => {
+            const modelReview = await parseAIReviewResponse(
               fullOutput,
               selectedPR.number,
               selectedPR.repository.fullName,
               provider,
             );
+            const parsedReview = {
+              ...modelReview,
+              comments: [
+                ...(await secretScan),
+                ...(await ruleRun),
+                ...modelReview.comments.filter((comment) =>
+                  meetsSeverityThreshold(comment.severity, policy?.severityThreshold ?? null),
+                ),
+              ],
+            };
             updateReview(pendingReview.id, {
               ...parsedReview,
+              headSha,
               status: "completed",
             });
+            if (headSha) {
+              recordAIReview({ ...parsedReview, id: pendingReview.id }, headSha, model).catch(
+                (error) => console.warn("[AI Review] Failed to record review:", error),
+              );
+            }
             setRunningByProvider((prev) => ({ ...prev, [provider]: false }));
             setAbortReviewByProvider((prev) => ({ ...prev, [provider]: null }));
             setRunningReviewIdByProvider((prev) => ({ ...prev, [provider]: null }));
@@ -791,21 +883,43 @@ function HomeComponent() {
         return false;
       }
 
-      // Format the comment body with severity badge and suggestion
-      let body = `**[${comment.severity.toUpperCase()}]** ${comment.body}`;
-      if (comment.suggestion) {
-        body += `\n\n**Suggestion:**\n\`\`\`\n${comment.suggestion}\n\`\`\``;
+      // GitHub rejects comments on lines outside the diff, so move the comment onto the
+      // diff or, failing that, post it as a general PR comment
+      const anchored = await anchorAIComments(diffFiles, [comment]).catch((error) => {
+        console.warn("[AI Review] Failed to check comment against the diff:", error);
+        return null;
+      });
+      if (anchored?.generalBody) {
+        const result = await addPullRequestComment(
+          selectedPR.repository.fullName,
+          selectedPR.number,
+          anchored.generalBody + AI_COMMENT_FOOTER,
+        );
+        if (!result.success) {
+          toast.error("Failed to post comment", { description: result.error });
+          return false;
+        }
+        toast.success("Comment posted as a general PR comment", {
+          description: anchored.unanchored[0]?.reason,
+        });
+        await refreshReviewComments(selectedPR);
+        return true;
       }
-      body += `\n\n---\n*🤖 AI Review Comment*`;
+      const target = anchored?.inline[0] ?? comment;
+      if (anchored?.inline[0]?.moved) {
+        toast.info(`Moved comment from line ${anchored.inline[0].originalLine} to ${target.line}`);
+      }
+
+      const body = formatAICommentBody(comment);
 
       const result = await addReviewComment(
         selectedPR.repository.fullName,
         selectedPR.number,
         body,
-        comment.path,
-        comment.line,
+        target.path,
+        target.line,
         selectedPR.headSha,
-        comment.side,
+        target.side,
         pendingReview?.nodeId,
       );
 
@@ -832,7 +946,95 @@ function HomeComponent() {
       toast.error("Failed to post comment", { description: errorDesc });
       return false;
     },
-    [selectedPR, pendingReview, refreshReviewComments, fetchPRDetails],
+    [selectedPR, pendingReview, diffFiles, refreshReviewComments, fetchPRDetails],
+  );
+
+  const handlePostAllAIComments = useCallback(
+    async (comments: AIReviewComment[], suggestions: AIReviewSuggestion[]): Promise<boolean> => {
+      if (!selectedPR || !selectedPR.headSha) {
+        toast.error("Cannot post comments", {
+          description: "No PR selected or missing commit information.",
+        });
+        return false;
+      }
+      if (pendingReview) {
+        toast.error("Cannot post comments", {
+          description: "You already have a pending review. Submit or discard it first.",
+        });
+        return false;
+      }
+
+      // Suggestions are anchored like comments on their last line
+      const suggestionById = new Map(suggestions.map((s) => [s.id, s]));
+      const suggestionComments: AIReviewComment[] = suggestions.map((s) => ({
+        id: s.id,
+        path: s.path,
+        line: s.endLine,
+        side: "RIGHT",
+        severity: "suggestion",
+        category: s.category,
+        body: s.explanation,
+        suggestion: s.suggestedCode,
+      }));
+
+      let anchored: AIAnchorResult;
+      try {
+        anchored = await anchorAIComments(diffFiles, [...comments, ...suggestionComments]);
+      } catch (error) {
+        toast.error("Failed to check comments against the diff", {
+          description: error instanceof Error ? error.message : String(error),
+        });
+        return false;
+      }
+
+      const batch: BatchReviewComment[] = anchored.inline.map((comment) => {
+        const suggestion = suggestionById.get(comment.id);
+        // A suggested change replaces exactly its lines, so it only applies where it wasn't moved
+        if (
+          suggestion &&
+          !comment.moved &&
+          isInOneHunk(diffFiles, comment.path, suggestion.startLine, suggestion.endLine)
+        ) {
+          return {
+            path: comment.path,
+            line: suggestion.endLine,
+            side: "RIGHT",
+            startLine: suggestion.startLine,
+            body: formatAICommentBody(comment, false),
+            suggestion: suggestion.suggestedCode,
+          };
+        }
+        return {
+          path: comment.path,
+          line: comment.line,
+          side: comment.side,
+          body: formatAICommentBody(comment),
+        };
+      });
+
+      const result = await postReviewBatch(
+        selectedPR.repository.fullName,
+        selectedPR.number,
+        selectedPR.headSha,
+        batch,
+        { body: anchored.generalBody ? anchored.generalBody + AI_COMMENT_FOOTER : undefined },
+      );
+      if (!result.success) {
+        toast.error("Failed to post comments", { description: result.error });
+        return false;
+      }
+
+      toast.success(`Added ${batch.length} comments to a pending review`, {
+        description:
+          anchored.unanchored.length > 0
+            ? `${anchored.unanchored.length} comments outside the diff went into the review body.`
+            : "Submit the review to publish them.",
+      });
+      await fetchPRDetails(selectedPR);
+      await refreshReviewComments(selectedPR);
+      return true;
+    },
+    [selectedPR, pendingReview, diffFiles, refreshReviewComments, fetchPRDetails],
   );
 
   const totalPRs = Array.from(pullRequests.values()).reduce((sum, prs) => sum + prs.length, 0);
@@ -1236,6 +1438,7 @@ function HomeComponent() {
                     setScrollToLine(line);
                   }}
                   onPostComment={handlePostAIComment}
+                  onPostAllComments={handlePostAllAIComments}
                 />
               </div>
             </div>
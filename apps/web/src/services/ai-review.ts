@@ -1,4 +1,13 @@
-import type { AIProvider, AIReviewConfig, AIReviewResult, ReviewStatus } from "@/types";
+import type {
+  AIPreviousFinding,
+  FileDiff,
+  AIProvider,
+  AIReviewComment,
+  AIReviewConfig,
+  AIReviewResult,
+  AIReviewSuggestion,
+  ReviewStatus,
+} from "@/types";
 
 import { logError } from "@/stores/error-log-store";
 
@@ -9,18 +18,123 @@ interface StreamCallbacks {
   onBlockStop: () => void;
   onComplete: (fullOutput: string) => void;
   onError: (error: string) => void;
+  /** The run was queued, got a slot, or changed priority */
+  onJob?: (job: AIJob) => void;
+  /** The run was held back by the budget, or released once it fit */
+  onBudget?: (decision: AIBudgetDecision) => void;
 }
 
-interface AIStreamEvent {
-  process_id: string;
-  event_type: "stdout" | "stderr" | "complete" | "error" | "cancelled";
-  data: string;
+export interface AITokenUsage {
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_input_tokens: number;
+  cache_read_input_tokens: number;
+  cost_usd: number | null;
 }
 
-interface AIContentEvent {
+/** Events sent by the backend over a run's channel */
+export type AIEvent =
+  | { type: "thinking_start" }
+  | { type: "thinking_delta"; text: string }
+  | { type: "text_delta"; text: string }
+  | { type: "block_stop" }
+  | { type: "usage"; usage: AITokenUsage }
+  | { type: "stderr"; line: string }
+  | { type: "complete"; message: string }
+  | { type: "error"; message: string }
+  | { type: "cancelled"; message: string }
+  | { type: "timeout"; reason: string }
+  | { type: "job"; info: AIJob }
+  | { type: "budget"; decision: AIBudgetDecision };
+
+export interface AIEventEnvelope {
+  v: number;
   process_id: string;
-  event_type: "thinking_start" | "thinking_delta" | "text_delta" | "block_stop";
-  text: string;
+  seq: number;
+  event: AIEvent;
+}
+
+/** Must match `AI_EVENT_VERSION` in the backend */
+const AI_EVENT_VERSION = 2;
+
+interface StreamState {
+  fullOutput: string;
+  stderrOutput: string;
+  isCleanedUp: boolean;
+}
+
+function createEventHandler(
+  state: StreamState,
+  callbacks: StreamCallbacks,
+  cleanup: () => void,
+  onFailure?: (errorMsg: string) => void,
+): (message: AIEventEnvelope) => void {
+  return (message) => {
+    if (state.isCleanedUp) return;
+    if (message.v !== AI_EVENT_VERSION) {
+      console.warn("[AI Review] Unexpected event version:", message.v);
+    }
+
+    const event = message.event;
+    switch (event.type) {
+      case "thinking_start":
+        console.log("[AI Review] Thinking started");
+        callbacks.onThinkingStart();
+        break;
+      case "thinking_delta":
+        state.fullOutput += event.text;
+        callbacks.onThinkingDelta(event.text);
+        break;
+      case "text_delta":
+        state.fullOutput += event.text;
+        callbacks.onTextDelta(event.text);
+        break;
+      case "block_stop":
+        console.log("[AI Review] Block stopped. Total output so far:", state.fullOutput.length);
+        callbacks.onBlockStop();
+        break;
+      case "usage":
+        console.log("[AI Review] Usage:", event.usage);
+        break;
+      case "stderr":
+        console.warn("[AI Review] stderr:", event.line);
+        state.stderrOutput += event.line + "\n";
+        break;
+      case "complete": {
+        console.log("[AI Review] Process complete. Output length:", state.fullOutput.length);
+        const output = state.fullOutput;
+        cleanup();
+        callbacks.onComplete(output);
+        break;
+      }
+      case "error":
+      case "timeout": {
+        const reason =
+          event.type === "error" ? event.message : `Review timed out: ${event.reason}`;
+        const errorMsg = state.stderrOutput.trim()
+          ? `${reason}\n\nStderr:\n${state.stderrOutput.trim()}`
+          : state.fullOutput.trim()
+            ? `${reason}\n\nOutput:\n${state.fullOutput.trim()}`
+            : reason;
+        onFailure?.(errorMsg);
+        cleanup();
+        callbacks.onError(errorMsg);
+        break;
+      }
+      case "cancelled":
+        console.log("[AI Review] Process cancelled");
+        cleanup();
+        callbacks.onError("Review cancelled");
+        break;
+      case "job":
+        callbacks.onJob?.(event.info);
+        break;
+      case "budget":
+        console.log(`[AI Review] Budget ${event.decision.action}:`, event.decision.reason);
+        callbacks.onBudget?.(event.decision);
+        break;
+    }
+  };
 }
 
 interface PRInfo {
@@ -34,54 +148,10 @@ function getProviderCommand(provider: AIProvider): string {
       return "claude";
     case "codex":
       return "codex";
-  }
-}
-
-interface ProviderConfig {
-  args: string[];
-  useStdin: boolean;
-}
-
-function isCommandNotFound(errorMessage: string): boolean {
-  const message = errorMessage.toLowerCase();
-  return (
-    message.includes("os error 2") ||
-    message.includes("no such file or directory") ||
-    message.includes("cannot find the file") ||
-    message.includes("not recognized") ||
-    message.includes("failed to execute")
-  );
-}
-
-function getProviderConfig(
-  provider: AIProvider,
-  model: string | undefined,
-  reasoningEffort: string | undefined,
-  prompt: string,
-): ProviderConfig {
-  switch (provider) {
-    case "claude": {
-      // Use json output format - simpler and more reliable than stream-json
-      // The CLI will output the final result as JSON when complete
-      const args = ["-p", prompt, "--allowedTools", "Bash(gh:*)", "--output-format", "json"];
-      if (model) {
-        args.unshift("--model", model);
-      }
-      return { args, useStdin: false };
-    }
-    case "codex": {
-      // Use exec subcommand for non-interactive mode with JSON output
-      // --sandbox danger-full-access allows network access for gh CLI
-      const args = ["exec", "--json", "--skip-git-repo-check", "--sandbox", "danger-full-access"];
-      if (model) {
-        args.push("--model", model);
-      }
-      if (reasoningEffort) {
-        args.push("-c", `model_reasoning_effort="${reasoningEffort}"`);
-      }
-      args.push(prompt);
-      return { args, useStdin: false };
-    }
+    case "gemini":
+      return "gemini";
+    case "opencode":
+      return "opencode";
   }
 }
 
@@ -90,18 +160,11 @@ export async function startStreamingAIReview(
   config: AIReviewConfig,
   callbacks: StreamCallbacks,
 ): Promise<() => Promise<void>> {
-  const { invoke } = await import("@tauri-apps/api/core");
-  const { listen } = await import("@tauri-apps/api/event");
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
 
   const command = getProviderCommand(config.provider);
-  const prompt = buildReviewPrompt(prInfo, config.systemPrompt);
-  const providerConfig = getProviderConfig(
-    config.provider,
-    config.model,
-    config.reasoningEffort,
-    prompt,
-  );
   const processId = crypto.randomUUID();
+  const logSource = `ai-${config.provider}` as const;
 
   console.log(
     "[AI Review] Starting review with provider:",
@@ -114,119 +177,51 @@ export async function startStreamingAIReview(
   console.log("[AI Review] Command:", command);
   console.log("[AI Review] PR:", prInfo.repository, "#", prInfo.number);
 
-  // Use a state object to track cleanup and prevent memory leaks
-  const state = {
+  const state: StreamState = {
     fullOutput: "",
     stderrOutput: "",
-    processId,
-    unlistenStream: null as (() => void) | null,
-    unlistenContent: null as (() => void) | null,
     isCleanedUp: false,
   };
 
+  // Events for this run arrive only on its own channel, so no filtering by process id
+  const channel = new Channel<AIEventEnvelope>();
+
   const cleanup = () => {
     if (state.isCleanedUp) return;
     state.isCleanedUp = true;
-
-    // Remove event listeners
-    state.unlistenStream?.();
-    state.unlistenContent?.();
-
-    // Nullify references to help GC
-    state.unlistenStream = null;
-    state.unlistenContent = null;
+    channel.onmessage = () => {};
     state.fullOutput = "";
     state.stderrOutput = "";
   };
 
-  try {
-    // Set up event listeners first
-    state.unlistenStream = await listen<AIStreamEvent>("ai-stream", (event) => {
-      // Skip if already cleaned up or different process
-      if (state.isCleanedUp) return;
-      if (event.payload.process_id !== state.processId) return;
-
-      console.log(
-        "[AI Review] Stream event:",
-        event.payload.event_type,
-        event.payload.data?.slice(0, 200),
-      );
-
-      switch (event.payload.event_type) {
-        case "stdout":
-          console.log("[AI Review] stdout:", event.payload.data);
-          break;
-        case "stderr":
-          console.warn("[AI Review] stderr:", event.payload.data);
-          state.stderrOutput += event.payload.data + "\n";
-          break;
-        case "complete": {
-          console.log("[AI Review] Process complete. Output length:", state.fullOutput.length);
-          const output = state.fullOutput;
-          cleanup();
-          callbacks.onComplete(output);
-          break;
-        }
-        case "error": {
-          const errorMsg = state.stderrOutput.trim()
-            ? `${event.payload.data}\n\nStderr:\n${state.stderrOutput.trim()}`
-            : state.fullOutput.trim()
-              ? `${event.payload.data}\n\nOutput:\n${state.fullOutput.trim()}`
-              : event.payload.data;
-          logError(config.provider === "claude" ? "ai-claude" : "ai-codex", command, errorMsg, {
-            stderr: state.stderrOutput,
-          });
-          cleanup();
-          callbacks.onError(errorMsg);
-          break;
-        }
-        case "cancelled":
-          console.log("[AI Review] Process cancelled");
-          cleanup();
-          callbacks.onError("Review cancelled");
-          break;
-      }
-    });
-
-    state.unlistenContent = await listen<AIContentEvent>("ai-content", (event) => {
-      // Skip if already cleaned up or different process
-      if (state.isCleanedUp) return;
-      if (event.payload.process_id !== state.processId) return;
-
-      console.log(
-        "[AI Review] Content event:",
-        event.payload.event_type,
-        "text length:",
-        event.payload.text?.length ?? 0,
-      );
-
-      switch (event.payload.event_type) {
-        case "thinking_start":
-          console.log("[AI Review] Thinking started");
-          callbacks.onThinkingStart();
-          break;
-        case "thinking_delta":
-          state.fullOutput += event.payload.text;
-          callbacks.onThinkingDelta(event.payload.text);
-          break;
-        case "text_delta":
-          state.fullOutput += event.payload.text;
-          callbacks.onTextDelta(event.payload.text);
-          break;
-        case "block_stop":
-          console.log("[AI Review] Block stopped. Total output so far:", state.fullOutput.length);
-          callbacks.onBlockStop();
-          break;
-      }
-    });
+  channel.onmessage = createEventHandler(state, callbacks, cleanup, (errorMsg) => {
+    logError(logSource, command, errorMsg, { stderr: state.stderrOutput });
+  });
 
-    // Start the process
+  try {
     console.log("[AI Review] Invoking start_ai_stream...");
+    // The backend provider adapter builds the CLI arguments and parses its output.
+    // With `context`, the backend fetches the PR into the prompt after these instructions.
     const returnedId = await invoke<string>("start_ai_stream", {
-      command,
-      args: providerConfig.args,
-      stdinInput: providerConfig.useStdin ? prompt : null,
+      provider: config.provider,
+      request: {
+        prompt: config.systemPrompt,
+        model: config.model ?? null,
+        reasoningEffort: config.reasoningEffort ?? null,
+        repository: prInfo.repository,
+        prNumber: prInfo.number,
+      },
       processId,
+      priority: config.priority ?? null,
+      timeouts: {
+        totalSecs: config.timeoutSecs ?? null,
+        idleSecs: config.idleTimeoutSecs ?? null,
+      },
+      context: {
+        tokenBudget: config.contextTokenBudget ?? null,
+        ignore: config.contextIgnore ?? [],
+      },
+      onEvent: channel,
     });
     if (returnedId !== processId) {
       console.warn("[AI Review] Process ID mismatch:", returnedId, processId);
@@ -234,7 +229,7 @@ export async function startStreamingAIReview(
     console.log("[AI Review] Process started with ID:", returnedId);
   } catch (error) {
     const errorMsg = error instanceof Error ? error.message : String(error);
-    logError(config.provider === "claude" ? "ai-claude" : "ai-codex", command, errorMsg);
+    logError(logSource, command, errorMsg);
     cleanup();
     callbacks.onError(errorMsg);
     return async () => {};
@@ -244,17 +239,683 @@ export async function startStreamingAIReview(
   return async () => {
     if (state.isCleanedUp) return;
 
-    if (state.processId) {
-      try {
-        await invoke("cancel_ai_stream", { processId: state.processId });
-      } catch {
-        // Ignore errors when cancelling (process might have already completed)
-      }
+    try {
+      await invoke("cancel_ai_stream", { processId });
+    } catch {
+      // Ignore errors when cancelling (process might have already completed)
     }
     cleanup();
   };
 }
 
+export interface EnsembleMemberConfig {
+  provider: AIProvider;
+  model?: string;
+  reasoningEffort?: string;
+}
+
+export interface EnsembleOptions {
+  /** Member runs get process ids `${ensembleId}-${index}` */
+  ensembleId?: string;
+  priority?: number;
+  timeoutSecs?: number;
+  idleTimeoutSecs?: number;
+  /** Events from every member, told apart by `process_id` */
+  onMemberEvent?: (envelope: AIEventEnvelope) => void;
+}
+
+interface EnsembleReview {
+  summary: string;
+  overallScore: number | null;
+  comments: (Omit<AIReviewComment, "id" | "suggestion"> & { suggestion: string | null })[];
+  suggestions: Omit<AIReviewSuggestion, "id">[];
+  members: { label: string }[];
+  warnings: string[];
+}
+
+/**
+ * Review a PR with several providers or models in parallel and merge their findings into
+ * one result. Comments several members raised on the same line are marked as consensus.
+ */
+export async function runEnsembleReview(
+  prInfo: PRInfo,
+  members: EnsembleMemberConfig[],
+  systemPrompt: string,
+  options: EnsembleOptions = {},
+): Promise<AIReviewResult> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+
+  const channel = new Channel<AIEventEnvelope>();
+  channel.onmessage = (envelope) => options.onMemberEvent?.(envelope);
+
+  const review = await invoke<EnsembleReview>("run_ensemble_review", {
+    members: members.map((member) => ({
+      provider: member.provider,
+      model: member.model ?? null,
+      reasoningEffort: member.reasoningEffort ?? null,
+    })),
+    request: {
+      prompt: buildReviewPrompt(prInfo, systemPrompt),
+      repository: prInfo.repository,
+      prNumber: prInfo.number,
+    },
+    ensembleId: options.ensembleId ?? null,
+    priority: options.priority ?? null,
+    timeouts: {
+      totalSecs: options.timeoutSecs ?? null,
+      idleSecs: options.idleTimeoutSecs ?? null,
+    },
+    onEvent: channel,
+  });
+
+  const id = crypto.randomUUID();
+  const now = new Date().toISOString();
+  if (review.warnings.length > 0) {
+    console.warn("[AI Review] Ensemble warnings:", review.warnings);
+  }
+  return {
+    id,
+    prNumber: prInfo.number,
+    repository: prInfo.repository,
+    provider: members[0].provider,
+    status: "completed",
+    summary: review.summary,
+    overallScore: review.overallScore ?? undefined,
+    comments: review.comments.map((c, i) => ({
+      ...c,
+      id: `${id}-comment-${i}`,
+      suggestion: c.suggestion ?? undefined,
+    })),
+    suggestions: review.suggestions.map((s, i) => ({
+      ...s,
+      id: `${id}-suggestion-${i}`,
+    })),
+    ensembleMembers: review.members.map((member) => member.label),
+    parseWarnings: review.warnings.length > 0 ? review.warnings : undefined,
+    createdAt: now,
+    completedAt: now,
+  };
+}
+
+/**
+ * Cancel every member of an ensemble review started with `options.ensembleId`
+ */
+export async function cancelEnsembleReview(ensembleId: string, memberCount: number): Promise<void> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  await Promise.all(
+    Array.from({ length: memberCount }, (_, index) =>
+      invoke("cancel_ai_stream", { processId: `${ensembleId}-${index}` }).catch(() => {
+        // Members that already finished can't be cancelled
+      }),
+    ),
+  );
+}
+
+export type MapReduceProgress =
+  | { type: "planned"; chunks: { index: number; files: string[]; estimated_tokens: number }[] }
+  | { type: "chunk_started"; index: number; process_id: string }
+  | {
+      type: "chunk_finished";
+      index: number;
+      outcome: "completed" | "failed" | "cancelled" | "timeout" | null;
+      comment_count: number;
+      error: string | null;
+    }
+  | { type: "summarizing"; process_id: string };
+
+export interface MapReduceOptions {
+  /** Token budget per chunk of the diff */
+  chunkTokens?: number;
+  /** Chunks queued at once */
+  maxParallel?: number;
+  /** Chunk runs get process ids `${reviewId}-chunk-${index}`, the summary `${reviewId}-summary` */
+  reviewId?: string;
+  priority?: number;
+  timeoutSecs?: number;
+  idleTimeoutSecs?: number;
+  onProgress?: (progress: MapReduceProgress) => void;
+}
+
+interface MapReduceReview {
+  summary: string;
+  overallScore: number | null;
+  comments: (Omit<AIReviewComment, "id" | "suggestion"> & { suggestion: string | null })[];
+  suggestions: Omit<AIReviewSuggestion, "id">[];
+  warnings: string[];
+}
+
+/**
+ * Review a PR whose diff is too large for one prompt. The backend fetches the diff,
+ * reviews it in chunks and merges the results with a final summary pass.
+ */
+export async function runMapReduceReview(
+  prInfo: PRInfo,
+  config: AIReviewConfig,
+  options: MapReduceOptions = {},
+): Promise<AIReviewResult> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+
+  const channel = new Channel<MapReduceProgress>();
+  channel.onmessage = (progress) => options.onProgress?.(progress);
+
+  const review = await invoke<MapReduceReview>("run_map_reduce_review", {
+    provider: config.provider,
+    request: {
+      prompt: "",
+      model: config.model ?? null,
+      reasoningEffort: config.reasoningEffort ?? null,
+      repository: prInfo.repository,
+    },
+    prNumber: prInfo.number,
+    systemPrompt: config.systemPrompt,
+    options: {
+      chunkTokens: options.chunkTokens ?? null,
+      maxParallel: options.maxParallel ?? null,
+      reviewId: options.reviewId ?? null,
+      priority: options.priority ?? config.priority ?? null,
+      timeouts: {
+        totalSecs: options.timeoutSecs ?? config.timeoutSecs ?? null,
+        idleSecs: options.idleTimeoutSecs ?? config.idleTimeoutSecs ?? null,
+      },
+    },
+    onProgress: channel,
+  });
+
+  const id = crypto.randomUUID();
+  const now = new Date().toISOString();
+  if (review.warnings.length > 0) {
+    console.warn("[AI Review] Map-reduce warnings:", review.warnings);
+  }
+  return {
+    id,
+    prNumber: prInfo.number,
+    repository: prInfo.repository,
+    provider: config.provider,
+    status: "completed",
+    summary: review.summary,
+    overallScore: review.overallScore ?? undefined,
+    comments: review.comments.map((c, i) => ({
+      ...c,
+      id: `${id}-comment-${i}`,
+      suggestion: c.suggestion ?? undefined,
+    })),
+    suggestions: review.suggestions.map((s, i) => ({
+      ...s,
+      id: `${id}-suggestion-${i}`,
+    })),
+    parseWarnings: review.warnings.length > 0 ? review.warnings : undefined,
+    createdAt: now,
+    completedAt: now,
+  };
+}
+
+export interface AIReviewRecord {
+  reviewId: string;
+  repository: string;
+  prNumber: number;
+  headSha: string;
+  provider: string;
+  model: string | null;
+  createdAt: number;
+  summary: string | null;
+  overallScore: number | null;
+  comments: (Omit<AIReviewComment, "id" | "suggestion"> & { suggestion: string | null })[];
+  suggestions: Omit<AIReviewSuggestion, "id">[];
+}
+
+/**
+ * Remember the commit a finished review was run against, so the next review of the PR
+ * can be incremental
+ */
+export async function recordAIReview(
+  review: AIReviewResult,
+  headSha: string,
+  model?: string,
+): Promise<void> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  await invoke("record_ai_review", {
+    record: {
+      reviewId: review.id,
+      repository: review.repository,
+      prNumber: review.prNumber,
+      headSha,
+      provider: review.provider,
+      model: model ?? null,
+      summary: review.summary,
+      overallScore: review.overallScore ?? null,
+      comments: review.comments.map(({ id: _, ...c }) => ({
+        ...c,
+        suggestion: c.suggestion ?? null,
+      })),
+      suggestions: review.suggestions.map(({ id: _, ...s }) => s),
+    },
+  });
+}
+
+/**
+ * The most recent recorded review of a PR, if any
+ */
+export async function getLastAIReview(
+  repository: string,
+  prNumber: number,
+): Promise<AIReviewRecord | null> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIReviewRecord | null>("get_last_ai_review", { repository, prNumber });
+}
+
+export interface IncrementalReviewOptions {
+  /** Process id of the run, for cancelling it */
+  reviewId?: string;
+  onEvent?: (envelope: AIEventEnvelope) => void;
+}
+
+interface IncrementalReview {
+  baseSha: string;
+  headSha: string;
+  summary: string;
+  overallScore: number | null;
+  comments: (Omit<AIReviewComment, "id" | "suggestion"> & { suggestion: string | null })[];
+  suggestions: Omit<AIReviewSuggestion, "id">[];
+  previousFindings: (Omit<AIPreviousFinding, "id" | "suggestion" | "note"> & {
+    suggestion: string | null;
+    note: string | null;
+  })[];
+  warnings: string[];
+}
+
+/**
+ * Review only the commits pushed since the last recorded review of a PR. The earlier
+ * findings come back in `previousFindings`, marked as resolved or still present.
+ */
+export async function runIncrementalReview(
+  prInfo: PRInfo,
+  config: AIReviewConfig,
+  options: IncrementalReviewOptions = {},
+): Promise<AIReviewResult> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+
+  const channel = new Channel<AIEventEnvelope>();
+  channel.onmessage = (envelope) => options.onEvent?.(envelope);
+
+  const reviewId = options.reviewId ?? crypto.randomUUID();
+  const review = await invoke<IncrementalReview>("run_incremental_review", {
+    provider: config.provider,
+    request: {
+      prompt: "",
+      model: config.model ?? null,
+      reasoningEffort: config.reasoningEffort ?? null,
+      repository: prInfo.repository,
+    },
+    prNumber: prInfo.number,
+    systemPrompt: config.systemPrompt,
+    options: {
+      reviewId,
+      priority: config.priority ?? null,
+      timeouts: {
+        totalSecs: config.timeoutSecs ?? null,
+        idleSecs: config.idleTimeoutSecs ?? null,
+      },
+    },
+    onEvent: channel,
+  });
+
+  const now = new Date().toISOString();
+  if (review.warnings.length > 0) {
+    console.warn("[AI Review] Incremental review warnings:", review.warnings);
+  }
+  return {
+    id: reviewId,
+    prNumber: prInfo.number,
+    repository: prInfo.repository,
+    provider: config.provider,
+    status: "completed",
+    summary: review.summary,
+    overallScore: review.overallScore ?? undefined,
+    comments: review.comments.map((c, i) => ({
+      ...c,
+      id: `${reviewId}-comment-${i}`,
+      suggestion: c.suggestion ?? undefined,
+    })),
+    suggestions: review.suggestions.map((s, i) => ({
+      ...s,
+      id: `${reviewId}-suggestion-${i}`,
+    })),
+    previousFindings: review.previousFindings.map((f, i) => ({
+      ...f,
+      id: `${reviewId}-previous-${i}`,
+      suggestion: f.suggestion ?? undefined,
+      note: f.note ?? undefined,
+    })),
+    headSha: review.headSha,
+    baseSha: review.baseSha,
+    parseWarnings: review.warnings.length > 0 ? review.warnings : undefined,
+    createdAt: now,
+    completedAt: now,
+  };
+}
+
+export interface AIStreamInfo {
+  process_id: string;
+  provider: string;
+  started_at: number;
+  finished: boolean;
+  last_seq: number;
+}
+
+/**
+ * List AI runs the backend is still buffering, e.g. to resume them after a webview reload
+ */
+export async function listAIStreams(): Promise<AIStreamInfo[]> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIStreamInfo[]>("list_ai_streams");
+}
+
+/**
+ * Re-subscribe to a running (or recently finished) AI run: buffered events are replayed
+ * through the callbacks first, then live events continue.
+ */
+export async function attachAIStream(
+  processId: string,
+  callbacks: StreamCallbacks,
+): Promise<() => Promise<void>> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+
+  const state: StreamState = {
+    fullOutput: "",
+    stderrOutput: "",
+    isCleanedUp: false,
+  };
+  const channel = new Channel<AIEventEnvelope>();
+
+  const cleanup = () => {
+    if (state.isCleanedUp) return;
+    state.isCleanedUp = true;
+    channel.onmessage = () => {};
+    state.fullOutput = "";
+    state.stderrOutput = "";
+  };
+
+  channel.onmessage = createEventHandler(state, callbacks, cleanup);
+
+  try {
+    const result = await invoke<{ info: AIStreamInfo; truncated: boolean }>("attach_ai_stream", {
+      processId,
+      onEvent: channel,
+    });
+    if (result.truncated) {
+      console.warn("[AI Review] Stream buffer was truncated; early output is missing");
+    }
+  } catch (error) {
+    cleanup();
+    callbacks.onError(error instanceof Error ? error.message : String(error));
+    return async () => {};
+  }
+
+  return async () => {
+    if (state.isCleanedUp) return;
+    try {
+      await invoke("cancel_ai_stream", { processId });
+    } catch {
+      // Ignore errors when cancelling (process might have already completed)
+    }
+    cleanup();
+  };
+}
+
+export interface AITranscriptInfo {
+  id: string;
+  provider: string;
+  command: string;
+  model: string | null;
+  started_at: number;
+  duration_ms: number | null;
+  exit_code: number | null;
+  outcome: "completed" | "failed" | "cancelled" | "timeout" | null;
+  size_bytes: number;
+}
+
+/**
+ * List transcripts of past AI runs, newest first
+ */
+export async function listAITranscripts(): Promise<AITranscriptInfo[]> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AITranscriptInfo[]>("list_ai_transcripts");
+}
+
+export interface AIReviewContextSection {
+  kind: "metadata" | "diff" | "linked_issue" | "file_content";
+  /** File path or issue reference */
+  label: string;
+  estimatedTokens: number;
+  /** Whether it fit the token budget */
+  included: boolean;
+}
+
+export interface AIReviewContext {
+  prompt: string;
+  headSha: string;
+  estimatedTokens: number;
+  tokenBudget: number;
+  sections: AIReviewContextSection[];
+  /** Secrets the diff adds; they are redacted from the prompt */
+  secretFindings: AISecretFinding[];
+  warnings: string[];
+}
+
+/** A secret found in a PR's diff by the backend scanner, shaped as a critical comment */
+export interface AISecretFinding extends Omit<AIReviewComment, "id" | "suggestion"> {
+  suggestion: string | null;
+  ruleId: string;
+  /** Start of the secret with the rest masked */
+  preview: string;
+}
+
+/**
+ * Assemble the prompt a review would be started with: the instructions followed by the PR's
+ * metadata, diff, linked issues and changed files, trimmed to the token budget
+ */
+export async function buildReviewContext(
+  prInfo: PRInfo,
+  instructions: string,
+  tokenBudget?: number,
+): Promise<AIReviewContext> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIReviewContext>("build_review_context", {
+    repository: prInfo.repository,
+    prNumber: prInfo.number,
+    instructions,
+    options: { tokenBudget: tokenBudget ?? null },
+  });
+}
+
+/**
+ * Secrets the PR's diff adds, found without any model, as critical comments. The backend
+ * redacts them from every prompt before it is sent to a provider.
+ */
+export async function scanPRSecrets(prInfo: PRInfo): Promise<AIReviewComment[]> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  const findings = await invoke<AISecretFinding[]>("scan_pr_secrets", {
+    repository: prInfo.repository,
+    prNumber: prInfo.number,
+  });
+  return findings.map((finding, i) => ({
+    id: `secret-${prInfo.number}-${finding.ruleId}-${i}`,
+    path: finding.path,
+    line: finding.line,
+    side: finding.side,
+    severity: finding.severity,
+    category: finding.category,
+    body: finding.body,
+  }));
+}
+
+export type ReviewRuleCheck =
+  | { type: "pattern"; pattern: string }
+  | { type: "forbidden_api"; apis: string[] }
+  | { type: "missing_pattern"; pattern: string }
+  | { type: "missing_file"; companion: string };
+
+/** A declarative rule the backend evaluates against a PR's diff without any model */
+export type ReviewRule = ReviewRuleCheck & {
+  id: string;
+  /** Comment body; `{match}` is replaced with the matched text */
+  message: string;
+  severity?: AIReviewComment["severity"];
+  category?: AIReviewComment["category"];
+  /** File globs the rule applies to; all files if empty */
+  paths?: string[];
+  excludePaths?: string[];
+  /** "owner/name" globs; all repositories if empty */
+  repositories?: string[];
+  enabled?: boolean;
+};
+
+export interface ReviewRulesConfig {
+  rules: ReviewRule[];
+}
+
+export async function getReviewRules(): Promise<ReviewRulesConfig> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<ReviewRulesConfig>("get_review_rules");
+}
+
+/**
+ * Replace the local review rules; fails if a rule's regex or glob is invalid
+ */
+export async function setReviewRules(config: ReviewRulesConfig): Promise<void> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  await invoke("set_review_rules", { config });
+}
+
+/**
+ * Evaluate the local review rules against the PR's diff. Findings are comments in the same
+ * shape as AI output, so they are merged with it and posted the same way.
+ */
+export async function runReviewRules(
+  prInfo: PRInfo,
+): Promise<{ comments: AIReviewComment[]; warnings: string[] }> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  const report = await invoke<{
+    findings: (Omit<AIReviewComment, "id" | "suggestion"> & {
+      suggestion: string | null;
+      ruleId: string;
+    })[];
+    warnings: string[];
+  }>("run_review_rules", { repository: prInfo.repository, prNumber: prInfo.number });
+  return {
+    comments: report.findings.map((finding, i) => ({
+      id: `rule-${prInfo.number}-${finding.ruleId}-${i}`,
+      path: finding.path,
+      line: finding.line,
+      side: finding.side,
+      severity: finding.severity,
+      category: finding.category,
+      body: finding.body,
+    })),
+    warnings: report.warnings,
+  };
+}
+
+/** Review configuration after the repository's `.github/lyon.yml` is applied */
+export interface AIReviewPolicy {
+  /** System prompt with the policy's focus areas and instructions appended */
+  instructions: string;
+  focusAreas: string[];
+  /** File globs left out of the review */
+  ignore: string[];
+  /** Findings below this severity are dropped */
+  severityThreshold: AIReviewComment["severity"] | null;
+  rules: ReviewRule[];
+  /** Where the policy was read from; null if the repository has none */
+  source: string | null;
+  warnings: string[];
+}
+
+/**
+ * Apply the review policy on the PR's base branch to the personal system prompt
+ */
+export async function getReviewPolicy(
+  prInfo: PRInfo,
+  instructions: string,
+): Promise<AIReviewPolicy> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIReviewPolicy>("get_review_policy", {
+    repository: prInfo.repository,
+    prNumber: prInfo.number,
+    instructions,
+  });
+}
+
+const SEVERITY_ORDER: AIReviewComment["severity"][] = ["info", "suggestion", "warning", "critical"];
+
+/**
+ * Whether a comment of `severity` is kept under a policy's severity threshold
+ */
+export function meetsSeverityThreshold(
+  severity: AIReviewComment["severity"],
+  threshold: AIReviewComment["severity"] | null,
+): boolean {
+  if (!threshold) return true;
+  return SEVERITY_ORDER.indexOf(severity) >= SEVERITY_ORDER.indexOf(threshold);
+}
+
+/** A `gh` command an AI agent ran that the backend's read-only proxy refused */
+export interface BlockedGhCommand {
+  time: number;
+  process_id: string | null;
+  repository: string | null;
+  pr_number: number | null;
+  args: string[];
+  reason: string;
+}
+
+/**
+ * List gh commands refused by the read-only proxy, oldest first, optionally for one run
+ */
+export async function listBlockedGhCommands(processId?: string): Promise<BlockedGhCommand[]> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<BlockedGhCommand[]>("list_blocked_gh_commands", { processId: processId ?? null });
+}
+
+/**
+ * Replay a transcript through the same callbacks as a live review.
+ * `speed` scales the original timing (2 = twice as fast, 0 = instantly).
+ */
+export async function replayAITranscript(
+  transcriptId: string,
+  callbacks: StreamCallbacks,
+  speed = 1,
+): Promise<() => void> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+
+  const state: StreamState = {
+    fullOutput: "",
+    stderrOutput: "",
+    isCleanedUp: false,
+  };
+  const channel = new Channel<AIEventEnvelope>();
+
+  const cleanup = () => {
+    if (state.isCleanedUp) return;
+    state.isCleanedUp = true;
+    channel.onmessage = () => {};
+    state.fullOutput = "";
+    state.stderrOutput = "";
+  };
+
+  channel.onmessage = createEventHandler(state, callbacks, cleanup);
+
+  try {
+    await invoke("replay_ai_transcript", { transcriptId, speed, onEvent: channel });
+  } catch (error) {
+    cleanup();
+    callbacks.onError(error instanceof Error ? error.message : String(error));
+  }
+
+  // Stops delivering events to the callbacks
+  return cleanup;
+}
+
 function buildReviewPrompt(prInfo: PRInfo, systemPrompt: string): string {
   return `${systemPrompt}
 
@@ -285,93 +946,52 @@ After reviewing the changes, respond with ONLY a valid JSON object (no markdown,
 If there are no issues, use an empty comments array. Start your response with { and end with }`;
 }
 
-function extractJsonFromResponse(response: string): string | null {
-  const codeBlockMatch = response.match(/```(?:json)?\s*([\s\S]*?)```/);
-  if (codeBlockMatch?.[1]) {
-    const jsonInBlock = codeBlockMatch[1].trim();
-    if (jsonInBlock.startsWith("{")) {
-      return jsonInBlock;
-    }
-  }
-
-  const jsonObjectMatch = response.match(/(\{[\s\S]*"summary"[\s\S]*\})/);
-  if (jsonObjectMatch?.[1]) {
-    let depth = 0;
-    let start = response.indexOf(jsonObjectMatch[1]);
-    let end = start;
-
-    for (let i = start; i < response.length; i++) {
-      if (response[i] === "{") depth++;
-      if (response[i] === "}") {
-        depth--;
-        if (depth === 0) {
-          end = i + 1;
-          break;
-        }
-      }
-    }
-
-    if (depth === 0) {
-      return response.slice(start, end);
-    }
-  }
-
-  const simpleMatch = response.match(/\{[\s\S]*\}/);
-  return simpleMatch?.[0] ?? null;
+interface ParsedReview {
+  foundJson: boolean;
+  summary: string | null;
+  overallScore: number | null;
+  comments: (Omit<AIReviewComment, "id" | "suggestion"> & { suggestion: string | null })[];
+  suggestions: Omit<AIReviewSuggestion, "id">[];
+  warnings: string[];
 }
 
-export function parseAIReviewResponse(
+export async function parseAIReviewResponse(
   response: string,
   prNumber: number,
   repository: string,
   provider: AIProvider,
-): AIReviewResult {
+): Promise<AIReviewResult> {
   const id = crypto.randomUUID();
   const now = new Date().toISOString();
 
   try {
-    const jsonString = extractJsonFromResponse(response);
+    const { invoke } = await import("@tauri-apps/api/core");
+    const parsed = await invoke<ParsedReview>("parse_ai_review", { output: response });
 
-    if (!jsonString) {
+    if (!parsed.foundJson) {
       console.warn("No JSON found in AI response. Raw response:", response.slice(0, 500));
-      return {
-        id,
-        prNumber,
-        repository,
-        provider,
-        status: "completed",
-        summary: response.length > 0 ? response : "No response received from AI",
-        comments: [],
-        suggestions: [],
-        createdAt: now,
-        completedAt: now,
-      };
+    } else if (parsed.warnings.length > 0) {
+      console.warn("Repaired AI review response:", parsed.warnings);
     }
 
-    const parsed = JSON.parse(jsonString) as {
-      summary?: string;
-      overallScore?: number;
-      comments?: AIReviewResult["comments"];
-      suggestions?: AIReviewResult["suggestions"];
-    };
-
     return {
       id,
       prNumber,
       repository,
       provider,
       status: "completed",
-      summary: parsed.summary ?? null,
-      overallScore: parsed.overallScore,
-      comments: (parsed.comments ?? []).map((c, i) => ({
+      summary: parsed.summary,
+      overallScore: parsed.overallScore ?? undefined,
+      comments: parsed.comments.map((c, i) => ({
         ...c,
         id: `${id}-comment-${i}`,
-        side: "RIGHT" as const,
+        suggestion: c.suggestion ?? undefined,
       })),
-      suggestions: (parsed.suggestions ?? []).map((s, i) => ({
+      suggestions: parsed.suggestions.map((s, i) => ({
         ...s,
         id: `${id}-suggestion-${i}`,
       })),
+      parseWarnings: parsed.warnings.length > 0 ? parsed.warnings : undefined,
       createdAt: now,
       completedAt: now,
     };
@@ -415,9 +1035,51 @@ export function createPendingReview(
   };
 }
 
+export interface AIAnchoredComment extends AIReviewComment {
+  /** Line the model gave, before moving it onto the diff */
+  originalLine: number;
+  moved: boolean;
+}
+
+export interface AIUnanchoredComment extends AIReviewComment {
+  reason: string;
+}
+
+export interface AIAnchorResult {
+  inline: AIAnchoredComment[];
+  unanchored: AIUnanchoredComment[];
+  /** Markdown for a general PR comment carrying the unanchored comments */
+  generalBody: string | null;
+}
+
+/**
+ * Check AI comments against the PR diff before posting. Comments that narrowly miss a diff
+ * line are moved onto it; the rest can't be posted inline and are gathered in `generalBody`.
+ */
+export async function anchorAIComments(
+  files: FileDiff[],
+  comments: AIReviewComment[],
+): Promise<AIAnchorResult> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  const result = await invoke<{
+    inline: (Omit<AIAnchoredComment, "suggestion"> & { suggestion: string | null })[];
+    unanchored: (Omit<AIUnanchoredComment, "suggestion"> & { suggestion: string | null })[];
+    generalBody: string | null;
+  }>("anchor_review_comments", {
+    files,
+    comments: comments.map((c) => ({ ...c, suggestion: c.suggestion ?? null })),
+  });
+  return {
+    inline: result.inline.map((c) => ({ ...c, suggestion: c.suggestion ?? undefined })),
+    unanchored: result.unanchored.map((c) => ({ ...c, suggestion: c.suggestion ?? undefined })),
+    generalBody: result.generalBody,
+  };
+}
+
 export interface AIProviderStatus {
   installed: boolean;
   authenticated: boolean;
+  version?: string;
   error?: string;
 }
 
@@ -427,40 +1089,19 @@ export interface AIProviderStatus {
 export async function checkProviderStatus(provider: AIProvider): Promise<AIProviderStatus> {
   try {
     const { invoke } = await import("@tauri-apps/api/core");
-    const command = getProviderCommand(provider);
-
-    // Check if installed (cross-platform) by running a simple command
-    try {
-      await invoke<string>("run_shell_command", {
-        command,
-        args: ["--version"],
-      });
-    } catch (error) {
-      const errorMsg = error instanceof Error ? error.message : String(error);
-      if (isCommandNotFound(errorMsg)) {
-        return {
-          installed: false,
-          authenticated: false,
-          error: `${command} CLI is not installed or not available on PATH`,
-        };
-      }
-      if (
-        errorMsg.includes("not logged in") ||
-        errorMsg.includes("auth") ||
-        errorMsg.includes("API key")
-      ) {
-        return {
-          installed: true,
-          authenticated: false,
-          error: `${command} CLI is not authenticated. Please run "${command} auth" to authenticate.`,
-        };
-      }
-      // If version check fails, it might still work
-      return { installed: true, authenticated: true };
-    }
-
-    // Claude/Codex don't have reliable auth status checks
-    return { installed: true, authenticated: true };
+    // The backend runs the provider's version check and classifies any failure
+    const status = await invoke<{
+      installed: boolean;
+      authenticated: boolean;
+      version: string | null;
+      error: string | null;
+    }>("check_ai_provider", { provider });
+    return {
+      installed: status.installed,
+      authenticated: status.authenticated,
+      version: status.version ?? undefined,
+      error: status.error ?? undefined,
+    };
   } catch (error) {
     return {
       installed: false,
@@ -474,3 +1115,99 @@ export async function checkProviderAvailability(provider: AIProvider): Promise<b
   const status = await checkProviderStatus(provider);
   return status.installed && status.authenticated;
 }
+
+export interface AIUsageTotals {
+  runs: number;
+  /** Runs whose provider reported no usage at all */
+  unreported_runs: number;
+  /** Runs that reported tokens but no cost, so cost_usd undercounts */
+  uncosted_runs: number;
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_input_tokens: number;
+  cache_read_input_tokens: number;
+  cost_usd: number;
+}
+
+export interface AIUsageBucket extends AIUsageTotals {
+  key: string;
+}
+
+export interface AIUsageSummary {
+  total: AIUsageTotals;
+  by_provider: AIUsageBucket[];
+  by_model: AIUsageBucket[];
+  by_repository: AIUsageBucket[];
+  /** UTC days as YYYY-MM-DD, oldest first */
+  by_day: AIUsageBucket[];
+}
+
+/** Usage of finished runs started within [since, until), as epoch milliseconds */
+export async function getAIUsageSummary(since?: number, until?: number): Promise<AIUsageSummary> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIUsageSummary>("get_ai_usage_summary", {
+    since: since ?? null,
+    until: until ?? null,
+  });
+}
+
+export interface AIJob {
+  id: string;
+  provider: string;
+  priority: number;
+  state: "queued" | "running" | "done";
+  /** Why a queued job is held back, e.g. an exhausted budget */
+  blocked_by: string | null;
+  outcome: "completed" | "failed" | "cancelled" | "timeout" | null;
+  queued_at: number;
+  started_at: number | null;
+  finished_at: number | null;
+}
+
+/**
+ * List queued, running and recently finished AI review jobs
+ */
+export async function listAIJobs(): Promise<AIJob[]> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIJob[]>("list_ai_jobs");
+}
+
+export async function reprioritizeAIJob(jobId: string, priority: number): Promise<AIJob> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIJob>("reprioritize_ai_job", { jobId, priority });
+}
+
+export async function setAIJobConcurrency(maxConcurrency: number): Promise<void> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  await invoke("set_ai_job_concurrency", { maxConcurrency });
+}
+
+export interface AIBudgetLimit {
+  /** Input, output and cache-write tokens per UTC day */
+  maxTokens?: number | null;
+  maxCostUsd?: number | null;
+}
+
+export interface AIBudgetConfig {
+  daily: AIBudgetLimit;
+  /** Keyed by "owner/name" */
+  repositories: Record<string, AIBudgetLimit>;
+  /** Refuse over-budget reviews, or queue them until the budget allows them */
+  whenExceeded: "refuse" | "queue";
+}
+
+/** Sent on a run's channel when the budget holds it back or releases it */
+export interface AIBudgetDecision {
+  action: "deferred" | "resumed";
+  reason: string;
+}
+
+export async function getAIBudgets(): Promise<AIBudgetConfig> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<AIBudgetConfig>("get_ai_budgets");
+}
+
+export async function setAIBudgets(config: AIBudgetConfig): Promise<void> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  await invoke("set_ai_budgets", { config });
+}
@@ -573,12 +573,15 @@ export async function getPullRequestReviewComments(
   return runGhCommand<Comment[]>(["api", `repos/${repo}/pulls/${prNumber}/comments`, "--jq", "."]);
 }
 
+/**
+ * Post a general PR comment. Resolves to the comment's URL, which is all `gh pr comment` prints.
+ */
 export async function addPullRequestComment(
   repo: string,
   prNumber: number,
   body: string,
-): Promise<CommandResult<Comment>> {
-  return runGhCommand<Comment>(["pr", "comment", String(prNumber), "--repo", repo, "--body", body]);
+): Promise<CommandResult<string>> {
+  return runGhCommandRaw(["pr", "comment", String(prNumber), "--repo", repo, "--body", body]);
 }
 
 async function runGhCommandWithInput<T>(args: string[], input: string): Promise<CommandResult<T>> {
@@ -683,6 +686,65 @@ export async function addReviewComment(
   );
 }
 
+export interface BatchReviewComment {
+  path: string;
+  /** Last line of the range */
+  line: number;
+  side: "LEFT" | "RIGHT";
+  /** First line of a multi-line range */
+  startLine?: number;
+  startSide?: "LEFT" | "RIGHT";
+  body: string;
+  /** Replacement for the commented lines, posted as a suggested change */
+  suggestion?: string;
+}
+
+export interface BatchReviewResult {
+  reviewId: number;
+  nodeId: string;
+  state: string;
+  htmlUrl: string | null;
+  commentCount: number;
+}
+
+/**
+ * Post comments as one pending review created in a single API call. If the review can't be
+ * verified or submitted it is deleted again, so either every comment is posted or none is.
+ */
+export async function postReviewBatch(
+  repo: string,
+  prNumber: number,
+  commitId: string,
+  comments: BatchReviewComment[],
+  options: { body?: string; event?: "COMMENT" | "APPROVE" | "REQUEST_CHANGES" } = {},
+): Promise<CommandResult<BatchReviewResult>> {
+  try {
+    const { invoke } = await import("@tauri-apps/api/core");
+    const result = await invoke<BatchReviewResult>("post_review_batch", {
+      request: {
+        repository: repo,
+        prNumber,
+        commitId,
+        body: options.body ?? null,
+        comments: comments.map((c) => ({
+          ...c,
+          startLine: c.startLine ?? null,
+          startSide: c.startSide ?? null,
+          suggestion: c.suggestion ?? null,
+        })),
+        event: options.event ?? null,
+      },
+    });
+    return { success: true, data: result };
+  } catch (error) {
+    const errorMsg = error instanceof Error ? error.message : String(error);
+    logError("gh", "gh api reviews", errorMsg, {
+      context: { repo, prNumber, comments: comments.length },
+    });
+    return { success: false, error: errorMsg };
+  }
+}
+
 export async function replyToReviewComment(
   repo: string,
   prNumber: number,
@@ -6,6 +6,7 @@ import type {
   ReviewFocusArea,
 } from "@/types";
 import {
+  AI_PROVIDERS,
   CODEX_REASONING_EFFORTS,
   DEFAULT_MODELS,
   DEFAULT_REASONING_EFFORT,
@@ -51,11 +52,15 @@ const DEFAULT_CONFIG: AIReviewConfig = {
 const DEFAULT_ACTIVE_REVIEWS: Record<AIProvider, AIReviewResult | null> = {
   claude: null,
   codex: null,
+  gemini: null,
+  opencode: null,
 };
 
 const DEFAULT_MODEL_BY_PROVIDER: Record<AIProvider, string> = {
   claude: DEFAULT_MODELS.claude,
   codex: DEFAULT_MODELS.codex,
+  gemini: DEFAULT_MODELS.gemini,
+  opencode: DEFAULT_MODELS.opencode,
 };
 
 export const useReviewStore = create<ReviewState>()(
@@ -188,15 +193,19 @@ export const useReviewStore = create<ReviewState>()(
         const merged = { ...current, ...persistedState };
 
         // Validate and fix model selections
-        const validClaudeModels = MODELS_BY_PROVIDER.claude.map((m) => m.id);
-        const validCodexModels = MODELS_BY_PROVIDER.codex.map((m) => m.id);
+        const validModels = (provider: AIProvider) =>
+          MODELS_BY_PROVIDER[provider].map((m) => m.id);
         const validReasoningEfforts = CODEX_REASONING_EFFORTS.map((e) => e.id);
 
         // Fix config.model if invalid
         if (merged.config) {
-          const validModels =
-            merged.config.provider === "claude" ? validClaudeModels : validCodexModels;
-          if (!merged.config.model || !validModels.includes(merged.config.model)) {
+          if (!AI_PROVIDERS.includes(merged.config.provider)) {
+            merged.config.provider = "claude";
+          }
+          if (
+            !merged.config.model ||
+            !validModels(merged.config.provider).includes(merged.config.model)
+          ) {
             merged.config.model = DEFAULT_MODELS[merged.config.provider];
           }
           // Fix reasoning effort if invalid
@@ -210,18 +219,15 @@ export const useReviewStore = create<ReviewState>()(
 
         // Fix modelByProvider if invalid
         if (merged.modelByProvider) {
-          if (
-            !merged.modelByProvider.claude ||
-            !validClaudeModels.includes(merged.modelByProvider.claude)
-          ) {
-            merged.modelByProvider.claude = DEFAULT_MODELS.claude;
-          }
-          if (
-            !merged.modelByProvider.codex ||
-            !validCodexModels.includes(merged.modelByProvider.codex)
-          ) {
-            merged.modelByProvider.codex = DEFAULT_MODELS.codex;
+          // Stores persisted before a provider existed have no entry for it
+          const modelByProvider = { ...DEFAULT_MODEL_BY_PROVIDER, ...merged.modelByProvider };
+          for (const provider of AI_PROVIDERS) {
+            const model = modelByProvider[provider];
+            if (!model || !validModels(provider).includes(model)) {
+              modelByProvider[provider] = DEFAULT_MODELS[provider];
+            }
           }
+          merged.modelByProvider = modelByProvider;
         }
 
         return merged;
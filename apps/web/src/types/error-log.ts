@@ -1,4 +1,10 @@
-export type ErrorLogSource = "gh" | "ai-claude" | "ai-codex" | "system";
+export type ErrorLogSource =
+  | "gh"
+  | "ai-claude"
+  | "ai-codex"
+  | "ai-gemini"
+  | "ai-opencode"
+  | "system";
 
 export interface ErrorLog {
   id: string;
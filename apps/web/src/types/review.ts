@@ -1,4 +1,14 @@
-export type AIProvider = "claude" | "codex";
+export type AIProvider = "claude" | "codex" | "gemini" | "opencode";
+
+export const AI_PROVIDERS: AIProvider[] = ["claude", "codex", "gemini", "opencode"];
+
+/** Display name of each provider's CLI and where to install it */
+export const PROVIDER_CLIS: Record<AIProvider, { name: string; installUrl: string }> = {
+  claude: { name: "Claude Code", installUrl: "https://docs.anthropic.com/en/docs/claude-code" },
+  codex: { name: "Codex", installUrl: "https://github.com/openai/codex" },
+  gemini: { name: "Gemini CLI", installUrl: "https://github.com/google-gemini/gemini-cli" },
+  opencode: { name: "opencode", installUrl: "https://opencode.ai" },
+};
 
 export interface AIModelConfig {
   id: string;
@@ -20,6 +30,22 @@ export const CODEX_MODELS: AIModelConfig[] = [
   { id: "gpt-5.1-codex-mini", name: "GPT-5.1 Codex Mini", description: "Smaller, cost-effective" },
 ];
 
+// Gemini CLI models
+export const GEMINI_MODELS: AIModelConfig[] = [
+  { id: "gemini-2.5-pro", name: "Gemini 2.5 Pro", description: "Strongest reasoning and coding" },
+  { id: "gemini-2.5-flash", name: "Gemini 2.5 Flash", description: "Fast and cost-effective" },
+];
+
+// opencode models are "provider/model"
+export const OPENCODE_MODELS: AIModelConfig[] = [
+  {
+    id: "anthropic/claude-sonnet-4-5",
+    name: "Claude Sonnet 4.5",
+    description: "Through opencode's Anthropic provider",
+  },
+  { id: "openai/gpt-5", name: "GPT-5", description: "Through opencode's OpenAI provider" },
+];
+
 // Codex reasoning effort levels
 export type CodexReasoningEffort = "low" | "medium" | "high" | "xhigh";
 
@@ -39,11 +65,15 @@ export const CODEX_REASONING_EFFORTS: ReasoningEffortConfig[] = [
 export const MODELS_BY_PROVIDER: Record<AIProvider, AIModelConfig[]> = {
   claude: CLAUDE_MODELS,
   codex: CODEX_MODELS,
+  gemini: GEMINI_MODELS,
+  opencode: OPENCODE_MODELS,
 };
 
 export const DEFAULT_MODELS: Record<AIProvider, string> = {
   claude: "sonnet",
   codex: "gpt-5.3-codex",
+  gemini: "gemini-2.5-pro",
+  opencode: "anthropic/claude-sonnet-4-5",
 };
 
 export const DEFAULT_REASONING_EFFORT: CodexReasoningEffort = "high";
@@ -55,6 +85,16 @@ export interface AIReviewConfig {
   systemPrompt: string;
   temperature?: number;
   maxTokens?: number;
+  /** Queue priority; higher values start first when reviews are waiting for a slot */
+  priority?: number;
+  /** Wall-clock limit for a run in seconds (0 disables it) */
+  timeoutSecs?: number;
+  /** Kill the run after this many seconds without output (0 disables it) */
+  idleTimeoutSecs?: number;
+  /** Token budget for the PR context the backend puts in the prompt */
+  contextTokenBudget?: number;
+  /** File globs left out of the PR context, e.g. from the repository's review policy */
+  contextIgnore?: string[];
 }
 
 export interface AIReviewRequest {
@@ -86,6 +126,16 @@ export interface AIReviewResult {
   comments: AIReviewComment[];
   suggestions: AIReviewSuggestion[];
   overallScore?: number;
+  /** Repairs made while extracting the result from the model output */
+  parseWarnings?: string[];
+  /** Labels of the providers/models whose findings were merged, for ensemble reviews */
+  ensembleMembers?: string[];
+  /** Commit the review was run against */
+  headSha?: string;
+  /** For incremental reviews, the commit of the review it continues from */
+  baseSha?: string;
+  /** For incremental reviews, the earlier review's findings and whether they were addressed */
+  previousFindings?: AIPreviousFinding[];
   createdAt: string;
   completedAt: string | null;
   error?: string;
@@ -102,6 +152,15 @@ export interface AIReviewComment {
   category: ReviewFocusArea;
   body: string;
   suggestion?: string;
+  /** Ensemble members that raised this comment */
+  sources?: string[];
+  /** Raised by more than one ensemble member */
+  consensus?: boolean;
+}
+
+export interface AIPreviousFinding extends AIReviewComment {
+  status: "resolved" | "still_present" | "unverified";
+  note?: string;
 }
 
 export type CommentSeverity = "critical" | "warning" | "info" | "suggestion";
@@ -115,6 +174,8 @@ export interface AIReviewSuggestion {
   suggestedCode: string;
   explanation: string;
   category: ReviewFocusArea;
+  sources?: string[];
+  consensus?: boolean;
 }
 
 export const DEFAULT_SYSTEM_PROMPTS: Record<string, string> = {